@@ -0,0 +1,173 @@
+use crate::constants;
+use crate::dbi;
+use crate::types::*;
+use log::{error, warn};
+use poise::serenity_prelude as serenity;
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::fs;
+
+/// Directory with the files of the tags of a server. Global tags have their own directory
+fn store_dir(guildid: Option<serenity::GuildId>) -> PathBuf {
+    let name = match guildid {
+        Some(id) => id.get().to_string(),
+        None => constants::DB_DEFAULT_DB.to_string(),
+    };
+    PathBuf::from(constants::TAG_ATTACHMENT_DIR).join(name)
+}
+
+/// Files are named after the hash of their content, so the same file is only stored once per
+/// server
+fn content_hash(data: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(data))
+}
+
+/// Stored files are only ever named by their hash. Anything else in the directory is left alone
+fn is_hash(name: &str) -> bool {
+    name.len() == 64 && name.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+/// Temporary files of `write_file` that were left behind by a crash or a failed rename
+fn is_temporary(name: &str) -> bool {
+    name.strip_suffix(".tmp").is_some_and(is_hash)
+}
+
+/// Writes the file to the store unless a file with the same content is already there
+async fn write_file(dir: &PathBuf, hash: &str, data: &[u8]) -> Result<(), Error> {
+    let path = dir.join(hash);
+    if fs::try_exists(&path).await? {
+        return Ok(());
+    }
+
+    // Write to a temporary file first, so a crash never leaves a truncated file behind
+    fs::create_dir_all(dir).await?;
+    let tmp_path = dir.join(format!("{}.tmp", hash));
+    fs::write(&tmp_path, data).await?;
+    fs::rename(&tmp_path, &path).await?;
+
+    Ok(())
+}
+
+/// Downloads the attachments and stores them for a tag that already has the `existing` files.
+/// Returns the references to put into the tag. Fails without storing anything if the limits on
+/// the count and size of the files would be exceeded
+pub async fn store_attachments(
+    attachments: &[serenity::Attachment],
+    existing: &[TagAttachment],
+    guildid: Option<serenity::GuildId>,
+) -> Result<Vec<TagAttachment>, Error> {
+    if existing.len() + attachments.len() > constants::TAG_ATTACHMENT_COUNT_LIMIT {
+        return Err(Box::new(TagError::TooManyAttachments));
+    }
+    let total_size: u64 = existing
+        .iter()
+        .map(|a| a.size as u64)
+        .chain(attachments.iter().map(|a| a.size as u64))
+        .sum();
+    if total_size > constants::TAG_ATTACHMENT_SIZE_LIMIT as u64 {
+        return Err(Box::new(TagError::AttachmentsTooLarge));
+    }
+
+    let dir = store_dir(guildid);
+    let mut stored: Vec<TagAttachment> = Vec::with_capacity(attachments.len());
+    for attachment in attachments.iter() {
+        let data = attachment.download().await?;
+        let hash = content_hash(&data);
+        write_file(&dir, &hash, &data).await?;
+        stored.push(TagAttachment {
+            filename: attachment.filename.to_owned(),
+            hash,
+            size: data.len() as u32,
+        });
+    }
+
+    Ok(stored)
+}
+
+/// Loads the files of a tag for posting. Files that went missing are skipped and logged
+pub async fn load_attachments(
+    tag: &Tag,
+    guildid: Option<serenity::GuildId>,
+) -> Vec<serenity::CreateAttachment> {
+    let dir = store_dir(guildid);
+    let mut files: Vec<serenity::CreateAttachment> = Vec::with_capacity(tag.attachments.len());
+    for attachment in tag.attachments.iter() {
+        match fs::read(dir.join(&attachment.hash)).await {
+            Ok(data) => files.push(serenity::CreateAttachment::bytes(
+                data,
+                attachment.filename.to_owned(),
+            )),
+            Err(e) => error!(
+                "attachment_store::load_attachments: couldn't load file {} of tag {}: {}",
+                attachment.hash, tag.name, e
+            ),
+        }
+    }
+
+    files
+}
+
+/// Removes all stored files of the server that no tag refers to anymore. Meant to be called after
+/// tags were removed or changed, so errors are only logged
+pub async fn remove_orphans(guildid: Option<serenity::GuildId>) {
+    if let Err(e) = try_remove_orphans(guildid).await {
+        error!(
+            "In {}, attachment_store::remove_orphans: {}",
+            store_dir(guildid).display(),
+            e
+        );
+    }
+}
+
+async fn try_remove_orphans(guildid: Option<serenity::GuildId>) -> Result<usize, Error> {
+    let dir = store_dir(guildid);
+    if !fs::try_exists(&dir).await? {
+        return Ok(0);
+    }
+
+    // Tags in the trash keep their files, so they can be restored
+    let trashed = dbi::get_trashed_tags(guildid)
+        .await?
+        .into_iter()
+        .map(|t| t.tag);
+    let referenced: HashSet<String> = dbi::get_all_tags(guildid)
+        .await?
+        .into_iter()
+        .chain(trashed)
+        .flat_map(|t| t.attachments.into_iter().map(|a| a.hash))
+        .collect();
+    let grace = Duration::from_secs(constants::TAG_ATTACHMENT_GRACE_SECS);
+
+    let mut removed = 0;
+    let mut entries = fs::read_dir(&dir).await?;
+    while let Some(entry) = entries.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().to_string();
+        // Temporary files are never referenced, they only get removed once they're old enough
+        // that no write can still be in progress
+        if !(is_hash(&name) || is_temporary(&name)) || referenced.contains(&name) {
+            continue;
+        }
+        let age = entry
+            .metadata()
+            .await?
+            .modified()?
+            .elapsed()
+            .unwrap_or_default();
+        if age < grace {
+            continue;
+        }
+        fs::remove_file(entry.path()).await?;
+        removed += 1;
+    }
+    if removed > 0 {
+        warn!(
+            "In {}, attachment_store::remove_orphans: removed {} unused files",
+            dir.display(),
+            removed
+        );
+    }
+
+    Ok(removed)
+}
@@ -3,6 +3,8 @@ use poise::serenity_prelude::Colour;
 use poise::serenity_prelude::CreateEmbed;
 use poise::Command;
 
+use crate::constants;
+use crate::dbi;
 use crate::embed_tools::*;
 use crate::types::*;
 
@@ -23,13 +25,13 @@ pub async fn register(ctx: Context<'_>) -> Result<(), Error> {
     Ok(())
 }
 
-async fn autocomplete_commands<'a>(ctx: Context<'_>, partial: &'a str) -> Vec<String> {
+async fn autocomplete_commands(ctx: Context<'_>, partial: &str) -> Vec<String> {
     let commands = &ctx.framework().options().commands;
 
     let mut all_commands: Vec<String> = Vec::new();
 
     for command in commands.iter() {
-        if command.subcommands.len() == 0 && !command.hide_in_help {
+        if command.subcommands.is_empty() && !command.hide_in_help {
             all_commands.push(command.qualified_name.to_owned());
         } else {
             for subcom in command.subcommands.iter() {
@@ -107,7 +109,7 @@ pub async fn help(
     let mut commands: Vec<&Command<Data, Error>> = Vec::with_capacity(base_commands.len());
     for base_command in base_commands {
         if !base_command.hide_in_help {
-            commands.push(&base_command);
+            commands.push(base_command);
         }
     }
 
@@ -116,7 +118,7 @@ pub async fn help(
     let mut all_commands: Vec<&Command<Data, Error>> = Vec::with_capacity(20);
 
     for command in commands.iter() {
-        if command.subcommands.len() == 0 && !command.hide_in_help {
+        if command.subcommands.is_empty() && !command.hide_in_help {
             all_commands.push(*command);
         } else {
             for subcom in command.subcommands.iter() {
@@ -129,10 +131,7 @@ pub async fn help(
 
     match command {
         Some(c) => {
-            if let Some(bot_command) = all_commands
-                .into_iter()
-                .filter(|com| com.qualified_name == c)
-                .next()
+            if let Some(bot_command) = all_commands.into_iter().find(|com| com.qualified_name == c)
             {
                 let embed = serenity::CreateEmbed::new()
                     .title(format!(
@@ -216,3 +215,130 @@ pub async fn help(
 
     Ok(())
 }
+
+/// Set which mentions the bot may ping
+///
+/// Tags and role messages are written by members, so the bot only pings what this policy allows.
+/// By default only users are pinged. Leave all options empty to show the current policy.
+#[poise::command(
+    slash_command,
+    required_permissions = "ADMINISTRATOR",
+    category = "Admins",
+    rename = "mentions",
+    guild_only
+)]
+pub async fn mention_policy(
+    ctx: Context<'_>,
+    #[description = "Allow pinging users"] users: Option<bool>,
+    #[description = "Allow pinging roles"] roles: Option<bool>,
+    #[description = "Allow pinging @everyone and @here"] everyone: Option<bool>,
+) -> Result<(), Error> {
+    let mut policy = dbi::get_mention_policy(ctx.guild_id()).await?;
+    if users.is_some() || roles.is_some() || everyone.is_some() {
+        policy = MentionPolicy {
+            users: users.unwrap_or(policy.users),
+            roles: roles.unwrap_or(policy.roles),
+            everyone: everyone.unwrap_or(policy.everyone),
+        };
+        policy = dbi::set_mention_policy(policy, ctx.guild_id()).await?;
+    }
+
+    let allowed = |b: bool| if b { "allowed" } else { "not allowed" };
+    ctx.say(format!(
+        "Mentions of users are {}, mentions of roles are {} and @everyone/@here is {}.",
+        allowed(policy.users),
+        allowed(policy.roles),
+        allowed(policy.everyone)
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// Parses an emote given as a command argument, either an emote of the server or a Unicode emoji.
+/// Returns None if the argument is neither
+pub async fn emote_argument(ctx: Context<'_>, input: &str) -> Result<Option<ReactionEmote>, Error> {
+    let guild_emojis = match ctx.guild_id() {
+        Some(gid) => gid.emojis(ctx).await?,
+        None => vec![],
+    };
+
+    Ok(ReactionEmote::parse(input, &guild_emojis))
+}
+
+/// Set the moderation log channel
+///
+/// The bot reports changes it makes on its own to this channel, e.g. when it removes a role from a
+/// role panel because the role or its emote was deleted. Leave the channel empty to disable it.
+#[poise::command(
+    slash_command,
+    required_permissions = "ADMINISTRATOR",
+    category = "Admins",
+    rename = "modlog",
+    guild_only
+)]
+pub async fn mod_log(
+    ctx: Context<'_>,
+    #[description = "Channel for the moderation log, empty to disable it"]
+    #[channel_types("Text")]
+    channel: Option<serenity::GuildChannel>,
+) -> Result<(), Error> {
+    let config = channel.as_ref().map(|c| ModLogConfig {
+        channel: c.id,
+        set_by: ctx.author().to_owned(),
+    });
+    dbi::set_mod_log_config(config, ctx.guild_id()).await?;
+
+    match channel {
+        Some(c) => {
+            ctx.say(format!("The moderation log will be posted to {}.", c))
+                .await?
+        }
+        None => ctx.say("Moderation log disabled.").await?,
+    };
+
+    Ok(())
+}
+
+/// Posts lines to the moderation log of a server, split into as many messages as needed. Does
+/// nothing if the server has no moderation log
+pub async fn post_to_mod_log(
+    ctx: &serenity::Context,
+    guildid: serenity::GuildId,
+    lines: &[String],
+) -> Result<(), Error> {
+    let config = match dbi::get_mod_log_config(Some(guildid)).await? {
+        Some(c) => c,
+        None => return Ok(()),
+    };
+
+    for content in split_into_messages(lines) {
+        config
+            .channel
+            .send_message(
+                ctx,
+                serenity::CreateMessage::new()
+                    .content(content)
+                    .allowed_mentions(serenity::CreateAllowedMentions::new()),
+            )
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Joins lines into as few messages as possible without going over the message character limit
+pub fn split_into_messages(lines: &[String]) -> Vec<String> {
+    let mut messages: Vec<String> = vec![];
+    for line in lines {
+        match messages.last_mut() {
+            Some(m) if m.chars().count() + line.chars().count() < constants::MESSAGE_CHAR_LIMIT => {
+                m.push('\n');
+                m.push_str(line);
+            }
+            _ => messages.push(line.chars().take(constants::MESSAGE_CHAR_LIMIT).collect()),
+        }
+    }
+
+    messages
+}
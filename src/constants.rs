@@ -4,8 +4,60 @@ pub static DB_TAGS: &str = "tag";
 pub static DB_USERS: &str = "user";
 pub static DB_ROLES: &str = "role";
 pub static DB_ROLEMSG: &str = "rolemessage";
+pub static DB_ROLEGROUPS: &str = "rolegroup";
+pub static DB_ROLEEXPIRY: &str = "roleexpiry";
+pub static DB_JOINROLES: &str = "joinroles";
+pub static DB_ROLESNAPSHOTS: &str = "rolesnapshot";
 pub static DB_POINTEMOTE: &str = "pointemote";
+pub static DB_TAGTRIGGER: &str = "tagtrigger";
+pub static DB_TAGSUGGESTIONS: &str = "tagsuggestion";
+pub static DB_TAGREVIEW: &str = "tagreview";
+pub static DB_MENTIONPOLICY: &str = "mentionpolicy";
+pub static DB_MODLOG: &str = "modlog";
+pub static DB_USERLANGUAGE: &str = "userlanguage";
+pub static DB_TAGTRASH: &str = "tagtrash";
+pub static DB_TAGTRASHCONFIG: &str = "tagtrashconfig";
+pub static DB_RESPONDERS: &str = "responder";
 
 pub static BOT_PREFIX: &str = "!r";
 
+/// Prefix of the custom ids of the buttons and forms on tag suggestions
+pub static TAG_SUGGESTION_ID: &str = "tagsuggestion";
+pub static ROLE_MESSAGE_ID: &str = "rolemessage";
+/// Panel that the role message and roles of servers from before named role panels are moved to
+pub static DEFAULT_ROLE_PANEL: &str = "default";
+/// Discord allows at most 25 buttons (5 rows of 5) and 25 select menu options per message
+pub static ROLE_COMPONENT_LIMIT: usize = 25;
+
+/// Most words with the trigger sigil that are looked up as tags in a single message
+pub static TAG_TRIGGER_CANDIDATE_LIMIT: usize = 5;
+pub static DEFAULT_TAG_CATEGORY: &str = "Uncategorized";
+pub static DEFAULT_TAG_TRIGGER_COOLDOWN: u64 = 30;
+pub static DEFAULT_TAG_TRASH_RETENTION_DAYS: u64 = 30;
+pub static TAG_TRASH_PURGE_INTERVAL_SECS: u64 = 60 * 60;
+pub static DEFAULT_RESPONDER_COOLDOWN: u64 = 60;
+pub static ROLE_EXPIRY_CHECK_INTERVAL_SECS: u64 = 60;
+/// Error code Discord answers with when a member isn't on the server (anymore)
+pub static DISCORD_UNKNOWN_MEMBER: isize = 10007;
+/// Longest duration a role can be handed out for
+pub static ROLE_DURATION_LIMIT_SECS: u64 = 365 * 24 * 60 * 60;
+/// Compiled size limit for responder patterns, so huge regexes can't slow down every message
+pub static RESPONDER_PATTERN_SIZE_LIMIT: usize = 1024 * 1024;
+pub static TAG_IMPORT_SIZE_LIMIT: u32 = 8 * 1024 * 1024;
+pub static TAG_ATTACHMENT_DIR: &str = "attachments";
+pub static TAG_ATTACHMENT_COUNT_LIMIT: usize = 4;
+/// Limit for all files of a tag together, so the tag can still be posted without boosts
+pub static TAG_ATTACHMENT_SIZE_LIMIT: u32 = 8 * 1024 * 1024;
+/// Files younger than this are never cleaned up, as the tag they belong to may not be saved yet
+pub static TAG_ATTACHMENT_GRACE_SECS: u64 = 600;
+
 pub static DEFAULT_AVATAR: &str = "https://cdn.discordapp.com/embed/avatars/0.png";
+
+pub static MESSAGE_CHAR_LIMIT: usize = 2000;
+pub static EMBED_TITLE_LIMIT: usize = 256;
+pub static EMBED_DESCRIPTION_LIMIT: usize = 4096;
+pub static EMBED_FIELD_COUNT_LIMIT: usize = 25;
+pub static EMBED_FIELD_NAME_LIMIT: usize = 256;
+pub static EMBED_FIELD_VALUE_LIMIT: usize = 1024;
+pub static EMBED_FOOTER_LIMIT: usize = 2048;
+pub static EMBED_TOTAL_LIMIT: usize = 6000;
@@ -1,62 +1,188 @@
 use log::warn;
 use once_cell::sync::Lazy;
-use poise::serenity_prelude::{Emoji, GuildId, Message, User};
+use poise::serenity_prelude::{GuildId, Message, Timestamp, User, UserId};
+use std::collections::HashMap;
+use std::sync::Arc;
 use surrealdb::engine::remote::ws::{Client, Ws};
 use surrealdb::opt::auth::Root;
 use surrealdb::Surreal;
+use tokio::sync::{Mutex, OnceCell};
 
 use crate::constants;
 use crate::types::*;
 
-pub static DB: Lazy<Surreal<Client>> = Lazy::new(Surreal::init);
+/// Connection to a db, set up on first use
+type DbCell = Arc<OnceCell<Surreal<Client>>>;
 
-/// Set up the connection to the surreal db server
-pub async fn initiate_db() -> surrealdb::Result<()> {
-    DB.connect::<Ws>("localhost:8000").await?;
-    warn!("Connected to DB at localhost:8000");
+/// One connection per db. The ns and db a query runs in are part of the session of a connection,
+/// so servers sharing a connection would read and write each other's db when called concurrently.
+/// Each db has its own cell, so connecting to one db doesn't hold up the others
+static CONNECTIONS: Lazy<Mutex<HashMap<String, DbCell>>> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Opens a connection to the surreal db server that uses the given db
+async fn connect(dbname: &str) -> surrealdb::Result<Surreal<Client>> {
+    let db = Surreal::new::<Ws>("localhost:8000").await?;
 
     let dbuser = std::env::var("SURREAL_USER").expect("missing SURREAL_USER");
     let dbpass = std::env::var("SURREAL_PASS").expect("missing SURREAL_PASS");
 
-    DB.signin(Root {
+    db.signin(Root {
         username: &dbuser,
         password: &dbpass,
     })
     .await?;
-    warn!("Signed into DB");
 
-    DB.use_ns(constants::DB_NS)
-        .use_db(constants::DB_DEFAULT_DB)
-        .await?;
-    warn!(
-        "Using ns {} and db {}",
-        constants::DB_NS,
-        constants::DB_DEFAULT_DB
+    db.use_ns(constants::DB_NS).use_db(dbname).await?;
+    warn!("Using ns {} and db {}", constants::DB_NS, dbname);
+
+    Ok(db)
+}
+
+/// Set up the connection to the surreal db server
+pub async fn initiate_db() -> surrealdb::Result<()> {
+    let db = connect(constants::DB_DEFAULT_DB).await?;
+    warn!("Connected to DB at localhost:8000");
+
+    CONNECTIONS.lock().await.insert(
+        constants::DB_DEFAULT_DB.to_string(),
+        Arc::new(OnceCell::from(db)),
     );
 
     Ok(())
 }
 
-async fn setdb(guildid: &Option<GuildId>) -> Result<(), DBIError> {
-    let dbname = match guildid {
+/// Name of the db of a server. Everything that doesn't belong to a server goes to the global db
+fn dbname(guildid: &Option<GuildId>) -> String {
+    match guildid {
         Some(id) => id.get().to_string(),
-        None => "global".to_string(),
-    };
-    DB.use_ns(constants::DB_NS).use_db(&dbname).await?;
+        None => constants::DB_DEFAULT_DB.to_string(),
+    }
+}
+
+/// Get the connection to the db of a server, connecting on first use
+async fn connection(guildid: &Option<GuildId>) -> Result<Surreal<Client>, DBIError> {
+    let name = dbname(guildid);
+    let cell = CONNECTIONS
+        .lock()
+        .await
+        .entry(name.to_owned())
+        .or_default()
+        .to_owned();
+    let db = cell
+        .get_or_try_init(|| async {
+            let db = connect(&name).await?;
+            if let Err(e) = migrate_role_panels(&db, &name).await {
+                warn!("In {}, db_interaction::connection: {}", name, e);
+            }
+            Ok::<_, DBIError>(db)
+        })
+        .await?;
+
+    Ok(db.to_owned())
+}
+
+/// Moves the role message and roles of a server from before role panels had names to the panel
+/// `constants::DEFAULT_ROLE_PANEL`. Does nothing on servers that don't have any of them
+async fn migrate_role_panels(db: &Surreal<Client>, name: &str) -> Result<(), DBIError> {
+    let legacy: Option<RoleMessage> = db.select((constants::DB_ROLEMSG, "0")).await?;
+    if let Some(legacy) = legacy {
+        db.query(
+            "BEGIN TRANSACTION;\
+DELETE type::thing($panels, \"0\");\
+CREATE type::thing($panels, $name) CONTENT $panel;\
+COMMIT TRANSACTION;",
+        )
+        .bind(("panels", constants::DB_ROLEMSG))
+        .bind(("name", constants::DEFAULT_ROLE_PANEL))
+        .bind((
+            "panel",
+            RoleMessage {
+                name: constants::DEFAULT_ROLE_PANEL.to_string(),
+                ..legacy
+            },
+        ))
+        .await?
+        .check()?;
+        warn!(
+            "In {}, db_interaction::migrate_role_panels: moved the role message to panel {}",
+            name,
+            constants::DEFAULT_ROLE_PANEL
+        );
+    }
+
+    let mut response = db
+        .query("UPDATE type::table($roles) SET panel = $name WHERE panel = NONE OR panel = \"\"")
+        .bind(("roles", constants::DB_ROLES))
+        .bind(("name", constants::DEFAULT_ROLE_PANEL))
+        .await?;
+    let moved: Vec<UserRole> = response.take(0)?;
+    if !moved.is_empty() {
+        warn!(
+            "In {}, db_interaction::migrate_role_panels: moved {} roles to panel {}",
+            name,
+            moved.len(),
+            constants::DEFAULT_ROLE_PANEL
+        );
+    }
 
     Ok(())
 }
 
+/// Returns the tags that have the given name as an alias. Runs on the connection of the caller
+async fn select_tags_by_alias(db: &Surreal<Client>, alias: &str) -> Result<Vec<Tag>, DBIError> {
+    let mut response = db
+        .query("SELECT * FROM type::table($table) WHERE aliases CONTAINS $alias")
+        .bind(("table", constants::DB_TAGS))
+        .bind(("alias", alias))
+        .await?;
+    let tags: Vec<Tag> = response.take(0)?;
+
+    Ok(tags)
+}
+
+/// Checks if the name or any of the aliases of the tag is already used as the name or an alias of
+/// another tag. The tag called `except` is ignored. Runs on the connection of the caller
+async fn tag_names_taken(
+    db: &Surreal<Client>,
+    tag: &Tag,
+    except: Option<&str>,
+) -> Result<bool, DBIError> {
+    for name in std::iter::once(&tag.name).chain(tag.aliases.iter()) {
+        let existing_tag: Option<Tag> = db.select((constants::DB_TAGS, name)).await?;
+        if existing_tag.is_some_and(|t| Some(t.name.as_str()) != except) {
+            return Ok(true);
+        }
+        if select_tags_by_alias(db, name)
+            .await?
+            .iter()
+            .any(|t| Some(t.name.as_str()) != except)
+        {
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+/// Fails with `DBIError::TagAlreadyExists` if the name or one of the aliases of the tag is already
+/// used by another tag. Lets callers bail out before doing expensive work for a new tag
+pub async fn check_tag_names(tag: &Tag, guildid: Option<GuildId>) -> Result<(), DBIError> {
+    let db = connection(&guildid).await?;
+
+    match tag_names_taken(&db, tag, None).await? {
+        true => Err(DBIError::TagAlreadyExists),
+        false => Ok(()),
+    }
+}
+
 /// Create a tag in the database with the id equal to the tag name
 pub async fn create_tag(tag: Tag, guildid: Option<GuildId>) -> Result<Tag, DBIError> {
-    setdb(&guildid).await?;
-
-    let existing_tag: Option<Tag> = DB.select((constants::DB_TAGS, &tag.name)).await?;
+    let db = connection(&guildid).await?;
 
-    match existing_tag {
-        Some(_) => Err(DBIError::TagAlreadyExists),
-        None => {
-            let created_tag: Option<Tag> = DB
+    match tag_names_taken(&db, &tag, None).await? {
+        true => Err(DBIError::TagAlreadyExists),
+        false => {
+            let created_tag: Option<Tag> = db
                 .create((constants::DB_TAGS, &tag.name))
                 .content(tag)
                 .await?;
@@ -65,52 +191,677 @@ pub async fn create_tag(tag: Tag, guildid: Option<GuildId>) -> Result<Tag, DBIEr
     }
 }
 
-/// Get a tag by its name. Returns an `TagError::TagNotFound` if the tag doens't exist
+/// Get a tag by its name or one of its aliases. Returns an `TagError::TagNotFound` if the tag
+/// doens't exist
 pub async fn get_tag(tagname: &str, guildid: Option<GuildId>) -> Result<Tag, DBIError> {
-    setdb(&guildid).await?;
+    let db = connection(&guildid).await?;
 
-    let tag: Option<Tag> = DB.select((constants::DB_TAGS, tagname)).await?;
+    let tag: Option<Tag> = db.select((constants::DB_TAGS, tagname)).await?;
+    if let Some(t) = tag {
+        return Ok(t);
+    }
 
-    // Note here that creation of tags prevents a name to be used multiple times.
+    // Note here that creation of tags prevents a name or alias to be used multiple times.
     // Thus the resulting vector is either of length 0 or 1
-    match tag {
+    match select_tags_by_alias(&db, tagname).await?.into_iter().next() {
         Some(t) => Ok(t),
         None => Err(DBIError::TagNotFound),
     }
 }
 
-/// Returns a vector of all the tags in the DB. Can be of length 0
+/// Get a tag by its name or one of its aliases from the server. If the server has no such tag, the
+/// global tags shared by all servers are searched
+pub async fn find_tag(
+    tagname: &str,
+    guildid: Option<GuildId>,
+) -> Result<(Tag, TagSource), DBIError> {
+    match get_tag(tagname, guildid).await {
+        Ok(t) => Ok((t, TagSource::Guild)),
+        Err(DBIError::TagNotFound) if guildid.is_some() => {
+            Ok((get_tag(tagname, None).await?, TagSource::Global))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+/// Returns a vector of all the tags in the db. Can be of length 0
 pub async fn get_all_tags(guildid: Option<GuildId>) -> Result<Vec<Tag>, DBIError> {
-    setdb(&guildid).await?;
+    let db = connection(&guildid).await?;
 
-    let tags: Vec<Tag> = DB.select(constants::DB_TAGS).await?;
+    let tags: Vec<Tag> = db.select(constants::DB_TAGS).await?;
 
     Ok(tags)
 }
 
-/// Removes a tag by its name. Returns `TagError::TagNotFound` if tag can't be found
-pub async fn remove_tag(tagname: &str, guildid: Option<GuildId>) -> Result<Tag, DBIError> {
-    setdb(&guildid).await?;
+/// Replaces an existing tag. If the name of the tag changed, the record is moved to the new name.
+/// Returns `DBIError::TagNotFound` if the old tag doesn't exist and `DBIError::TagAlreadyExists`
+/// if the new name is already taken by another tag
+pub async fn update_tag(
+    oldname: &str,
+    tag: Tag,
+    guildid: Option<GuildId>,
+) -> Result<Tag, DBIError> {
+    let db = connection(&guildid).await?;
+
+    let cur_tag: Option<Tag> = db.select((constants::DB_TAGS, oldname)).await?;
+    if cur_tag.is_none() {
+        return Err(DBIError::TagNotFound);
+    }
+
+    if tag_names_taken(&db, &tag, Some(oldname)).await? {
+        return Err(DBIError::TagAlreadyExists);
+    }
+    // A renamed tag is moved to a new record, both in one transaction so the tag can't get lost
+    // in between
+    let query = match tag.name == oldname {
+        true => "UPDATE type::thing($table, $name) CONTENT $tag;",
+        false => {
+            "BEGIN TRANSACTION;\
+DELETE type::thing($table, $oldname);\
+CREATE type::thing($table, $name) CONTENT $tag;\
+COMMIT TRANSACTION;"
+        }
+    };
+    db.query(query)
+        .bind(("table", constants::DB_TAGS))
+        .bind(("oldname", oldname))
+        .bind(("name", &tag.name))
+        .bind(("tag", &tag))
+        .await?
+        .check()?;
+    warn!(
+        "In {}, db_interaction::update_tag: updated Tag {}",
+        dbname(&guildid),
+        oldname
+    );
+
+    Ok(tag)
+}
+
+/// Increments the usage counter of a tag
+pub async fn increment_tag_uses(tagname: &str, guildid: Option<GuildId>) -> Result<(), DBIError> {
+    let db = connection(&guildid).await?;
+
+    db.query("UPDATE type::thing($table, $name) SET uses = (uses OR 0) + 1")
+        .bind(("table", constants::DB_TAGS))
+        .bind(("name", tagname))
+        .await?;
+
+    Ok(())
+}
+
+/// Moves the overwritten tags into the trash and creates the new tags, all in a single
+/// transaction. If any of the statements fails, nothing is changed. Returns the number of created
+/// tags
+pub async fn import_tags(
+    overwritten: Vec<TrashedTag>,
+    tags: Vec<Tag>,
+    guildid: Option<GuildId>,
+) -> Result<usize, DBIError> {
+    let db = connection(&guildid).await?;
+
+    let mut query = String::from("BEGIN TRANSACTION;");
+    for idx in 0..overwritten.len() {
+        query.push_str(&format!(
+            "DELETE type::thing($table, $remove{}); CREATE type::thing($trash, $id{}) CONTENT $trashed{};",
+            idx, idx, idx
+        ));
+    }
+    for idx in 0..tags.len() {
+        query.push_str(&format!(
+            "CREATE type::thing($table, $name{}) CONTENT $tag{};",
+            idx, idx
+        ));
+    }
+    query.push_str("COMMIT TRANSACTION;");
+
+    let mut request = db
+        .query(query)
+        .bind(("table", constants::DB_TAGS))
+        .bind(("trash", constants::DB_TAGTRASH));
+    for (idx, trashed) in overwritten.iter().enumerate() {
+        request = request
+            .bind((format!("remove{}", idx), &trashed.tag.name))
+            .bind((format!("id{}", idx), &trashed.trash_id))
+            .bind((format!("trashed{}", idx), trashed));
+    }
+    for (idx, tag) in tags.iter().enumerate() {
+        request = request
+            .bind((format!("name{}", idx), &tag.name))
+            .bind((format!("tag{}", idx), tag));
+    }
+    request.await?.check()?;
+
+    warn!(
+        "In {}, db_interaction::import_tags: moved {} to the trash and imported {} Tags",
+        dbname(&guildid),
+        overwritten.len(),
+        tags.len()
+    );
+
+    Ok(tags.len())
+}
+
+/// Moves a tag into the trash in a single transaction. Returns `TagError::TagNotFound` if the tag
+/// can't be found
+pub async fn trash_tag(
+    tagname: &str,
+    deleted_by: &User,
+    guildid: Option<GuildId>,
+) -> Result<TrashedTag, DBIError> {
+    let db = connection(&guildid).await?;
+
+    let tag: Option<Tag> = db.select((constants::DB_TAGS, tagname)).await?;
+    let tag = match tag {
+        Some(t) => t,
+        None => return Err(DBIError::TagNotFound),
+    };
+    let deleted_at = Timestamp::now();
+    let trashed = TrashedTag {
+        trash_id: TrashedTag::new_id(),
+        tag,
+        deleted_by: deleted_by.to_owned(),
+        deleted_at,
+    };
+
+    db.query(
+        "BEGIN TRANSACTION;\
+DELETE type::thing($table, $name);\
+CREATE type::thing($trash, $id) CONTENT $trashed;\
+COMMIT TRANSACTION;",
+    )
+    .bind(("table", constants::DB_TAGS))
+    .bind(("name", &trashed.tag.name))
+    .bind(("trash", constants::DB_TAGTRASH))
+    .bind(("id", &trashed.trash_id))
+    .bind(("trashed", &trashed))
+    .await?
+    .check()?;
+    warn!(
+        "In {}, db_interaction::trash_tag: {} moved Tag {} to the trash",
+        dbname(&guildid),
+        deleted_by.name,
+        &trashed.tag.name
+    );
+
+    Ok(trashed)
+}
+
+/// Returns all tags in the trash of the server, most recently removed first
+pub async fn get_trashed_tags(guildid: Option<GuildId>) -> Result<Vec<TrashedTag>, DBIError> {
+    let db = connection(&guildid).await?;
+
+    let mut trashed: Vec<TrashedTag> = db.select(constants::DB_TAGTRASH).await?;
+    trashed.sort_by_key(|t| std::cmp::Reverse(t.deleted_at));
+
+    Ok(trashed)
+}
+
+/// Moves a tag from the trash back to the tags in a single transaction. Fails with
+/// `DBIError::TagAlreadyExists` if the name or an alias was taken in the meantime
+pub async fn restore_tag(trash_id: &str, guildid: Option<GuildId>) -> Result<Tag, DBIError> {
+    let db = connection(&guildid).await?;
+
+    let trashed: Option<TrashedTag> = db.select((constants::DB_TAGTRASH, trash_id)).await?;
+    let trashed = match trashed {
+        Some(t) => t,
+        None => return Err(DBIError::TrashedTagNotFound),
+    };
+    if tag_names_taken(&db, &trashed.tag, None).await? {
+        return Err(DBIError::TagAlreadyExists);
+    }
+
+    db.query(
+        "BEGIN TRANSACTION;\
+DELETE type::thing($trash, $id);\
+CREATE type::thing($table, $name) CONTENT $tag;\
+COMMIT TRANSACTION;",
+    )
+    .bind(("trash", constants::DB_TAGTRASH))
+    .bind(("id", trash_id))
+    .bind(("table", constants::DB_TAGS))
+    .bind(("name", &trashed.tag.name))
+    .bind(("tag", &trashed.tag))
+    .await?
+    .check()?;
+    warn!(
+        "In {}, db_interaction::restore_tag: restored Tag {}",
+        dbname(&guildid),
+        &trashed.tag.name
+    );
+
+    Ok(trashed.tag)
+}
+
+/// Permanently removes all tags from the trash that were removed before the cutoff. Returns the
+/// purged tags
+pub async fn purge_trash(
+    cutoff: Timestamp,
+    guildid: Option<GuildId>,
+) -> Result<Vec<TrashedTag>, DBIError> {
+    let expired: Vec<TrashedTag> = get_trashed_tags(guildid)
+        .await?
+        .into_iter()
+        .filter(|t| t.deleted_at < cutoff)
+        .collect();
+    let db = connection(&guildid).await?;
+    for trashed in expired.iter() {
+        let _purged: Option<TrashedTag> = db
+            .delete((constants::DB_TAGTRASH, &trashed.trash_id))
+            .await?;
+    }
+    if !expired.is_empty() {
+        warn!(
+            "In {}, db_interaction::purge_trash: purged {} Tags from the trash",
+            dbname(&guildid),
+            expired.len()
+        );
+    }
+
+    Ok(expired)
+}
+
+/// Get the trash settings of the server. Returns the defaults if none were saved yet
+pub async fn get_tag_trash_config(guildid: Option<GuildId>) -> Result<TagTrashConfig, DBIError> {
+    let db = connection(&guildid).await?;
+
+    let config: Option<TagTrashConfig> = db.select((constants::DB_TAGTRASHCONFIG, "0")).await?;
+
+    Ok(config.unwrap_or_default())
+}
+
+/// Saves the trash settings of the server, creating the record if none exists
+pub async fn set_tag_trash_config(
+    config: TagTrashConfig,
+    guildid: Option<GuildId>,
+) -> Result<TagTrashConfig, DBIError> {
+    let db = connection(&guildid).await?;
+
+    let cur_config: Option<TagTrashConfig> = db.select((constants::DB_TAGTRASHCONFIG, "0")).await?;
+    let new_config: Option<TagTrashConfig> = match cur_config {
+        Some(_) => {
+            db.update((constants::DB_TAGTRASHCONFIG, "0"))
+                .content(config)
+                .await?
+        }
+        None => {
+            db.create((constants::DB_TAGTRASHCONFIG, "0"))
+                .content(config)
+                .await?
+        }
+    };
+    warn!(
+        "In {}, db_interaction::set_tag_trash_config: changed settings to {:?}",
+        dbname(&guildid),
+        &new_config
+    );
+
+    Ok(new_config.unwrap_or_default())
+}
+
+/// Get the inline tag trigger settings of the server. Returns the defaults if none were saved yet
+pub async fn get_tag_trigger_config(
+    guildid: Option<GuildId>,
+) -> Result<TagTriggerConfig, DBIError> {
+    let db = connection(&guildid).await?;
+
+    let config: Option<TagTriggerConfig> = db.select((constants::DB_TAGTRIGGER, "0")).await?;
+
+    Ok(config.unwrap_or_default())
+}
+
+/// Saves the inline tag trigger settings of the server, creating the record if none exists
+pub async fn set_tag_trigger_config(
+    config: TagTriggerConfig,
+    guildid: Option<GuildId>,
+) -> Result<TagTriggerConfig, DBIError> {
+    let db = connection(&guildid).await?;
+
+    let cur_config: Option<TagTriggerConfig> = db.select((constants::DB_TAGTRIGGER, "0")).await?;
+    let new_config: Option<TagTriggerConfig> = match cur_config {
+        Some(_) => {
+            db.update((constants::DB_TAGTRIGGER, "0"))
+                .content(config)
+                .await?
+        }
+        None => {
+            db.create((constants::DB_TAGTRIGGER, "0"))
+                .content(config)
+                .await?
+        }
+    };
+    warn!(
+        "In {}, db_interaction::set_tag_trigger_config: changed settings to {:?}",
+        guildid.unwrap().get(),
+        &new_config
+    );
+
+    Ok(new_config.unwrap_or_default())
+}
+
+/// Stores a new tag suggestion under its suggestion id
+pub async fn create_tag_suggestion(
+    suggestion: TagSuggestion,
+    guildid: Option<GuildId>,
+) -> Result<TagSuggestion, DBIError> {
+    let db = connection(&guildid).await?;
 
-    let tag: Option<Tag> = DB.delete((constants::DB_TAGS, tagname)).await?;
-    match tag {
-        Some(t) => {
+    let created: Option<TagSuggestion> = db
+        .create((constants::DB_TAGSUGGESTIONS, &suggestion.suggestion_id))
+        .content(suggestion)
+        .await?;
+    match created {
+        Some(s) => {
             warn!(
-                "In {}, db_interaction::remove_tag: removed Tag: {:?}",
-                &guildid.unwrap().get(),
-                &t
+                "In {}, db_interaction::create_tag_suggestion: {} suggested Tag {}",
+                dbname(&guildid),
+                &s.suggester.name,
+                &s.name
             );
-            Ok(t)
+            Ok(s)
         }
-        None => Err(DBIError::TagNotFound),
+        None => Err(DBIError::SuggestionNotFound),
     }
 }
 
+/// Get a tag suggestion by its id. Returns `DBIError::SuggestionNotFound` if it was already
+/// handled
+pub async fn get_tag_suggestion(
+    suggestion_id: &str,
+    guildid: Option<GuildId>,
+) -> Result<TagSuggestion, DBIError> {
+    let db = connection(&guildid).await?;
+
+    let suggestion: Option<TagSuggestion> = db
+        .select((constants::DB_TAGSUGGESTIONS, suggestion_id))
+        .await?;
+
+    suggestion.ok_or(DBIError::SuggestionNotFound)
+}
+
+/// Replaces a stored tag suggestion
+pub async fn update_tag_suggestion(
+    suggestion: TagSuggestion,
+    guildid: Option<GuildId>,
+) -> Result<TagSuggestion, DBIError> {
+    let db = connection(&guildid).await?;
+
+    let updated: Option<TagSuggestion> = db
+        .update((constants::DB_TAGSUGGESTIONS, &suggestion.suggestion_id))
+        .content(suggestion)
+        .await?;
+
+    updated.ok_or(DBIError::SuggestionNotFound)
+}
+
+/// Removes a tag suggestion once it was approved or rejected
+pub async fn remove_tag_suggestion(
+    suggestion_id: &str,
+    guildid: Option<GuildId>,
+) -> Result<TagSuggestion, DBIError> {
+    let db = connection(&guildid).await?;
+
+    let removed: Option<TagSuggestion> = db
+        .delete((constants::DB_TAGSUGGESTIONS, suggestion_id))
+        .await?;
+
+    removed.ok_or(DBIError::SuggestionNotFound)
+}
+
+/// Get the channel tag suggestions are posted to. Returns None if suggestions are disabled
+pub async fn get_tag_review_config(
+    guildid: Option<GuildId>,
+) -> Result<Option<TagReviewConfig>, DBIError> {
+    let db = connection(&guildid).await?;
+
+    let config: Option<TagReviewConfig> = db.select((constants::DB_TAGREVIEW, "0")).await?;
+
+    Ok(config)
+}
+
+/// Sets the channel tag suggestions are posted to. Passing None disables suggestions
+pub async fn set_tag_review_config(
+    config: Option<TagReviewConfig>,
+    guildid: Option<GuildId>,
+) -> Result<(), DBIError> {
+    let db = connection(&guildid).await?;
+
+    let cur_config: Option<TagReviewConfig> = db.select((constants::DB_TAGREVIEW, "0")).await?;
+    match (cur_config, config) {
+        (Some(_), Some(c)) => {
+            let _: Option<TagReviewConfig> =
+                db.update((constants::DB_TAGREVIEW, "0")).content(c).await?;
+        }
+        (None, Some(c)) => {
+            let _: Option<TagReviewConfig> =
+                db.create((constants::DB_TAGREVIEW, "0")).content(c).await?;
+        }
+        (Some(_), None) => {
+            let _: Option<TagReviewConfig> = db.delete((constants::DB_TAGREVIEW, "0")).await?;
+        }
+        (None, None) => {}
+    };
+    warn!(
+        "In {}, db_interaction::set_tag_review_config: changed tag review channel",
+        dbname(&guildid)
+    );
+
+    Ok(())
+}
+
+/// Get the channel the bot reports its own changes to. Returns None if no channel is set
+pub async fn get_mod_log_config(
+    guildid: Option<GuildId>,
+) -> Result<Option<ModLogConfig>, DBIError> {
+    let db = connection(&guildid).await?;
+
+    let config: Option<ModLogConfig> = db.select((constants::DB_MODLOG, "0")).await?;
+
+    Ok(config)
+}
+
+/// Sets the channel the bot reports its own changes to. Passing None disables the moderation log
+pub async fn set_mod_log_config(
+    config: Option<ModLogConfig>,
+    guildid: Option<GuildId>,
+) -> Result<(), DBIError> {
+    let db = connection(&guildid).await?;
+
+    let cur_config: Option<ModLogConfig> = db.select((constants::DB_MODLOG, "0")).await?;
+    match (cur_config, config) {
+        (Some(_), Some(c)) => {
+            let _: Option<ModLogConfig> = db.update((constants::DB_MODLOG, "0")).content(c).await?;
+        }
+        (None, Some(c)) => {
+            let _: Option<ModLogConfig> = db.create((constants::DB_MODLOG, "0")).content(c).await?;
+        }
+        (Some(_), None) => {
+            let _: Option<ModLogConfig> = db.delete((constants::DB_MODLOG, "0")).await?;
+        }
+        (None, None) => {}
+    };
+    warn!(
+        "In {}, db_interaction::set_mod_log_config: changed moderation log channel",
+        dbname(&guildid)
+    );
+
+    Ok(())
+}
+
+/// Get the language a user prefers to read tags in. Preferences are stored in the global db
+pub async fn get_user_language(userid: UserId) -> Result<Option<String>, DBIError> {
+    let db = connection(&None).await?;
+
+    let language: Option<UserLanguage> = db
+        .select((constants::DB_USERLANGUAGE, userid.to_string()))
+        .await?;
+
+    Ok(language.map(|l| l.language))
+}
+
+/// Saves the language a user prefers to read tags in. Passing `None` removes the preference
+pub async fn set_user_language(
+    user: &User,
+    language: Option<String>,
+) -> Result<Option<String>, DBIError> {
+    let db = connection(&None).await?;
+
+    let id = (constants::DB_USERLANGUAGE, user.id.to_string());
+    let new_language: Option<UserLanguage> = match language {
+        // Updating a record that doesn't exist yet creates it
+        Some(language) => {
+            db.update(id)
+                .content(UserLanguage {
+                    user: user.to_owned(),
+                    language,
+                })
+                .await?
+        }
+        None => {
+            let _removed: Option<UserLanguage> = db.delete(id).await?;
+            None
+        }
+    };
+    warn!(
+        "In {}, db_interaction::set_user_language: {} set their language to {:?}",
+        dbname(&None),
+        user.name,
+        new_language.as_ref().map(|l| &l.language)
+    );
+
+    Ok(new_language.map(|l| l.language))
+}
+
+/// Get the mention policy of the server. Returns the default policy if none was saved yet
+pub async fn get_mention_policy(guildid: Option<GuildId>) -> Result<MentionPolicy, DBIError> {
+    let db = connection(&guildid).await?;
+
+    let policy: Option<MentionPolicy> = db.select((constants::DB_MENTIONPOLICY, "0")).await?;
+
+    Ok(policy.unwrap_or_default())
+}
+
+/// Saves the mention policy of the server, creating the record if none exists
+pub async fn set_mention_policy(
+    policy: MentionPolicy,
+    guildid: Option<GuildId>,
+) -> Result<MentionPolicy, DBIError> {
+    let db = connection(&guildid).await?;
+
+    let cur_policy: Option<MentionPolicy> = db.select((constants::DB_MENTIONPOLICY, "0")).await?;
+    let new_policy: Option<MentionPolicy> = match cur_policy {
+        Some(_) => {
+            db.update((constants::DB_MENTIONPOLICY, "0"))
+                .content(policy)
+                .await?
+        }
+        None => {
+            db.create((constants::DB_MENTIONPOLICY, "0"))
+                .content(policy)
+                .await?
+        }
+    };
+    warn!(
+        "In {}, db_interaction::set_mention_policy: changed mention policy to {:?}",
+        dbname(&guildid),
+        &new_policy
+    );
+
+    Ok(new_policy.unwrap_or_default())
+}
+
+/// Returns all auto-responders of the server, ordered by name
+pub async fn get_all_responders(guildid: Option<GuildId>) -> Result<Vec<AutoResponder>, DBIError> {
+    let db = connection(&guildid).await?;
+
+    let mut responders: Vec<AutoResponder> = db.select(constants::DB_RESPONDERS).await?;
+    responders.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(responders)
+}
+
+/// Get an auto-responder by its name
+pub async fn get_responder(
+    name: &str,
+    guildid: Option<GuildId>,
+) -> Result<AutoResponder, DBIError> {
+    let db = connection(&guildid).await?;
+
+    let responder: Option<AutoResponder> = db.select((constants::DB_RESPONDERS, name)).await?;
+
+    responder.ok_or(DBIError::ResponderNotFound)
+}
+
+/// Stores a new auto-responder under its name. Returns `DBIError::ResponderAlreadyExists` if the
+/// name is taken
+pub async fn create_responder(
+    responder: AutoResponder,
+    guildid: Option<GuildId>,
+) -> Result<AutoResponder, DBIError> {
+    let db = connection(&guildid).await?;
+
+    let existing: Option<AutoResponder> = db
+        .select((constants::DB_RESPONDERS, &responder.name))
+        .await?;
+    if existing.is_some() {
+        return Err(DBIError::ResponderAlreadyExists);
+    }
+
+    let created: Option<AutoResponder> = db
+        .create((constants::DB_RESPONDERS, &responder.name))
+        .content(responder)
+        .await?;
+    warn!(
+        "In {}, db_interaction::create_responder: created AutoResponder {:?}",
+        dbname(&guildid),
+        &created
+    );
+
+    created.ok_or(DBIError::ResponderAlreadyExists)
+}
+
+/// Overwrites an existing auto-responder
+pub async fn update_responder(
+    responder: AutoResponder,
+    guildid: Option<GuildId>,
+) -> Result<AutoResponder, DBIError> {
+    let db = connection(&guildid).await?;
+
+    let updated: Option<AutoResponder> = db
+        .update((constants::DB_RESPONDERS, &responder.name))
+        .content(responder)
+        .await?;
+    warn!(
+        "In {}, db_interaction::update_responder: updated AutoResponder {:?}",
+        dbname(&guildid),
+        &updated
+    );
+
+    updated.ok_or(DBIError::ResponderNotFound)
+}
+
+/// Removes an auto-responder by its name
+pub async fn remove_responder(
+    name: &str,
+    guildid: Option<GuildId>,
+) -> Result<AutoResponder, DBIError> {
+    let db = connection(&guildid).await?;
+
+    let removed: Option<AutoResponder> = db.delete((constants::DB_RESPONDERS, name)).await?;
+    warn!(
+        "In {}, db_interaction::remove_responder: removed AutoResponder {:?}",
+        dbname(&guildid),
+        &removed
+    );
+
+    removed.ok_or(DBIError::ResponderNotFound)
+}
+
 /// Get a vector of all the roles that users can asign to themselves. Can be of length 0.
 pub async fn get_all_roles(guildid: Option<GuildId>) -> Result<Vec<UserRole>, DBIError> {
-    setdb(&guildid).await?;
+    let db = connection(&guildid).await?;
 
-    let roles: Vec<UserRole> = DB.select(constants::DB_ROLES).await?;
+    let roles: Vec<UserRole> = db.select(constants::DB_ROLES).await?;
 
     Ok(roles)
 }
@@ -118,9 +869,9 @@ pub async fn get_all_roles(guildid: Option<GuildId>) -> Result<Vec<UserRole>, DB
 /// Add a role to the saved user-assignable roles. Returns `DBIError::RoleAlreadyExists` if the
 /// role was already added previously
 pub async fn add_role(role: UserRole, guildid: Option<GuildId>) -> Result<UserRole, DBIError> {
-    setdb(&guildid).await?;
+    let db = connection(&guildid).await?;
 
-    let created: Option<UserRole> = DB
+    let created: Option<UserRole> = db
         .create((constants::DB_ROLES, role.guild_role.id.to_string()))
         .content(role)
         .await?;
@@ -139,9 +890,9 @@ pub async fn add_role(role: UserRole, guildid: Option<GuildId>) -> Result<UserRo
 
 /// Get a role by its ID
 pub async fn get_role(role_id: String, guildid: Option<GuildId>) -> Result<UserRole, DBIError> {
-    setdb(&guildid).await?;
+    let db = connection(&guildid).await?;
 
-    let user_role: Option<UserRole> = DB.select((constants::DB_ROLES, role_id)).await?;
+    let user_role: Option<UserRole> = db.select((constants::DB_ROLES, role_id)).await?;
 
     match user_role {
         Some(ur) => Ok(ur),
@@ -149,12 +900,29 @@ pub async fn get_role(role_id: String, guildid: Option<GuildId>) -> Result<UserR
     }
 }
 
+/// Overwrites an existing user-assignable role
+pub async fn update_role(role: UserRole, guildid: Option<GuildId>) -> Result<UserRole, DBIError> {
+    let db = connection(&guildid).await?;
+
+    let updated: Option<UserRole> = db
+        .update((constants::DB_ROLES, role.guild_role.id.to_string()))
+        .content(role)
+        .await?;
+    warn!(
+        "In {}, db_interaction::update_role: updated UserRole {:?}",
+        dbname(&guildid),
+        &updated
+    );
+
+    updated.ok_or(DBIError::RoleNotFound)
+}
+
 /// Remove a role from the user-assignable roles. Returns `DBIError::RoleNotFound` if the role is
 /// not in the database
 pub async fn remove_role(role: UserRole, guildid: Option<GuildId>) -> Result<UserRole, DBIError> {
-    setdb(&guildid).await?;
+    let db = connection(&guildid).await?;
 
-    let removed_role: Option<UserRole> = DB
+    let removed_role: Option<UserRole> = db
         .delete((constants::DB_ROLES, role.guild_role.id.to_string()))
         .await?;
     match removed_role {
@@ -170,63 +938,90 @@ pub async fn remove_role(role: UserRole, guildid: Option<GuildId>) -> Result<Use
     }
 }
 
-/// Remove all roles from the db
-pub async fn remove_all_roles(guildid: Option<GuildId>) -> Result<Vec<UserRole>, DBIError> {
-    setdb(&guildid).await?;
+/// Get the roles offered on a role panel. Can be of length 0.
+pub async fn get_panel_roles(
+    panel: &str,
+    guildid: Option<GuildId>,
+) -> Result<Vec<UserRole>, DBIError> {
+    let db = connection(&guildid).await?;
 
-    let roles: Vec<UserRole> = DB.delete(constants::DB_ROLES).await?;
+    let mut response = db
+        .query("SELECT * FROM type::table($table) WHERE panel = $panel")
+        .bind(("table", constants::DB_ROLES))
+        .bind(("panel", panel))
+        .await?;
+    let roles: Vec<UserRole> = response.take(0)?;
 
     Ok(roles)
 }
 
-/// Returns the currently set role message. Returns None if no message is set
-pub async fn get_role_message(guildid: Option<GuildId>) -> Result<Option<RoleMessage>, DBIError> {
-    setdb(&guildid).await?;
+/// Returns all the role panels of the server, sorted by name. Can be of length 0.
+pub async fn get_all_role_messages(guildid: Option<GuildId>) -> Result<Vec<RoleMessage>, DBIError> {
+    let db = connection(&guildid).await?;
+
+    let mut panels: Vec<RoleMessage> = db.select(constants::DB_ROLEMSG).await?;
+    panels.sort_by(|a, b| a.name.cmp(&b.name));
 
-    let cur_message: Option<RoleMessage> = DB.select((constants::DB_ROLEMSG, "0")).await?;
+    Ok(panels)
+}
+
+/// Returns the role panel with the given name. Returns None if no such panel exists
+pub async fn get_role_message(
+    panel: &str,
+    guildid: Option<GuildId>,
+) -> Result<Option<RoleMessage>, DBIError> {
+    let db = connection(&guildid).await?;
+
+    let cur_message: Option<RoleMessage> = db.select((constants::DB_ROLEMSG, panel)).await?;
     Ok(cur_message)
 }
 
-/// Sets the current role message for the server. If one already exists, it is overwritten
+/// Sets the text of a role panel. The panel is created if it doesn't exist yet, otherwise the text
+/// is overwritten
 pub async fn set_role_message(
+    panel: &str,
     msg: String,
     user: &User,
     guildid: Option<GuildId>,
 ) -> Result<(), DBIError> {
-    setdb(&guildid).await?;
+    let db = connection(&guildid).await?;
 
-    let cur_message: Option<RoleMessage> = DB.select((constants::DB_ROLEMSG, "0")).await?;
+    let cur_message: Option<RoleMessage> = db.select((constants::DB_ROLEMSG, panel)).await?;
     match cur_message {
         Some(cur_msg) => {
-            let _newmessage: Option<RoleMessage> = DB
-                .update((constants::DB_ROLEMSG, "0"))
+            let _newmessage: Option<RoleMessage> = db
+                .update((constants::DB_ROLEMSG, panel))
                 .content(RoleMessage {
                     messagetext: msg.to_owned(),
                     message_by: user.to_owned(),
-                    ..cur_msg
+                    ..cur_msg.to_owned()
                 })
                 .await?;
             warn!(
-                "In {}, db_interaction::set_role_message: changed role message from \"{}\" to \"{}\"",
+                "In {}, db_interaction::set_role_message: changed text of role panel {} from \"{}\" to \"{}\"",
                 guildid.unwrap().get(),
+                panel,
                 &cur_msg.messagetext,
                 &msg
             );
         }
         None => {
-            let _newmessage: Option<RoleMessage> = DB
-                .create((constants::DB_ROLEMSG, "0"))
+            let _newmessage: Option<RoleMessage> = db
+                .create((constants::DB_ROLEMSG, panel))
                 .content(RoleMessage {
+                    name: panel.to_string(),
                     messagetext: msg.to_owned(),
                     guild_message: None,
                     active: false,
                     message_by: user.to_owned(),
                     posted_by: None,
+                    style: RoleMessageStyle::default(),
                 })
                 .await?;
             warn!(
-                "In {}, db_interactions::set_role_message: created role message \"{}\"",
+                "In {}, db_interactions::set_role_message: created role panel {} with \"{}\"",
                 guildid.unwrap().get(),
+                panel,
                 &msg
             );
         }
@@ -236,24 +1031,26 @@ pub async fn set_role_message(
 }
 
 /// Activate the role message by setting the id of the posted message and setting the active bool
-/// to true
+/// to true. The style is the way members pick roles on the posted message
 ///
 /// This function may only be called when a role message has been previously set!
 pub async fn set_active_role_message(
     role_message: &RoleMessage,
     guild_message: Message,
     state: bool,
+    style: RoleMessageStyle,
     user: &User,
     guildid: Option<GuildId>,
 ) -> Result<(), DBIError> {
-    setdb(&guildid).await?;
+    let db = connection(&guildid).await?;
 
-    let _newmessage: Option<RoleMessage> = DB
-        .update((constants::DB_ROLEMSG, "0"))
+    let _newmessage: Option<RoleMessage> = db
+        .update((constants::DB_ROLEMSG, &role_message.name))
         .content(RoleMessage {
             guild_message: Some(guild_message),
             active: state,
             posted_by: Some(user.to_owned()),
+            style,
             ..role_message.to_owned()
         })
         .await?;
@@ -261,20 +1058,258 @@ pub async fn set_active_role_message(
     Ok(())
 }
 
+/// Removes a role panel together with the roles offered on it. Returns
+/// `DBIError::RolePanelNotFound` if there is no panel with that name
+pub async fn remove_role_message(
+    panel: &str,
+    guildid: Option<GuildId>,
+) -> Result<RoleMessage, DBIError> {
+    let db = connection(&guildid).await?;
+
+    let removed = get_role_message(panel, guildid)
+        .await?
+        .ok_or(DBIError::RolePanelNotFound)?;
+    db.query(
+        "BEGIN TRANSACTION;\
+DELETE type::thing($panels, $name);\
+DELETE type::table($roles) WHERE panel = $name;\
+COMMIT TRANSACTION;",
+    )
+    .bind(("panels", constants::DB_ROLEMSG))
+    .bind(("roles", constants::DB_ROLES))
+    .bind(("name", panel))
+    .await?
+    .check()?;
+    warn!(
+        "In {}, db_interaction::remove_role_message: removed role panel {:?}",
+        dbname(&guildid),
+        &removed
+    );
+
+    Ok(removed)
+}
+
+/// Returns all the role groups of the server, sorted by name. Can be of length 0.
+pub async fn get_all_role_groups(guildid: Option<GuildId>) -> Result<Vec<RoleGroup>, DBIError> {
+    let db = connection(&guildid).await?;
+
+    let mut groups: Vec<RoleGroup> = db.select(constants::DB_ROLEGROUPS).await?;
+    groups.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(groups)
+}
+
+/// Get a role group by its name
+pub async fn get_role_group(name: &str, guildid: Option<GuildId>) -> Result<RoleGroup, DBIError> {
+    let db = connection(&guildid).await?;
+
+    let group: Option<RoleGroup> = db.select((constants::DB_ROLEGROUPS, name)).await?;
+
+    group.ok_or(DBIError::RoleGroupNotFound)
+}
+
+/// Creates a role group or overwrites the settings of an existing one
+pub async fn set_role_group(
+    group: RoleGroup,
+    guildid: Option<GuildId>,
+) -> Result<RoleGroup, DBIError> {
+    let db = connection(&guildid).await?;
+
+    let cur_group: Option<RoleGroup> = db.select((constants::DB_ROLEGROUPS, &group.name)).await?;
+    let new_group: Option<RoleGroup> = match cur_group {
+        Some(_) => {
+            db.update((constants::DB_ROLEGROUPS, &group.name))
+                .content(group)
+                .await?
+        }
+        None => {
+            db.create((constants::DB_ROLEGROUPS, &group.name))
+                .content(group)
+                .await?
+        }
+    };
+    warn!(
+        "In {}, db_interaction::set_role_group: saved RoleGroup {:?}",
+        dbname(&guildid),
+        &new_group
+    );
+
+    new_group.ok_or(DBIError::RoleGroupNotFound)
+}
+
+/// Removes a role group. The roles of the group stay user-assignable, they just no longer belong to
+/// a group
+pub async fn remove_role_group(
+    name: &str,
+    guildid: Option<GuildId>,
+) -> Result<RoleGroup, DBIError> {
+    let db = connection(&guildid).await?;
+
+    let removed = get_role_group(name, guildid).await?;
+    db.query(
+        "BEGIN TRANSACTION;\
+DELETE type::thing($groups, $name);\
+UPDATE type::table($roles) SET role_group = NONE WHERE role_group = $name;\
+COMMIT TRANSACTION;",
+    )
+    .bind(("groups", constants::DB_ROLEGROUPS))
+    .bind(("roles", constants::DB_ROLES))
+    .bind(("name", name))
+    .await?
+    .check()?;
+    warn!(
+        "In {}, db_interaction::remove_role_group: removed RoleGroup {:?}",
+        dbname(&guildid),
+        &removed
+    );
+
+    Ok(removed)
+}
+
+/// Returns the role expiries of the server, the ones that expire first come first
+pub async fn get_role_expiries(guildid: Option<GuildId>) -> Result<Vec<RoleExpiry>, DBIError> {
+    let db = connection(&guildid).await?;
+
+    let mut expiries: Vec<RoleExpiry> = db.select(constants::DB_ROLEEXPIRY).await?;
+    expiries.sort_by_key(|e| e.expires_at);
+
+    Ok(expiries)
+}
+
+/// Saves when a role of a member expires. An earlier expiry of the same role of the member is
+/// replaced
+pub async fn set_role_expiry(
+    expiry: RoleExpiry,
+    guildid: Option<GuildId>,
+) -> Result<RoleExpiry, DBIError> {
+    let db = connection(&guildid).await?;
+
+    let cur_expiry: Option<RoleExpiry> = db
+        .select((constants::DB_ROLEEXPIRY, &expiry.expiry_id))
+        .await?;
+    let new_expiry: Option<RoleExpiry> = match cur_expiry {
+        Some(_) => {
+            db.update((constants::DB_ROLEEXPIRY, &expiry.expiry_id))
+                .content(expiry)
+                .await?
+        }
+        None => {
+            db.create((constants::DB_ROLEEXPIRY, &expiry.expiry_id))
+                .content(expiry)
+                .await?
+        }
+    };
+    warn!(
+        "In {}, db_interaction::set_role_expiry: saved RoleExpiry {:?}",
+        dbname(&guildid),
+        &new_expiry
+    );
+
+    new_expiry.ok_or(DBIError::RoleExpiryNotFound)
+}
+
+/// Removes a role expiry by its id
+pub async fn remove_role_expiry(
+    expiry_id: &str,
+    guildid: Option<GuildId>,
+) -> Result<RoleExpiry, DBIError> {
+    let db = connection(&guildid).await?;
+
+    let removed: Option<RoleExpiry> = db.delete((constants::DB_ROLEEXPIRY, expiry_id)).await?;
+    if removed.is_some() {
+        warn!(
+            "In {}, db_interaction::remove_role_expiry: removed RoleExpiry {:?}",
+            dbname(&guildid),
+            &removed
+        );
+    }
+
+    removed.ok_or(DBIError::RoleExpiryNotFound)
+}
+
+/// Get the join role settings of the server. Returns the defaults if none were saved yet
+pub async fn get_join_role_config(guildid: Option<GuildId>) -> Result<JoinRoleConfig, DBIError> {
+    let db = connection(&guildid).await?;
+
+    let config: Option<JoinRoleConfig> = db.select((constants::DB_JOINROLES, "0")).await?;
+
+    Ok(config.unwrap_or_default())
+}
+
+/// Saves the join role settings of the server, creating the record if none exists
+pub async fn set_join_role_config(
+    config: JoinRoleConfig,
+    guildid: Option<GuildId>,
+) -> Result<JoinRoleConfig, DBIError> {
+    let db = connection(&guildid).await?;
+
+    let cur_config: Option<JoinRoleConfig> = db.select((constants::DB_JOINROLES, "0")).await?;
+    let new_config: Option<JoinRoleConfig> = match cur_config {
+        Some(_) => {
+            db.update((constants::DB_JOINROLES, "0"))
+                .content(config)
+                .await?
+        }
+        None => {
+            db.create((constants::DB_JOINROLES, "0"))
+                .content(config)
+                .await?
+        }
+    };
+    warn!(
+        "In {}, db_interaction::set_join_role_config: changed settings to {:?}",
+        dbname(&guildid),
+        &new_config
+    );
+
+    Ok(new_config.unwrap_or_default())
+}
+
+/// Saves the roles of a member who left. An older snapshot of the member is replaced
+pub async fn set_role_snapshot(
+    snapshot: RoleSnapshot,
+    guildid: Option<GuildId>,
+) -> Result<(), DBIError> {
+    let db = connection(&guildid).await?;
+
+    // Updating a record that doesn't exist yet creates it
+    let _: Option<RoleSnapshot> = db
+        .update((constants::DB_ROLESNAPSHOTS, snapshot.user.id.to_string()))
+        .content(snapshot)
+        .await?;
+
+    Ok(())
+}
+
+/// Removes and returns the roles a member had when they left. Returns None if there is no
+/// snapshot of the member
+pub async fn take_role_snapshot(
+    user_id: UserId,
+    guildid: Option<GuildId>,
+) -> Result<Option<RoleSnapshot>, DBIError> {
+    let db = connection(&guildid).await?;
+
+    let snapshot: Option<RoleSnapshot> = db
+        .delete((constants::DB_ROLESNAPSHOTS, user_id.to_string()))
+        .await?;
+
+    Ok(snapshot)
+}
+
 /// Get all user data
 pub async fn get_all_user_data(guildid: Option<GuildId>) -> Result<Vec<MyUser>, DBIError> {
-    setdb(&guildid).await?;
+    let db = connection(&guildid).await?;
 
-    let user_data: Vec<MyUser> = DB.select(constants::DB_USERS).await?;
+    let user_data: Vec<MyUser> = db.select(constants::DB_USERS).await?;
 
     Ok(user_data)
 }
 
 /// Get the data for a specific user
 pub async fn get_user_data(guildid: Option<GuildId>, user_id: u64) -> Result<MyUser, DBIError> {
-    setdb(&guildid).await?;
+    let db = connection(&guildid).await?;
 
-    let user: Option<MyUser> = DB
+    let user: Option<MyUser> = db
         .select((constants::DB_USERS, user_id.to_string()))
         .await?;
 
@@ -286,28 +1321,28 @@ pub async fn get_user_data(guildid: Option<GuildId>, user_id: u64) -> Result<MyU
 
 /// Get the current point emote record
 pub async fn get_point_data(guildid: Option<GuildId>) -> Result<Option<PointsData>, DBIError> {
-    setdb(&guildid).await?;
+    let db = connection(&guildid).await?;
 
-    let cur_point_emote: Option<PointsData> = DB.select((constants::DB_POINTEMOTE, "0")).await?;
+    let cur_point_emote: Option<PointsData> = db.select((constants::DB_POINTEMOTE, "0")).await?;
 
     Ok(cur_point_emote)
 }
 
 /// Change points for a given user using the given function to apply to points
 ///
-/// This will create a new user if no record exists in DB
+/// This will create a new user if no record exists in db
 pub async fn change_user_points(
     guildid: Option<GuildId>,
     user: User,
     func: fn(u32) -> u32,
 ) -> Result<MyUser, DBIError> {
-    setdb(&guildid).await?;
+    let db = connection(&guildid).await?;
 
-    let cur_user: Option<MyUser> = DB
+    let cur_user: Option<MyUser> = db
         .select((constants::DB_USERS, user.id.to_string()))
         .await?;
 
-    let cur_point_stats: Option<PointsData> = DB.select((constants::DB_POINTEMOTE, "0")).await?;
+    let cur_point_stats: Option<PointsData> = db.select((constants::DB_POINTEMOTE, "0")).await?;
 
     match (cur_user, cur_point_stats) {
         (Some(u), Some(p)) => {
@@ -319,11 +1354,11 @@ pub async fn change_user_points(
                 total: func(p.total),
                 ..p
             };
-            let _: Option<MyUser> = DB
+            let _: Option<MyUser> = db
                 .update((constants::DB_USERS, user.id.to_string()))
                 .content(new_user.to_owned())
                 .await?;
-            let _: Option<PointsData> = DB
+            let _: Option<PointsData> = db
                 .update((constants::DB_POINTEMOTE, "0"))
                 .content(new_points.to_owned())
                 .await?;
@@ -340,11 +1375,11 @@ pub async fn change_user_points(
                 discord_user: user.to_owned(),
                 grammarpoints: func(0),
             };
-            let _: Option<MyUser> = DB
+            let _: Option<MyUser> = db
                 .create((constants::DB_USERS, user.id.to_string()))
                 .content(new_user.to_owned())
                 .await?;
-            let _: Option<PointsData> = DB
+            let _: Option<PointsData> = db
                 .update((constants::DB_POINTEMOTE, "0"))
                 .content(new_points.to_owned())
                 .await?;
@@ -357,48 +1392,76 @@ pub async fn change_user_points(
 
 /// Updates the current point emote or will create the entry if none exists
 pub async fn set_point_emote(
-    point_emote: &Emoji,
+    point_emote: &ReactionEmote,
     user: &User,
     guildid: Option<GuildId>,
 ) -> Result<(), DBIError> {
-    setdb(&guildid).await?;
+    let db = connection(&guildid).await?;
 
-    let cur_points: Option<PointsData> = DB.select((constants::DB_POINTEMOTE, "0")).await?;
+    let cur_points: Option<PointsData> = db.select((constants::DB_POINTEMOTE, "0")).await?;
     match cur_points {
         Some(p) => {
-            let _: Option<PointsData> = DB
+            let _: Option<PointsData> = db
                 .update((constants::DB_POINTEMOTE, "0"))
                 .content(PointsData {
                     guild_emote: point_emote.to_owned(),
                     set_by: user.to_owned(),
                     active: p.active,
                     total: p.total,
+                    emote_deleted: false,
                 })
                 .await?;
             warn!(
                 "In {}, db_interaction::set_point_emote: changed point emote from {} to {}",
                 guildid.unwrap().get(),
-                &p.guild_emote.name,
-                &point_emote.name
+                &p.guild_emote,
+                point_emote
             );
         }
         None => {
-            let _: Option<PointsData> = DB
+            let _: Option<PointsData> = db
                 .create((constants::DB_POINTEMOTE, "0"))
                 .content(PointsData {
                     guild_emote: point_emote.to_owned(),
                     set_by: user.to_owned(),
                     active: false,
                     total: 0,
+                    emote_deleted: false,
                 })
                 .await?;
             warn!(
                 "In {}, db_interactions::set_point_emote: created point emote \"{}\"",
                 guildid.unwrap().get(),
-                &point_emote.name
+                point_emote
             );
         }
     };
 
     Ok(())
 }
+
+/// Marks the point emote as deleted from the server. It stays marked until a new one is set
+pub async fn invalidate_point_emote(guildid: Option<GuildId>) -> Result<(), DBIError> {
+    let db = connection(&guildid).await?;
+
+    let cur_points: Option<PointsData> = db.select((constants::DB_POINTEMOTE, "0")).await?;
+    match cur_points {
+        Some(p) => {
+            let _: Option<PointsData> = db
+                .update((constants::DB_POINTEMOTE, "0"))
+                .content(PointsData {
+                    emote_deleted: true,
+                    ..p.to_owned()
+                })
+                .await?;
+            warn!(
+                "In {}, db_interaction::invalidate_point_emote: point emote {} was deleted",
+                dbname(&guildid),
+                &p.guild_emote
+            );
+
+            Ok(())
+        }
+        None => Err(DBIError::PointDataNotFound),
+    }
+}
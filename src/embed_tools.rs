@@ -1,16 +1,87 @@
 use std::time::Duration;
 
+use crate::constants;
 use crate::types::*;
 use poise::serenity_prelude as serenity;
 
+/// Build the navigation components for the paginated message. If sections are given, a select
+/// menu to jump to the first page of each section is added below the buttons.
+fn pagination_components(
+    ctx_id: u64,
+    page: usize,
+    last_page: usize,
+    sections: &Option<Vec<(String, usize)>>,
+) -> Vec<serenity::CreateActionRow> {
+    let mut components = vec![serenity::CreateActionRow::Buttons(vec![
+        serenity::CreateButton::new(format!("{}prev", ctx_id))
+            .style(serenity::ButtonStyle::Primary)
+            .emoji('◀')
+            .disabled(page == 0),
+        serenity::CreateButton::new(format!("{}next", ctx_id))
+            .style(serenity::ButtonStyle::Primary)
+            .emoji('▶')
+            .disabled(page == last_page),
+    ])];
+
+    if let Some(sections) = sections {
+        // A select menu can hold at most 25 options. With more sections, the menu shows the ones
+        // around the current page and entries to get to the earlier and later ones
+        let current = sections.iter().rposition(|(_, s)| *s <= page).unwrap_or(0);
+        let (first, last) = match sections.len() <= 25 {
+            true => (0, sections.len()),
+            false => {
+                let first = current / 23 * 23;
+                (first, (first + 23).min(sections.len()))
+            }
+        };
+        let mut options: Vec<serenity::CreateSelectMenuOption> = Vec::with_capacity(25);
+        if first > 0 {
+            options.push(serenity::CreateSelectMenuOption::new(
+                "◀ Earlier sections",
+                sections[first - 1].1.to_string(),
+            ));
+        }
+        options.extend(sections[first..last].iter().map(|(label, start)| {
+            serenity::CreateSelectMenuOption::new(label, start.to_string())
+                .default_selection(*start <= page && section_end(sections, *start) > page)
+        }));
+        if last < sections.len() {
+            options.push(serenity::CreateSelectMenuOption::new(
+                "Later sections ▶",
+                sections[last].1.to_string(),
+            ));
+        }
+        components.push(serenity::CreateActionRow::SelectMenu(
+            serenity::CreateSelectMenu::new(
+                format!("{}section", ctx_id),
+                serenity::CreateSelectMenuKind::String { options },
+            )
+            .placeholder("Jump to section"),
+        ));
+    }
+
+    components
+}
+
+/// Returns the first page after the section starting at `start`
+fn section_end(sections: &[(String, usize)], start: usize) -> usize {
+    sections
+        .iter()
+        .map(|(_, s)| *s)
+        .find(|s| *s > start)
+        .unwrap_or(usize::MAX)
+}
+
 async fn paginage_generic(
     ctx: Context<'_>,
     texts_embeds: (Option<Vec<String>>, Option<Vec<serenity::CreateEmbed>>),
+    sections: Option<Vec<(String, usize)>>,
 ) -> Result<(), Error> {
-    // Define some unique identifiers for the navigation buttons
+    // Define some unique identifiers for the navigation components
     let ctx_id = ctx.id();
     let prev_button_id = format!("{}prev", ctx_id);
     let next_button_id = format!("{}next", ctx_id);
+    let section_menu_id = format!("{}section", ctx_id);
 
     let mut page = 0;
     let last_page = match &texts_embeds {
@@ -19,67 +90,52 @@ async fn paginage_generic(
         _ => 0,
     };
 
-    let components = serenity::CreateActionRow::Buttons(vec![
-        serenity::CreateButton::new(prev_button_id.clone())
-            .style(serenity::ButtonStyle::Primary)
-            .emoji('◀')
-            .disabled(true),
-        serenity::CreateButton::new(next_button_id.clone())
-            .style(serenity::ButtonStyle::Primary)
-            .emoji('▶')
-            .disabled(page == last_page),
-    ]);
+    let components = pagination_components(ctx_id, page, last_page, &sections);
     let builder = match &texts_embeds {
         (Some(texts), None) => poise::CreateReply::default()
             .content(texts[page].to_owned())
-            .components(vec![components]),
+            .components(components),
         (None, Some(embeds)) => poise::CreateReply::default()
             .embed(embeds[page].to_owned())
-            .components(vec![components]),
+            .components(components),
         _ => poise::CreateReply::default(),
     };
 
     let reply = ctx.send(builder).await?;
 
-    let mut disable_prev;
-    let mut disable_next;
-
     while let Some(interaction) = reply
         .message()
         .await?
         .await_component_interaction(ctx)
         .author_id(ctx.author().id)
-        .custom_ids(vec![prev_button_id.to_owned(), next_button_id.to_owned()])
+        .custom_ids(vec![
+            prev_button_id.to_owned(),
+            next_button_id.to_owned(),
+            section_menu_id.to_owned(),
+        ])
         .timeout(Duration::from_secs(300))
         .next()
         .await
     {
         if interaction.data.custom_id == prev_button_id {
-            page -= 1;
-            disable_prev = page == 0;
-            disable_next = false;
-        } else {
-            page += 1;
-            disable_prev = false;
-            disable_next = page == last_page;
+            page = page.saturating_sub(1);
+        } else if interaction.data.custom_id == next_button_id {
+            page = (page + 1).min(last_page);
+        } else if let serenity::ComponentInteractionDataKind::StringSelect { values } =
+            &interaction.data.kind
+        {
+            if let Some(start) = values.first().and_then(|v| v.parse::<usize>().ok()) {
+                page = start.min(last_page);
+            }
         }
-        let edit_components = serenity::CreateActionRow::Buttons(vec![
-            serenity::CreateButton::new(prev_button_id.clone())
-                .style(serenity::ButtonStyle::Primary)
-                .emoji('◀')
-                .disabled(disable_prev),
-            serenity::CreateButton::new(next_button_id.clone())
-                .style(serenity::ButtonStyle::Primary)
-                .emoji('▶')
-                .disabled(disable_next),
-        ]);
+        let edit_components = pagination_components(ctx_id, page, last_page, &sections);
 
         let interaction_response_message = match &texts_embeds {
             (Some(texts), None) => serenity::CreateInteractionResponseMessage::new()
-                .components(vec![edit_components])
+                .components(edit_components)
                 .content(texts[page].clone()),
             (None, Some(embeds)) => serenity::CreateInteractionResponseMessage::new()
-                .components(vec![edit_components])
+                .components(edit_components)
                 .embed(embeds[page].clone()),
             _ => serenity::CreateInteractionResponseMessage::new(),
         };
@@ -101,14 +157,186 @@ pub async fn paginate_with_embeds(
     embeds: Vec<serenity::CreateEmbed>,
 ) -> Result<(), Error> {
     // Define some unique identifiers for the navigation buttons
-    paginage_generic(ctx, (None, Some(embeds))).await?;
+    paginage_generic(ctx, (None, Some(embeds)), None).await?;
+
+    Ok(())
+}
+
+/// Paginate embeds that are grouped into named sections. Next to the page buttons, a select menu
+/// allows jumping straight to the first page of a section.
+pub async fn paginate_with_embed_sections(
+    ctx: Context<'_>,
+    sections: Vec<(String, Vec<serenity::CreateEmbed>)>,
+) -> Result<(), Error> {
+    let mut section_starts: Vec<(String, usize)> = Vec::with_capacity(sections.len());
+    let mut embeds: Vec<serenity::CreateEmbed> = Vec::new();
+    for (label, section_embeds) in sections.into_iter() {
+        section_starts.push((label, embeds.len()));
+        embeds.extend(section_embeds);
+    }
+
+    paginage_generic(ctx, (None, Some(embeds)), Some(section_starts)).await?;
 
     Ok(())
 }
 
 #[allow(dead_code)]
 pub async fn paginate_with_text(ctx: Context<'_>, texts: Vec<String>) -> Result<(), Error> {
-    paginage_generic(ctx, (Some(texts), None)).await?;
+    paginage_generic(ctx, (Some(texts), None), None).await?;
+
+    Ok(())
+}
+
+/// Parse an embed definition written either as JSON or as TOML
+///
+/// Surrounding code fences (```json ... ```) are stripped, so definitions can be pasted straight
+/// from a Discord message. The parsed embed is validated against the Discord embed limits.
+pub fn parse_tag_embed(definition: &str) -> Result<TagEmbed, TagError> {
+    let definition = definition.trim();
+    let definition = definition
+        .strip_prefix("```")
+        .and_then(|d| d.strip_suffix("```"))
+        .map(|d| {
+            d.trim_start_matches("json")
+                .trim_start_matches("toml")
+                .trim()
+        })
+        .unwrap_or(definition);
+
+    let embed: TagEmbed = if definition.starts_with('{') {
+        serde_json::from_str(definition).map_err(|e| TagError::InvalidEmbed(e.to_string()))?
+    } else {
+        toml::from_str(definition).map_err(|e| TagError::InvalidEmbed(e.to_string()))?
+    };
+
+    validate_tag_embed(&embed)?;
+
+    Ok(embed)
+}
+
+/// Check a tag embed against the limits Discord puts on embeds
+pub fn validate_tag_embed(embed: &TagEmbed) -> Result<(), TagError> {
+    let too_long = |what: &str, text: &Option<String>, limit: usize| match text {
+        Some(t) if t.chars().count() > limit => Err(TagError::InvalidEmbed(format!(
+            "the {} can be at most {} characters long",
+            what, limit
+        ))),
+        _ => Ok(()),
+    };
+
+    too_long("title", &embed.title, constants::EMBED_TITLE_LIMIT)?;
+    too_long(
+        "description",
+        &embed.description,
+        constants::EMBED_DESCRIPTION_LIMIT,
+    )?;
+    too_long("footer", &embed.footer, constants::EMBED_FOOTER_LIMIT)?;
+
+    if embed.fields.len() > constants::EMBED_FIELD_COUNT_LIMIT {
+        return Err(TagError::InvalidEmbed(format!(
+            "an embed can have at most {} fields",
+            constants::EMBED_FIELD_COUNT_LIMIT
+        )));
+    }
+    for field in embed.fields.iter() {
+        if field.name.is_empty() || field.value.is_empty() {
+            return Err(TagError::InvalidEmbed(String::from(
+                "field names and values can't be empty",
+            )));
+        }
+        too_long(
+            "field name",
+            &Some(field.name.to_owned()),
+            constants::EMBED_FIELD_NAME_LIMIT,
+        )?;
+        too_long(
+            "field value",
+            &Some(field.value.to_owned()),
+            constants::EMBED_FIELD_VALUE_LIMIT,
+        )?;
+    }
+
+    let total: usize = [&embed.title, &embed.description, &embed.footer]
+        .iter()
+        .map(|t| t.as_ref().map_or(0, |t| t.chars().count()))
+        .sum::<usize>()
+        + embed
+            .fields
+            .iter()
+            .map(|f| f.name.chars().count() + f.value.chars().count())
+            .sum::<usize>();
+    if total > constants::EMBED_TOTAL_LIMIT {
+        return Err(TagError::InvalidEmbed(format!(
+            "all texts of an embed combined can be at most {} characters long",
+            constants::EMBED_TOTAL_LIMIT
+        )));
+    }
+
+    if let Some(colour) = &embed.colour {
+        parse_colour(colour)?;
+    }
+
+    if let Some(url) = &embed.image_url {
+        if !(url.starts_with("https://") || url.starts_with("http://")) {
+            return Err(TagError::InvalidEmbed(String::from(
+                "the image url has to start with http:// or https://",
+            )));
+        }
+    }
+
+    if embed.title.is_none()
+        && embed.description.is_none()
+        && embed.fields.is_empty()
+        && embed.image_url.is_none()
+    {
+        return Err(TagError::InvalidEmbed(String::from(
+            "an embed needs at least a title, a description, a field or an image",
+        )));
+    }
 
     Ok(())
 }
+
+fn parse_colour(colour: &str) -> Result<serenity::Colour, TagError> {
+    u32::from_str_radix(colour.trim_start_matches('#'), 16)
+        .ok()
+        .filter(|c| *c <= 0xFFFFFF)
+        .map(serenity::Colour::new)
+        .ok_or(TagError::InvalidEmbed(format!(
+            "{} is not a valid colour, use the form #RRGGBB",
+            colour
+        )))
+}
+
+/// Build the serenity embed for a stored tag embed
+pub fn build_tag_embed(embed: &TagEmbed) -> serenity::CreateEmbed {
+    let mut created = serenity::CreateEmbed::default()
+        .colour(
+            embed
+                .colour
+                .as_ref()
+                .and_then(|c| parse_colour(c).ok())
+                .unwrap_or(serenity::Colour::BLUE),
+        )
+        .fields(
+            embed
+                .fields
+                .iter()
+                .map(|f| (f.name.to_owned(), f.value.to_owned(), f.inline)),
+        );
+
+    if let Some(title) = &embed.title {
+        created = created.title(title);
+    }
+    if let Some(description) = &embed.description {
+        created = created.description(description);
+    }
+    if let Some(footer) = &embed.footer {
+        created = created.footer(serenity::CreateEmbedFooter::new(footer));
+    }
+    if let Some(url) = &embed.image_url {
+        created = created.image(url);
+    }
+
+    created
+}
@@ -1,21 +1,428 @@
+use crate::commands_util::post_to_mod_log;
+use crate::constants;
 use crate::dbi;
+use crate::output;
+use crate::responder_commands::find_responder;
+use crate::role_commands::{
+    check_requirements, check_role_groups, exclusive_conflicts, guild_role_name,
+    purge_deleted_roles, remove_stale_reactions, start_role_timer, stop_role_timer,
+};
 use crate::serenity::Context;
+use crate::tag_commands::{
+    reader_languages, suggestion_components, suggestion_embed, SuggestionModal,
+};
 use crate::types::*;
 use log::{error, warn};
+use once_cell::sync::Lazy;
 use poise::serenity_prelude::{self as serenity, CacheHttp};
+use poise::Modal;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// End of a running cooldown, per channel and name
+type Cooldowns = Mutex<HashMap<(serenity::ChannelId, String), Instant>>;
+
+/// Cooldowns of inline tag triggers, keyed by tag name
+static TAG_TRIGGER_COOLDOWNS: Lazy<Cooldowns> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Cooldowns of auto-responders, keyed by responder name
+static RESPONDER_COOLDOWNS: Lazy<Cooldowns> = Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Checks if the cooldown for the key is over
+fn cooldown_over(cooldowns: &Cooldowns, key: &(serenity::ChannelId, String)) -> bool {
+    cooldowns
+        .lock()
+        .unwrap()
+        .get(key)
+        .is_none_or(|until| *until <= Instant::now())
+}
+
+/// Starts the cooldown for the key. Cooldowns that are over are dropped along the way, so the map
+/// only holds the running ones
+fn start_cooldown(cooldowns: &Cooldowns, key: (serenity::ChannelId, String), seconds: u64) {
+    let now = Instant::now();
+    let mut cooldowns = cooldowns.lock().unwrap();
+    cooldowns.retain(|_, until| *until > now);
+    cooldowns.insert(key, now + Duration::from_secs(seconds));
+}
 
 pub async fn my_event_handler(ctx: &Context, event: &serenity::FullEvent) -> Result<(), Error> {
     // println!("Got event: {}", event.name().unwrap());
     match event {
-        // Event::Message { new_message } => handle_message(ctx, new_message).await?,
+        serenity::FullEvent::Message { new_message } => handle_message(ctx, new_message).await?,
         serenity::FullEvent::ReactionAdd { add_reaction } => {
-            handle_add_reaction(ctx, &add_reaction).await?
+            handle_add_reaction(ctx, add_reaction).await?
         }
         serenity::FullEvent::ReactionRemove { removed_reaction } => {
-            handle_remove_reaction(ctx, &removed_reaction).await?
+            handle_remove_reaction(ctx, removed_reaction).await?
         }
         serenity::FullEvent::InteractionCreate { interaction } => {
-            todo!()
+            handle_interaction(ctx, interaction).await?
+        }
+        serenity::FullEvent::GuildMemberAddition { new_member } => {
+            handle_member_join(ctx, new_member).await?
+        }
+        serenity::FullEvent::GuildMemberRemoval {
+            guild_id,
+            user,
+            member_data_if_available,
+        } => handle_member_leave(*guild_id, user, member_data_if_available.as_ref()).await?,
+        serenity::FullEvent::GuildRoleDelete { guild_id, .. } => {
+            handle_deleted_roles(ctx, *guild_id).await?
+        }
+        serenity::FullEvent::GuildEmojisUpdate {
+            guild_id,
+            current_state,
+        } => handle_emojis_update(ctx, *guild_id, current_state).await?,
+        _ => {}
+    };
+
+    Ok(())
+}
+
+async fn handle_message(ctx: &Context, message: &serenity::Message) -> Result<(), Error> {
+    if message.author.bot || message.guild_id.is_none() {
+        return Ok(());
+    }
+
+    // A message that already got a tag as reply doesn't need an automatic reply on top
+    if !handle_inline_tag(ctx, message).await? {
+        handle_auto_responder(ctx, message).await?;
+    }
+
+    Ok(())
+}
+
+/// Finds the words in the message that start with the sigil and returns the rest of them as tag
+/// names, in the order they appear. Punctuation at the end of a word is ignored.
+fn find_inline_triggers<'a>(content: &'a str, sigil: &'a str) -> impl Iterator<Item = &'a str> {
+    content
+        .split_whitespace()
+        .filter_map(move |word| word.strip_prefix(sigil))
+        .map(|name| name.trim_end_matches(|c: char| c.is_ascii_punctuation()))
+        .filter(|name| !name.is_empty())
+}
+
+/// Posts the tag that is mentioned inline in the message. Returns whether a tag was posted
+async fn handle_inline_tag(ctx: &Context, message: &serenity::Message) -> Result<bool, Error> {
+    let config = dbi::get_tag_trigger_config(message.guild_id).await?;
+    let sigil = match &config.sigil {
+        Some(s) => s,
+        None => return Ok(false),
+    };
+    if config.denied_channels.contains(&message.channel_id)
+        || (!config.allowed_channels.is_empty()
+            && !config.allowed_channels.contains(&message.channel_id))
+    {
+        return Ok(false);
+    }
+
+    // The first word with the sigil might not be a tag, so the later ones are tried as well
+    let mut found = None;
+    for tagname in
+        find_inline_triggers(&message.content, sigil).take(constants::TAG_TRIGGER_CANDIDATE_LIMIT)
+    {
+        if let Ok(f) = dbi::find_tag(tagname, message.guild_id).await {
+            found = Some(f);
+            break;
+        }
+    }
+    let (tag, source) = match found {
+        Some(f) => f,
+        None => return Ok(false),
+    };
+    let roles = match &message.member {
+        Some(m) => m.roles.to_owned(),
+        None => vec![],
+    };
+    if !tag.access.allows_public(&roles, message.channel_id) {
+        return Ok(false);
+    }
+
+    let key = (message.channel_id, tag.name.to_owned());
+    if !cooldown_over(&TAG_TRIGGER_COOLDOWNS, &key) {
+        return Ok(false);
+    }
+    start_cooldown(&TAG_TRIGGER_COOLDOWNS, key, config.cooldown);
+
+    let tag = tag.localized(&reader_languages(message.author.id, None, None).await);
+    let reply = output::tag_message(&tag, source, message, false, message.guild_id).await?;
+    message.channel_id.send_message(ctx, reply).await?;
+    dbi::increment_tag_uses(&tag.name, source.db_guild_id(message.guild_id)).await?;
+    warn!(
+        "In {}, events::handle_inline_tag: {} triggered tag {} inline.",
+        message.guild_id.unwrap().get(),
+        message.author.name,
+        tag.name
+    );
+
+    Ok(true)
+}
+
+/// Replies to the message with the first auto-responder that matches it
+async fn handle_auto_responder(ctx: &Context, message: &serenity::Message) -> Result<(), Error> {
+    let responders = dbi::get_all_responders(message.guild_id).await?;
+    // Responders on cooldown are passed over, so a later matching one can still reply
+    let responder = match find_responder(
+        &responders,
+        &message.content,
+        message.channel_id,
+        message.guild_id,
+        |r| {
+            cooldown_over(
+                &RESPONDER_COOLDOWNS,
+                &(message.channel_id, r.name.to_owned()),
+            )
+        },
+    ) {
+        Some(r) => r,
+        None => return Ok(()),
+    };
+    start_cooldown(
+        &RESPONDER_COOLDOWNS,
+        (message.channel_id, responder.name.to_owned()),
+        responder.cooldown,
+    );
+
+    let reply = match &responder.response {
+        ResponderReply::Text(text) => output::user_text_message(text, message.guild_id)
+            .await?
+            .reference_message(message),
+        ResponderReply::Tag(tagname) => {
+            let (tag, source) = match dbi::find_tag(tagname, message.guild_id).await {
+                Ok(found) => found,
+                Err(e) => {
+                    error!(
+                        "In {}, events::handle_auto_responder: tag {} of responder {}: {}",
+                        message.guild_id.unwrap().get(),
+                        tagname,
+                        responder.name,
+                        e
+                    );
+                    return Ok(());
+                }
+            };
+            let roles = match &message.member {
+                Some(m) => m.roles.to_owned(),
+                None => vec![],
+            };
+            if !tag.access.allows_public(&roles, message.channel_id) {
+                return Ok(());
+            }
+            let tag = tag.localized(&reader_languages(message.author.id, None, None).await);
+            dbi::increment_tag_uses(&tag.name, source.db_guild_id(message.guild_id)).await?;
+            output::tag_message(&tag, source, message, false, message.guild_id).await?
+        }
+    };
+    message.channel_id.send_message(ctx, reply).await?;
+    warn!(
+        "In {}, events::handle_auto_responder: responder {} replied to {}.",
+        message.guild_id.unwrap().get(),
+        responder.name,
+        message.author.name
+    );
+
+    Ok(())
+}
+
+/// Handles interactions that are not tied to a running command, like the buttons on messages the
+/// bot posted earlier. Slash commands and collectors are taken care of by poise
+async fn handle_interaction(
+    ctx: &Context,
+    interaction: &serenity::Interaction,
+) -> Result<(), Error> {
+    let suggestion_prefix = format!("{}:", constants::TAG_SUGGESTION_ID);
+    let role_prefix = format!("{}:", constants::ROLE_MESSAGE_ID);
+    match interaction {
+        serenity::Interaction::Component(component) => {
+            if let Some((action, id)) = component
+                .data
+                .custom_id
+                .strip_prefix(&suggestion_prefix)
+                .and_then(|rest| rest.split_once(':'))
+            {
+                handle_tag_suggestion_button(ctx, component, action, id).await?;
+            } else if let Some((action, id)) = component
+                .data
+                .custom_id
+                .strip_prefix(&role_prefix)
+                .and_then(|rest| rest.split_once(':'))
+            {
+                handle_role_component(ctx, component, action, id).await?;
+            }
+        }
+        serenity::Interaction::Modal(modal) => {
+            if let Some((_, id)) = modal
+                .data
+                .custom_id
+                .strip_prefix(&suggestion_prefix)
+                .and_then(|rest| rest.split_once(':'))
+            {
+                handle_tag_suggestion_edit(ctx, modal, id).await?;
+            }
+        }
+        _ => {}
+    };
+
+    Ok(())
+}
+
+fn ephemeral_response(text: impl Into<String>) -> serenity::CreateInteractionResponse {
+    serenity::CreateInteractionResponse::Message(
+        serenity::CreateInteractionResponseMessage::new()
+            .content(text)
+            .ephemeral(true),
+    )
+}
+
+async fn handle_tag_suggestion_button(
+    ctx: &Context,
+    component: &serenity::ComponentInteraction,
+    action: &str,
+    suggestion_id: &str,
+) -> Result<(), Error> {
+    let is_moderator = component
+        .member
+        .as_ref()
+        .and_then(|m| m.permissions)
+        .is_some_and(|p| p.manage_messages());
+    if !is_moderator {
+        component
+            .create_response(
+                ctx,
+                ephemeral_response("Only moderators can review tag suggestions."),
+            )
+            .await?;
+        return Ok(());
+    }
+
+    let suggestion = match dbi::get_tag_suggestion(suggestion_id, component.guild_id).await {
+        Ok(s) => s,
+        Err(e) => {
+            component
+                .create_response(ctx, ephemeral_response(format!("{}", e)))
+                .await?;
+            return Ok(());
+        }
+    };
+    let reviewer = &component.user;
+
+    match action {
+        "approve" => {
+            let tag = Tag {
+                name: suggestion.name.to_owned(),
+                content: suggestion.content.to_owned(),
+                creator: suggestion.suggester.to_owned(),
+                embed: None,
+                category: suggestion.category.to_owned(),
+                aliases: vec![],
+                created_at: Some(serenity::Timestamp::now()),
+                last_editor: None,
+                edited_at: None,
+                uses: 0,
+                variants: BTreeMap::new(),
+                access: TagAccess::default(),
+                attachments: vec![],
+            };
+            if let Err(e) = dbi::create_tag(tag, component.guild_id).await {
+                component
+                    .create_response(
+                        ctx,
+                        ephemeral_response(format!(
+                            "{}. Edit the suggestion to pick another name.",
+                            e
+                        )),
+                    )
+                    .await?;
+                return Ok(());
+            }
+            dbi::remove_tag_suggestion(suggestion_id, component.guild_id).await?;
+            component
+                .create_response(
+                    ctx,
+                    serenity::CreateInteractionResponse::UpdateMessage(
+                        serenity::CreateInteractionResponseMessage::new()
+                            .embed(
+                                suggestion_embed(&suggestion)
+                                    .field("Approved by", reviewer.to_string(), false)
+                                    .colour(serenity::Colour::DARK_GREEN),
+                            )
+                            .components(suggestion_components(suggestion_id, true)),
+                    ),
+                )
+                .await?;
+            warn!(
+                "In {}, events::handle_tag_suggestion_button: {} approved Tag {} suggested by {}.",
+                component.guild_id.unwrap().get(),
+                reviewer.name,
+                suggestion.name,
+                suggestion.suggester.name
+            );
+            let _ = suggestion
+                .suggester
+                .direct_message(
+                    &ctx,
+                    serenity::CreateMessage::new().content(format!(
+                        "Your tag suggestion {} was approved.",
+                        suggestion.name
+                    )),
+                )
+                .await;
+        }
+        "reject" => {
+            dbi::remove_tag_suggestion(suggestion_id, component.guild_id).await?;
+            component
+                .create_response(
+                    ctx,
+                    serenity::CreateInteractionResponse::UpdateMessage(
+                        serenity::CreateInteractionResponseMessage::new()
+                            .embed(
+                                suggestion_embed(&suggestion)
+                                    .field("Rejected by", reviewer.to_string(), false)
+                                    .colour(serenity::Colour::RED),
+                            )
+                            .components(suggestion_components(suggestion_id, true)),
+                    ),
+                )
+                .await?;
+            warn!(
+                "In {}, events::handle_tag_suggestion_button: {} rejected Tag {} suggested by {}.",
+                component.guild_id.unwrap().get(),
+                reviewer.name,
+                suggestion.name,
+                suggestion.suggester.name
+            );
+            let _ = suggestion
+                .suggester
+                .direct_message(
+                    &ctx,
+                    serenity::CreateMessage::new().content(format!(
+                        "Your tag suggestion {} was rejected.",
+                        suggestion.name
+                    )),
+                )
+                .await;
+        }
+        "edit" => {
+            let defaults = SuggestionModal {
+                name: suggestion.name,
+                content: suggestion.content,
+                category: suggestion.category,
+            };
+            component
+                .create_response(
+                    ctx,
+                    SuggestionModal::create(
+                        Some(defaults),
+                        format!(
+                            "{}:editsubmit:{}",
+                            constants::TAG_SUGGESTION_ID,
+                            suggestion_id
+                        ),
+                    ),
+                )
+                .await?;
         }
         _ => {}
     };
@@ -23,43 +430,220 @@ pub async fn my_event_handler(ctx: &Context, event: &serenity::FullEvent) -> Res
     Ok(())
 }
 
+async fn handle_tag_suggestion_edit(
+    ctx: &Context,
+    modal: &serenity::ModalInteraction,
+    suggestion_id: &str,
+) -> Result<(), Error> {
+    let data = match SuggestionModal::parse(modal.data.clone())
+        .map_err(|e| e.to_string())
+        .and_then(|d| d.validated().map_err(|e| e.to_string()))
+    {
+        Ok(d) => d,
+        Err(e) => {
+            modal.create_response(ctx, ephemeral_response(e)).await?;
+            return Ok(());
+        }
+    };
+
+    let suggestion = match dbi::get_tag_suggestion(suggestion_id, modal.guild_id).await {
+        Ok(s) => s,
+        Err(e) => {
+            modal
+                .create_response(ctx, ephemeral_response(format!("{}", e)))
+                .await?;
+            return Ok(());
+        }
+    };
+    let suggestion = dbi::update_tag_suggestion(
+        TagSuggestion {
+            name: data.name,
+            content: data.content,
+            category: data.category,
+            ..suggestion
+        },
+        modal.guild_id,
+    )
+    .await?;
+
+    modal
+        .create_response(
+            ctx,
+            serenity::CreateInteractionResponse::UpdateMessage(
+                serenity::CreateInteractionResponseMessage::new()
+                    .embed(suggestion_embed(&suggestion).field(
+                        "Edited by",
+                        modal.user.to_string(),
+                        false,
+                    ))
+                    .components(suggestion_components(suggestion_id, false)),
+            ),
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Returns the active role panel that was posted as the given message, if there is one
+async fn find_role_panel(
+    guildid: Option<serenity::GuildId>,
+    message_id: serenity::MessageId,
+) -> Result<Option<RoleMessage>, Error> {
+    Ok(dbi::get_all_role_messages(guildid)
+        .await?
+        .into_iter()
+        .find(|p| {
+            p.active
+                && p.guild_message
+                    .as_ref()
+                    .is_some_and(|gm| gm.id == message_id)
+        }))
+}
+
+/// Handles the buttons and the select menu of a posted role panel. A button toggles its role,
+/// the select menu sets exactly the selected roles out of the ones on the panel
+async fn handle_role_component(
+    ctx: &Context,
+    component: &serenity::ComponentInteraction,
+    action: &str,
+    id: &str,
+) -> Result<(), Error> {
+    let panel = find_role_panel(component.guild_id, component.message.id).await?;
+    let (member, panel) = match (&component.member, panel) {
+        (Some(m), Some(p)) => (m, p),
+        _ => {
+            component
+                .create_response(
+                    ctx,
+                    ephemeral_response("This role message is no longer active."),
+                )
+                .await?;
+            return Ok(());
+        }
+    };
+
+    // Only roles that are still user-assignable are handed out, the message may be outdated
+    let all_roles = dbi::get_all_roles(component.guild_id).await?;
+    let groups = dbi::get_all_role_groups(component.guild_id).await?;
+    let cur_roles: Vec<&UserRole> = all_roles.iter().filter(|r| r.panel == panel.name).collect();
+    let (to_add, mut to_remove): (Vec<&UserRole>, Vec<&UserRole>) =
+        match (action, &component.data.kind) {
+            ("button", _) => match cur_roles
+                .iter()
+                .copied()
+                .find(|r| r.guild_role.id.to_string() == id)
+            {
+                Some(ur) if member.roles.contains(&ur.guild_role.id) => (vec![], vec![ur]),
+                Some(ur) => (vec![ur], vec![]),
+                None => (vec![], vec![]),
+            },
+            ("select", serenity::ComponentInteractionDataKind::StringSelect { values }) => {
+                cur_roles
+                    .iter()
+                    .copied()
+                    .filter(|r| {
+                        values.contains(&r.guild_role.id.to_string())
+                            != member.roles.contains(&r.guild_role.id)
+                    })
+                    .partition(|r| values.contains(&r.guild_role.id.to_string()))
+            }
+            _ => (vec![], vec![]),
+        };
+
+    if to_add.is_empty() && to_remove.is_empty() {
+        component
+            .create_response(ctx, ephemeral_response("Your roles are unchanged."))
+            .await?;
+        return Ok(());
+    }
+
+    // A button picking a role of an exclusive group replaces the other role of the group. The
+    // select menu sets the roles as selected, so picking two roles of such a group is refused
+    if action == "button" {
+        for ur in to_add.iter() {
+            to_remove.extend(exclusive_conflicts(ur, &member.roles, &all_roles, &groups));
+        }
+    }
+    let after: Vec<serenity::RoleId> = member
+        .roles
+        .iter()
+        .filter(|id| !to_remove.iter().any(|r| r.guild_role.id == **id))
+        .copied()
+        .chain(to_add.iter().map(|r| r.guild_role.id))
+        .collect();
+    let allowed = to_add
+        .iter()
+        .try_for_each(|ur| {
+            check_requirements(ur, &member.roles, |id| {
+                guild_role_name(ctx, component.guild_id, id)
+            })
+        })
+        .and_then(|_| check_role_groups(&member.roles, &after, &all_roles, &groups));
+    if let Err(reason) = allowed {
+        component
+            .create_response(ctx, ephemeral_response(reason))
+            .await?;
+        return Ok(());
+    }
+
+    for ur in to_add.iter() {
+        member.add_role(&ctx.http, ur.guild_role.id).await?;
+        start_role_timer(&member.user, ur, component.guild_id).await?;
+    }
+    for ur in to_remove.iter() {
+        member.remove_role(&ctx.http, ur.guild_role.id).await?;
+        stop_role_timer(member.user.id, ur.guild_role.id, component.guild_id).await?;
+    }
+    remove_stale_reactions(ctx, component.guild_id, member.user.id, &to_remove).await?;
+    warn!(
+        "In {}, events::handle_role_component: Member {} picked roles on panel {}, added: [{}], removed: [{}].",
+        component.guild_id.unwrap().get(),
+        member.display_name(),
+        panel.name,
+        role_names(&to_add),
+        role_names(&to_remove)
+    );
+
+    let mut lines: Vec<String> = Vec::new();
+    if !to_add.is_empty() {
+        lines.push(format!("Added: {}", role_names(&to_add)));
+    }
+    if !to_remove.is_empty() {
+        lines.push(format!("Removed: {}", role_names(&to_remove)));
+    }
+    component
+        .create_response(ctx, ephemeral_response(lines.join("\n")))
+        .await?;
+
+    Ok(())
+}
+
 async fn handle_add_reaction(ctx: &Context, reaction: &serenity::Reaction) -> Result<(), Error> {
     if reaction.user_id.unwrap() != ctx.cache.current_user().id {
-        let cur_role_msg = dbi::get_role_message(reaction.guild_id).await?;
+        // we only handle role reactions if the message is one of the posted role panels
+        let cur_role_panel = find_role_panel(reaction.guild_id, reaction.message_id).await?;
         let cur_point_emote = dbi::get_point_data(reaction.guild_id).await?;
-        // we only handle role reactions if the messge exists in the first place
-
-        match (reaction, cur_role_msg, cur_point_emote) {
-            // this is if it's a reaction to the role message
-            // we check if the message reacted to is the same id as the guild message saved in the
-            // db
-            // we also make sure that we have a role message in the db and that a guild message was
-            // posted
+
+        match (reaction, cur_role_panel, cur_point_emote) {
+            // this is if it's a reaction to a role panel that uses reactions
             (
-                rct,
+                _,
                 Some(RoleMessage {
-                    guild_message: Some(gm),
+                    name,
+                    style: RoleMessageStyle::Reactions,
                     ..
                 }),
                 _,
-            ) if rct.message_id == gm.id => {
-                let cur_roles = dbi::get_all_roles(reaction.guild_id).await?;
+            ) => {
+                let cur_roles = dbi::get_panel_roles(&name, reaction.guild_id).await?;
                 handle_add_role(ctx, reaction, cur_roles).await?;
             }
 
             // this is if the reaction is a point emote
-            // we check if the id of the emote in the reaction matches the id of the emote saved to
-            // the db
-            (
-                serenity::Reaction {
-                    emoji: serenity::ReactionType::Custom { id, .. },
-                    ..
-                },
-                _,
-                Some(cpe),
-            ) if cpe.guild_emote.id.get() == id.get() => {
+            // we check if the emote in the reaction matches the emote saved to the db
+            (rct, _, Some(cpe)) if !cpe.emote_deleted && cpe.guild_emote.matches(&rct.emoji) => {
                 let message_reacted_to = reaction.message(ctx).await?;
-                handle_add_point(&ctx, &reaction, message_reacted_to).await?;
+                handle_add_point(ctx, reaction, message_reacted_to).await?;
             }
             _ => {}
         }
@@ -70,41 +654,30 @@ async fn handle_add_reaction(ctx: &Context, reaction: &serenity::Reaction) -> Re
 
 async fn handle_remove_reaction(ctx: &Context, reaction: &serenity::Reaction) -> Result<(), Error> {
     if reaction.user_id.unwrap() != ctx.cache.current_user().id {
-        let cur_role_msg = dbi::get_role_message(reaction.guild_id).await?;
+        // we only handle role reactions if the message is one of the posted role panels
+        let cur_role_panel = find_role_panel(reaction.guild_id, reaction.message_id).await?;
         let cur_point_emote = dbi::get_point_data(reaction.guild_id).await?;
-        // we only handle role reactions if the messge exists in the first place
-
-        match (reaction, cur_role_msg, cur_point_emote) {
-            // this is if it's a reaction to the role message
-            // we check if the message reacted to is the same id as the guild message saved in the
-            // db
-            // we also make sure that we have a role message in the db and that a guild message was
-            // posted
+
+        match (reaction, cur_role_panel, cur_point_emote) {
+            // this is if it's a reaction to a role panel that uses reactions
             (
-                rct,
+                _,
                 Some(RoleMessage {
-                    guild_message: Some(gm),
+                    name,
+                    style: RoleMessageStyle::Reactions,
                     ..
                 }),
                 _,
-            ) if rct.message_id == gm.id => {
-                let cur_roles = dbi::get_all_roles(reaction.guild_id).await?;
+            ) => {
+                let cur_roles = dbi::get_panel_roles(&name, reaction.guild_id).await?;
                 handle_remove_role(ctx, reaction, cur_roles).await?;
             }
 
             // this is if the reaction is a point emote
-            // we check if the id of the emote in the reaction matches the id of the emote saved to
-            // the db
-            (
-                serenity::Reaction {
-                    emoji: serenity::ReactionType::Custom { id, .. },
-                    ..
-                },
-                _,
-                Some(cpe),
-            ) if cpe.guild_emote.id.get() == id.get() => {
+            // we check if the emote in the reaction matches the emote saved to the db
+            (rct, _, Some(cpe)) if !cpe.emote_deleted && cpe.guild_emote.matches(&rct.emoji) => {
                 let message_reacted_to = reaction.message(ctx).await?;
-                handle_remove_point(&ctx, &reaction, message_reacted_to).await?;
+                handle_remove_point(ctx, reaction, message_reacted_to).await?;
             }
 
             _ => {}
@@ -119,39 +692,67 @@ async fn handle_add_role(
     reaction: &serenity::Reaction,
     cur_roles: Vec<UserRole>,
 ) -> Result<(), Error> {
-    if let serenity::ReactionType::Custom {
-        animated: _,
-        id,
-        name: _,
-    } = &reaction.emoji
+    if let Some(ur) = cur_roles
+        .iter()
+        .find(|ur| ur.emote.matches(&reaction.emoji))
     {
-        if let Some(ur) = cur_roles
-            .iter()
-            .filter(|ur| ur.emote.id.get() == id.get())
-            .next()
+        if let Ok(member) = reaction
+            .guild_id
+            .unwrap()
+            .member(ctx.http(), reaction.user_id.unwrap())
+            .await
         {
-            if let Ok(member) = reaction
-                .guild_id
-                .unwrap()
-                .member(ctx.http(), reaction.user_id.unwrap())
-                .await
-            {
-                let _ = member.add_role(&ctx.http, ur.guild_role.id).await?;
-                warn!(
-                    "In {}, events::handle_add_role: Added role {} to member {} with reaction.",
-                    reaction.guild_id.unwrap().get(),
-                    ur.guild_role.name,
-                    member.display_name()
-                );
+            let all_roles = dbi::get_all_roles(reaction.guild_id).await?;
+            let groups = dbi::get_all_role_groups(reaction.guild_id).await?;
+            // Picking a role of an exclusive group replaces the other role of the group
+            let replaced = exclusive_conflicts(ur, &member.roles, &all_roles, &groups);
+            let after: Vec<serenity::RoleId> = member
+                .roles
+                .iter()
+                .filter(|id| !replaced.iter().any(|r| r.guild_role.id == **id))
+                .copied()
+                .chain(std::iter::once(ur.guild_role.id))
+                .collect();
+            let allowed = check_requirements(ur, &member.roles, |id| {
+                guild_role_name(ctx, reaction.guild_id, id)
+            })
+            .and_then(|_| check_role_groups(&member.roles, &after, &all_roles, &groups));
+            if let Err(reason) = allowed {
+                let _ = reaction.delete(ctx).await;
                 let _ = member
                     .user
                     .direct_message(
                         &ctx,
-                        serenity::CreateMessage::new()
-                            .content(format!("The role {} was added to you.", ur.guild_role.name)),
+                        serenity::CreateMessage::new().content(format!(
+                            "The role {} wasn't added. {}",
+                            ur.guild_role.name, reason
+                        )),
                     )
                     .await;
-            };
+                return Ok(());
+            }
+
+            for r in replaced.iter() {
+                member.remove_role(&ctx.http, r.guild_role.id).await?;
+                stop_role_timer(member.user.id, r.guild_role.id, reaction.guild_id).await?;
+            }
+            member.add_role(&ctx.http, ur.guild_role.id).await?;
+            start_role_timer(&member.user, ur, reaction.guild_id).await?;
+            remove_stale_reactions(ctx, reaction.guild_id, member.user.id, &replaced).await?;
+            warn!(
+                "In {}, events::handle_add_role: Added role {} to member {} with reaction.",
+                reaction.guild_id.unwrap().get(),
+                ur.guild_role.name,
+                member.display_name()
+            );
+            let mut text = format!("The role {} was added to you.", ur.guild_role.name);
+            if !replaced.is_empty() {
+                text.push_str(&format!(" It replaced {}.", role_names(&replaced)));
+            }
+            let _ = member
+                .user
+                .direct_message(&ctx, serenity::CreateMessage::new().content(text))
+                .await;
         };
     };
 
@@ -163,47 +764,215 @@ async fn handle_remove_role(
     reaction: &serenity::Reaction,
     cur_roles: Vec<UserRole>,
 ) -> Result<(), Error> {
-    if let serenity::ReactionType::Custom {
-        animated: _,
-        id,
-        name: _,
-    } = &reaction.emoji
+    if let Some(ur) = cur_roles
+        .iter()
+        .find(|ur| ur.emote.matches(&reaction.emoji))
     {
-        if let Some(ur) = cur_roles
-            .iter()
-            .filter(|ur| ur.emote.id.get() == id.get())
-            .next()
+        if let Ok(member) = reaction
+            .guild_id
+            .unwrap()
+            .member(ctx.http(), reaction.user_id.unwrap())
+            .await
         {
-            if let Ok(member) = reaction
-                .guild_id
-                .unwrap()
-                .member(ctx.http(), reaction.user_id.unwrap())
-                .await
-            {
-                let _ = member.remove_role(&ctx.http, ur.guild_role.id).await?;
-                warn!(
-                    "In {}, events::handle_remove_role: Removed role {} from member {} with reaction.",
-                    reaction.guild_id.unwrap().get(),
-                    ur.guild_role.name,
-                    member.display_name()
-                );
+            // The role may already be gone, e.g. when it was replaced by another role of an
+            // exclusive group and the bot removed the stale reaction
+            if !member.roles.contains(&ur.guild_role.id) {
+                return Ok(());
+            }
+            let all_roles = dbi::get_all_roles(reaction.guild_id).await?;
+            let groups = dbi::get_all_role_groups(reaction.guild_id).await?;
+            let after: Vec<serenity::RoleId> = member
+                .roles
+                .iter()
+                .filter(|id| **id != ur.guild_role.id)
+                .copied()
+                .collect();
+            if let Err(reason) = check_role_groups(&member.roles, &after, &all_roles, &groups) {
                 let _ = member
                     .user
                     .direct_message(
                         &ctx,
                         serenity::CreateMessage::new().content(format!(
-                            "The role {} was removed from you.",
-                            ur.guild_role.name
+                            "The role {} wasn't removed. {}",
+                            ur.guild_role.name, reason
                         )),
                     )
                     .await;
-            };
+                return Ok(());
+            }
+
+            member.remove_role(&ctx.http, ur.guild_role.id).await?;
+            stop_role_timer(member.user.id, ur.guild_role.id, reaction.guild_id).await?;
+            warn!(
+                "In {}, events::handle_remove_role: Removed role {} from member {} with reaction.",
+                reaction.guild_id.unwrap().get(),
+                ur.guild_role.name,
+                member.display_name()
+            );
+            let _ = member
+                .user
+                .direct_message(
+                    &ctx,
+                    serenity::CreateMessage::new().content(format!(
+                        "The role {} was removed from you.",
+                        ur.guild_role.name
+                    )),
+                )
+                .await;
         };
     };
 
     Ok(())
 }
 
+fn role_names(roles: &[&UserRole]) -> String {
+    roles
+        .iter()
+        .map(|r| r.guild_role.name.to_owned())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// Gives new members the join roles and members who rejoin the self-assignable roles they had when
+/// they left
+async fn handle_member_join(ctx: &Context, member: &serenity::Member) -> Result<(), Error> {
+    if member.user.bot {
+        return Ok(());
+    }
+    let guildid = Some(member.guild_id);
+    let config = dbi::get_join_role_config(guildid).await?;
+    let snapshot = dbi::take_role_snapshot(member.user.id, guildid).await?;
+
+    let mut roles: Vec<serenity::RoleId> = config.roles.to_owned();
+    if let (true, Some(snapshot)) = (config.sticky, snapshot) {
+        let expiries = dbi::get_role_expiries(guildid).await?;
+        let now = serenity::Timestamp::now();
+        // Only roles that are still self-assignable come back, and timed roles only if they
+        // haven't expired in the meantime. That includes roles given for a while with /role
+        // grant, which don't have a duration of their own
+        for ur in dbi::get_all_roles(guildid).await?.iter() {
+            let id = ur.guild_role.id;
+            let expired = match snapshot.expiries.iter().find(|e| e.role.id == id) {
+                Some(e) => e.expires_at <= now,
+                // Snapshots from before expiries were kept only know the duration of the role
+                None => {
+                    ur.duration.is_some()
+                        && !expiries
+                            .iter()
+                            .any(|e| e.expiry_id == RoleExpiry::id_for(member.user.id, id))
+                }
+            };
+            if snapshot.roles.contains(&id) && !expired && !roles.contains(&id) {
+                roles.push(id);
+            }
+        }
+    }
+    if roles.is_empty() {
+        return Ok(());
+    }
+
+    member.add_roles(&ctx.http, &roles).await?;
+    warn!(
+        "In {}, events::handle_member_join: Gave {} roles to new member {}.",
+        member.guild_id.get(),
+        roles.len(),
+        member.user.name
+    );
+
+    Ok(())
+}
+
+/// Remembers the self-assignable roles of a member who leaves, so they get them back when they
+/// rejoin. Roles a moderator took away aren't held anymore and so aren't given back
+async fn handle_member_leave(
+    guildid: serenity::GuildId,
+    user: &serenity::User,
+    member: Option<&serenity::Member>,
+) -> Result<(), Error> {
+    if user.bot || !dbi::get_join_role_config(Some(guildid)).await?.sticky {
+        return Ok(());
+    }
+    let member = match member {
+        Some(m) => m,
+        None => {
+            warn!(
+                "In {}, events::handle_member_leave: roles of member {} weren't cached, they can't be restored.",
+                guildid.get(),
+                user.name
+            );
+            return Ok(());
+        }
+    };
+
+    let roles: Vec<serenity::RoleId> = dbi::get_all_roles(Some(guildid))
+        .await?
+        .into_iter()
+        .map(|ur| ur.guild_role.id)
+        .filter(|id| member.roles.contains(id))
+        .collect();
+    let expiries: Vec<RoleExpiry> = dbi::get_role_expiries(Some(guildid))
+        .await?
+        .into_iter()
+        .filter(|e| e.user.id == user.id && roles.contains(&e.role.id))
+        .collect();
+    match roles.is_empty() {
+        // An older snapshot must not bring back roles the member gave up since then
+        true => {
+            dbi::take_role_snapshot(user.id, Some(guildid)).await?;
+        }
+        false => {
+            dbi::set_role_snapshot(
+                RoleSnapshot {
+                    user: user.to_owned(),
+                    roles,
+                    left_at: serenity::Timestamp::now(),
+                    expiries,
+                },
+                Some(guildid),
+            )
+            .await?;
+        }
+    };
+
+    Ok(())
+}
+
+/// Cleans up after roles or emotes that were deleted from the server and reports the changes to
+/// the moderation log
+async fn handle_deleted_roles(ctx: &Context, guildid: serenity::GuildId) -> Result<(), Error> {
+    let changes = purge_deleted_roles(ctx, guildid).await?;
+    if !changes.is_empty() {
+        post_to_mod_log(ctx, guildid, &changes).await?;
+    }
+
+    Ok(())
+}
+
+/// Cleans up the roles whose emote was deleted and invalidates a deleted point emote
+async fn handle_emojis_update(
+    ctx: &Context,
+    guildid: serenity::GuildId,
+    emojis: &HashMap<serenity::EmojiId, serenity::Emoji>,
+) -> Result<(), Error> {
+    let mut changes = purge_deleted_roles(ctx, guildid).await?;
+    if let Some(points) = dbi::get_point_data(Some(guildid)).await? {
+        if let ReactionEmote::Custom(e) = &points.guild_emote {
+            if !points.emote_deleted && !emojis.contains_key(&e.id) {
+                dbi::invalidate_point_emote(Some(guildid)).await?;
+                changes.push(format!(
+                    "The point emote :{}: was deleted, so no points can be given until a new one is set with `/points emote_set`.",
+                    e.name
+                ));
+            }
+        }
+    }
+    if !changes.is_empty() {
+        post_to_mod_log(ctx, guildid, &changes).await?;
+    }
+
+    Ok(())
+}
+
 async fn handle_add_point(
     ctx: &Context,
     reaction: &serenity::Reaction,
@@ -279,3 +1048,37 @@ async fn handle_remove_point(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_triggers_in_order() {
+        let triggers: Vec<&str> =
+            find_inline_triggers("Read $passive and $articles", "$").collect();
+        assert_eq!(triggers, vec!["passive", "articles"]);
+    }
+
+    #[test]
+    fn trims_punctuation_after_trigger() {
+        let triggers: Vec<&str> = find_inline_triggers("See $passive, or $tense!?", "$").collect();
+        assert_eq!(triggers, vec!["passive", "tense"]);
+    }
+
+    #[test]
+    fn ignores_sigil_inside_words() {
+        assert_eq!(find_inline_triggers("It costs 5$ or US$5", "$").count(), 0);
+    }
+
+    #[test]
+    fn ignores_bare_sigil() {
+        assert_eq!(find_inline_triggers("$ $. $!", "$").count(), 0);
+    }
+
+    #[test]
+    fn supports_longer_sigils() {
+        let triggers: Vec<&str> = find_inline_triggers("try ??passive or ?tense", "??").collect();
+        assert_eq!(triggers, vec!["passive"]);
+    }
+}
@@ -1,17 +1,18 @@
 use db_interactions as dbi;
 use poise::serenity_prelude as serenity;
 
-use log4rs;
-
 use events::my_event_handler;
 use types::*;
 
+mod attachment_store;
 mod commands_util;
 mod constants;
 mod db_interactions;
 mod embed_tools;
 mod events;
+mod output;
 mod point_commands;
+mod responder_commands;
 mod role_commands;
 mod tag_commands;
 mod types;
@@ -35,6 +36,11 @@ async fn main() {
                 commands_util::register(),
                 tag_commands::tags(),
                 tag_commands::create_tag(),
+                tag_commands::send_tag(),
+                commands_util::mention_policy(),
+                commands_util::mod_log(),
+                responder_commands::responder(),
+                responder_commands::test_message_responders(),
                 role_commands::role(),
                 point_commands::points(),
                 user_commands::user_info(),
@@ -46,9 +52,15 @@ async fn main() {
             event_handler: |ctx, event, _framework, _data| Box::pin(my_event_handler(ctx, event)),
             ..Default::default()
         })
-        .setup(|ctx, _ready, framework| {
+        .setup(|ctx, ready, framework| {
             Box::pin(async move {
                 poise::builtins::register_globally(ctx, &framework.options().commands).await?;
+                tokio::spawn(tag_commands::purge_trash_periodically(ctx.cache.clone()));
+                tokio::spawn(role_commands::expire_roles_periodically(ctx.clone()));
+                tokio::spawn(role_commands::reconcile_on_startup(
+                    ctx.clone(),
+                    ready.guilds.iter().map(|g| g.id).collect(),
+                ));
                 Ok(data)
             })
         })
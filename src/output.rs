@@ -0,0 +1,239 @@
+use crate::attachment_store;
+use crate::constants;
+use crate::dbi;
+use crate::embed_tools::build_tag_embed;
+use crate::types::*;
+use poise::serenity_prelude::{self as serenity, Mentionable};
+
+/// Put between the @ and the name of a mass mention so that it is still readable but can't ping
+static ZERO_WIDTH_SPACE: &str = "\u{200B}";
+
+static MASS_MENTIONS: [&str; 2] = ["@everyone", "@here"];
+
+/// Returns true if the text contains a mention that pings everyone or everyone online
+pub fn contains_mass_mention(text: &str) -> bool {
+    MASS_MENTIONS.iter().any(|m| text.contains(m))
+}
+
+/// Breaks up @everyone and @here, so they are still shown but never ping anyone
+pub fn neutralize_mass_mentions(text: &str) -> String {
+    MASS_MENTIONS
+        .iter()
+        .fold(text.to_string(), |text, mention| {
+            text.replace(
+                mention,
+                &format!("@{}{}", ZERO_WIDTH_SPACE, mention.trim_start_matches('@')),
+            )
+        })
+}
+
+/// Applies the mention policy to user-provided text. Mass mentions are neutralized unless the
+/// policy allows them
+pub fn sanitize_mentions(text: &str, policy: &MentionPolicy) -> String {
+    match policy.everyone {
+        true => text.to_string(),
+        false => neutralize_mass_mentions(text),
+    }
+}
+
+/// The allowed mentions that correspond to the mention policy
+pub fn allowed_mentions(policy: &MentionPolicy) -> serenity::CreateAllowedMentions {
+    serenity::CreateAllowedMentions::new()
+        .all_users(policy.users)
+        .all_roles(policy.roles)
+        .everyone(policy.everyone)
+}
+
+/// The text to post for a tag. Global tags are marked as such below the content, as long as the
+/// message stays within the character limit
+pub fn tag_content(tag: &Tag, source: TagSource) -> String {
+    let note = "\n-# Global tag";
+    match source {
+        TagSource::Global
+            if tag.content.chars().count() + note.chars().count()
+                <= constants::MESSAGE_CHAR_LIMIT =>
+        {
+            format!("{}{}", tag.content, note).trim_start().to_string()
+        }
+        _ => tag.content.to_owned(),
+    }
+}
+
+/// Puts a mention of the user in front of the text, as long as it still fits in a message
+pub fn prefix_mention(text: &str, user: serenity::UserId) -> Option<String> {
+    let prefixed = format!("{} {}", user.mention(), text);
+    match prefixed.chars().count() <= constants::MESSAGE_CHAR_LIMIT {
+        true => Some(prefixed.trim_end().to_string()),
+        false => None,
+    }
+}
+
+/// The allowed mentions of the policy, extended by a user that was explicitly picked to be
+/// mentioned
+fn allowed_mentions_with(
+    policy: &MentionPolicy,
+    user: Option<serenity::UserId>,
+) -> serenity::CreateAllowedMentions {
+    match user {
+        // Discord rejects listing single users when all users may be mentioned anyway
+        Some(user) if !policy.users => allowed_mentions(policy).users(vec![user]),
+        _ => allowed_mentions(policy),
+    }
+}
+
+/// Builds the reply for showing a tag with a command, following the mention policy of the server.
+/// If a member is given, the tag is directed at them with a mention in front of the content.
+/// Restricted tags can be limited to ephemeral replies
+pub async fn tag_reply(
+    tag: &Tag,
+    source: TagSource,
+    to: Option<serenity::UserId>,
+    guildid: Option<serenity::GuildId>,
+) -> Result<poise::CreateReply, Error> {
+    let policy = dbi::get_mention_policy(guildid).await?;
+
+    let mut reply = poise::CreateReply::default()
+        .allowed_mentions(allowed_mentions_with(&policy, to))
+        .ephemeral(tag.access.ephemeral);
+    let mut content = sanitize_mentions(&tag_content(tag, source), &policy);
+    if let Some(user) = to {
+        content = prefix_mention(&content, user)
+            .ok_or("The tag is too long to put a mention in front of it.")?;
+    }
+    if !content.is_empty() {
+        reply = reply.content(content);
+    }
+    if let Some(embed) = &tag.embed {
+        reply = reply.embed(build_tag_embed(embed));
+    }
+    for file in attachment_store::load_attachments(tag, source.db_guild_id(guildid)).await {
+        reply = reply.attachment(file);
+    }
+
+    Ok(reply)
+}
+
+/// Builds the message for posting a tag in a channel as a reply to another message, following the
+/// mention policy of the server. The author of the message is only pinged if `ping_author` is set
+pub async fn tag_message(
+    tag: &Tag,
+    source: TagSource,
+    reply_to: &serenity::Message,
+    ping_author: bool,
+    guildid: Option<serenity::GuildId>,
+) -> Result<serenity::CreateMessage, Error> {
+    let policy = dbi::get_mention_policy(guildid).await?;
+
+    let mut message = serenity::CreateMessage::new()
+        .reference_message(reply_to)
+        .allowed_mentions(allowed_mentions(&policy).replied_user(ping_author));
+    let content = sanitize_mentions(&tag_content(tag, source), &policy);
+    if !content.is_empty() {
+        message = message.content(content);
+    }
+    if let Some(embed) = &tag.embed {
+        message = message.embed(build_tag_embed(embed));
+    }
+    message = message
+        .add_files(attachment_store::load_attachments(tag, source.db_guild_id(guildid)).await);
+
+    Ok(message)
+}
+
+/// Builds a message with user-provided text, following the mention policy of the server
+pub async fn user_text_message(
+    text: &str,
+    guildid: Option<serenity::GuildId>,
+) -> Result<serenity::CreateMessage, Error> {
+    let policy = dbi::get_mention_policy(guildid).await?;
+
+    Ok(serenity::CreateMessage::new()
+        .content(sanitize_mentions(text, &policy))
+        .allowed_mentions(allowed_mentions(&policy)))
+}
+
+/// Builds an edit that replaces the text of a message with user-provided text, following the
+/// mention policy of the server
+pub async fn user_text_edit(
+    text: &str,
+    guildid: Option<serenity::GuildId>,
+) -> Result<serenity::EditMessage, Error> {
+    let policy = dbi::get_mention_policy(guildid).await?;
+
+    Ok(serenity::EditMessage::new()
+        .content(sanitize_mentions(text, &policy))
+        .allowed_mentions(allowed_mentions(&policy)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_mass_mentions() {
+        assert!(contains_mass_mention("Hey @everyone, look at this"));
+        assert!(contains_mass_mention("@here"));
+        assert!(!contains_mass_mention("Hey everyone, look at this"));
+        assert!(!contains_mass_mention("Ask <@123456789> or <@&987654321>"));
+    }
+
+    #[test]
+    fn neutralizes_mass_mentions() {
+        let neutralized = neutralize_mass_mentions("@everyone and @here, read this!");
+        assert_eq!(
+            neutralized,
+            "@\u{200B}everyone and @\u{200B}here, read this!"
+        );
+        assert!(!contains_mass_mention(&neutralized));
+    }
+
+    #[test]
+    fn neutralizing_is_idempotent() {
+        let once = neutralize_mass_mentions("@everyone @everyone");
+        assert_eq!(neutralize_mass_mentions(&once), once);
+    }
+
+    #[test]
+    fn leaves_other_text_alone() {
+        let text = "The passive voice: <@123> wrote *it was written*.\n  Indented line";
+        assert_eq!(neutralize_mass_mentions(text), text);
+    }
+
+    #[test]
+    fn sanitizing_follows_policy() {
+        let allow_everyone = MentionPolicy {
+            everyone: true,
+            ..MentionPolicy::default()
+        };
+        assert_eq!(sanitize_mentions("@here", &allow_everyone), "@here");
+        assert_eq!(
+            sanitize_mentions("@here", &MentionPolicy::default()),
+            "@\u{200B}here"
+        );
+    }
+
+    #[test]
+    fn prefixes_mention() {
+        let user = serenity::UserId::new(123);
+        assert_eq!(
+            prefix_mention("Read this", user).as_deref(),
+            Some("<@123> Read this")
+        );
+        assert_eq!(prefix_mention("", user).as_deref(), Some("<@123>"));
+    }
+
+    #[test]
+    fn mention_must_fit_in_message() {
+        let user = serenity::UserId::new(123);
+        let full = "a".repeat(constants::MESSAGE_CHAR_LIMIT);
+        assert_eq!(prefix_mention(&full, user), None);
+        let fitting = "a".repeat(constants::MESSAGE_CHAR_LIMIT - 7);
+        assert!(prefix_mention(&fitting, user).is_some());
+    }
+
+    #[test]
+    fn default_policy_only_allows_users() {
+        let mentions = serde_json::to_value(allowed_mentions(&MentionPolicy::default())).unwrap();
+        assert_eq!(mentions["parse"], serde_json::json!(["users"]));
+    }
+}
@@ -1,3 +1,4 @@
+use crate::commands_util::emote_argument;
 use crate::dbi;
 use crate::embed_tools::paginate_with_embeds;
 use crate::types::*;
@@ -14,9 +15,9 @@ pub async fn points(_ctx: Context<'_>) -> Result<(), Error> {
 
 /// Set the emote for the grammar points
 ///
-/// This command is used to set an emote from the guild to be the GrammarPoint emote. Whenever a
-/// user that isn't the author of the message reacts with said emote to a message, one GrammarPoint
-/// will be added to the authors Points.
+/// This command is used to set an emote from the guild or a Unicode emoji to be the GrammarPoint
+/// emote. Whenever a user that isn't the author of the message reacts with said emote to a message,
+/// one GrammarPoint will be added to the authors Points.
 #[poise::command(
     slash_command,
     required_permissions = "ADMINISTRATOR",
@@ -25,8 +26,16 @@ pub async fn points(_ctx: Context<'_>) -> Result<(), Error> {
 )]
 pub async fn emote_set(
     ctx: Context<'_>,
-    #[description = "Guild Emote to use as point emote"] emote: serenity::Emoji,
+    #[description = "Guild emote or Unicode emoji to use as point emote"] emote: String,
 ) -> Result<(), Error> {
+    let emote = match emote_argument(ctx, &emote).await? {
+        Some(e) => e,
+        None => {
+            ctx.say("That's neither an emote of this server nor a Unicode emoji.")
+                .await?;
+            return Ok(());
+        }
+    };
     dbi::set_point_emote(&emote, ctx.author(), ctx.guild_id()).await?;
 
     ctx.say(format!("Set the new point emote to: {}", emote))
@@ -53,7 +62,10 @@ pub async fn emote_stats(ctx: Context<'_>) -> Result<(), Error> {
             ctx.send(poise::CreateReply::default().embed(serenity::CreateEmbed::default()
                 .title("Points system info")
                 .description("Points can be given to users by other users by reacting to their messages with the point emote.")
-                .field("Point emote", pointsdata.guild_emote.to_string(), false)
+                .field("Point emote", match pointsdata.emote_deleted {
+                    true => format!("{} was deleted, set a new one with `/points emote_set`", pointsdata.guild_emote),
+                    false => pointsdata.guild_emote.to_string(),
+                }, false)
                 .field("Active", pointsdata.active.to_string(), false)
                 .field("Total points scored", pointsdata.total.to_string(), false)
                 .colour(serenity::Colour::BLUE)
@@ -83,19 +95,16 @@ pub async fn leaderboard(ctx: Context<'_>) -> Result<(), Error> {
 
     // check if we have any point_data
     if let Some(points_data) = point_data {
-        if user_data.len() == 0 {
+        if user_data.is_empty() {
             ctx.say("No points earned on this server yet.").await?;
         } else {
             // sort from most points to least points and slice into 20 entries per page
             user_data.sort_by_key(|a| std::cmp::Reverse(a.grammarpoints));
 
-            let command_user_position = match user_data
+            let command_user_position = user_data
                 .iter()
                 .position(|u| u.discord_user.id.get() == command_user.id.get())
-            {
-                Some(i) => Some((i, &user_data[i])),
-                None => None,
-            };
+                .map(|i| (i, &user_data[i]));
 
             let sliced_data: Vec<Vec<MyUser>> =
                 user_data.chunks(20).map(|chunk| chunk.to_vec()).collect();
@@ -120,7 +129,7 @@ pub async fn leaderboard(ctx: Context<'_>) -> Result<(), Error> {
                 }
 
                 let embed = serenity::CreateEmbed::default()
-                    .title(format!("Point Leaderboard for {}", ctx.guild_id().unwrap().name(&ctx).unwrap()))
+                    .title(format!("Point Leaderboard for {}", ctx.guild_id().unwrap().name(ctx).unwrap()))
                     .description(format!("Leaderboard for the points scored on this server. A total of **{} Points** have been scored on this server. {}", points_data.total, match command_user_position {
                         Some(u) => format!("**You** have scored **{}** Points and are Ranked **{}**", u.1.grammarpoints, u.0),
                         None => String::from("**You** have not scored any points yet.")
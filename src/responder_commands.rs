@@ -0,0 +1,596 @@
+use crate::constants;
+use crate::dbi;
+use crate::embed_tools::paginate_with_embeds;
+use crate::output;
+use crate::types::*;
+use once_cell::sync::Lazy;
+use poise::serenity_prelude::{self as serenity, Mentionable};
+use regex::{Regex, RegexBuilder};
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Compiled responder patterns, keyed by the pattern and whether it's a regex. `None` for patterns
+/// that don't compile
+type PatternCache = HashMap<(String, bool), Option<Regex>>;
+
+/// Compiled patterns per server. A server's entries are dropped when its responders are added or
+/// removed
+static COMPILED_PATTERNS: Lazy<Mutex<HashMap<Option<serenity::GuildId>, PatternCache>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+async fn autocomplete_responder(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    match dbi::get_all_responders(ctx.guild_id()).await {
+        Ok(r) => r
+            .iter()
+            .filter(|r| r.name.contains(partial))
+            .map(|r| r.name.to_owned())
+            .collect(),
+        Err(_) => vec![],
+    }
+}
+
+async fn autocomplete_tagname(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    match dbi::get_all_tags(ctx.guild_id()).await {
+        Ok(t) => t
+            .iter()
+            .filter(|t| t.name.contains(partial))
+            .map(|t| t.name.to_owned())
+            .collect(),
+        Err(_) => vec![],
+    }
+}
+
+/// Compiles the pattern of a responder. A phrase matches when it appears as whole words, ignoring
+/// case and the amount of whitespace between the words
+pub fn compile_pattern(pattern: &str, regex: bool) -> Result<Regex, regex::Error> {
+    let source = match regex {
+        true => pattern.to_string(),
+        false => {
+            let words: Vec<String> = pattern.split_whitespace().map(regex::escape).collect();
+            format!(r"(?i)(?:^|\W){}(?:$|\W)", words.join(r"\s+"))
+        }
+    };
+
+    RegexBuilder::new(&source)
+        .size_limit(constants::RESPONDER_PATTERN_SIZE_LIMIT)
+        .build()
+}
+
+/// Checks if the responder is enabled and allowed to react in the channel
+fn is_active(responder: &AutoResponder, channel: serenity::ChannelId) -> bool {
+    responder.enabled && (responder.channels.is_empty() || responder.channels.contains(&channel))
+}
+
+/// Drops the compiled patterns of the server, so they are compiled again from the stored responders
+fn forget_patterns(guildid: Option<serenity::GuildId>) {
+    COMPILED_PATTERNS.lock().unwrap().remove(&guildid);
+}
+
+/// Checks if the message content matches the pattern of the responder. Patterns that don't compile
+/// never match
+fn matches(responder: &AutoResponder, content: &str, guildid: Option<serenity::GuildId>) -> bool {
+    let pattern = COMPILED_PATTERNS
+        .lock()
+        .unwrap()
+        .entry(guildid)
+        .or_default()
+        .entry((responder.pattern.to_owned(), responder.regex))
+        .or_insert_with(|| compile_pattern(&responder.pattern, responder.regex).ok())
+        .clone();
+
+    pattern.is_some_and(|r| r.is_match(content))
+}
+
+/// Returns the responder that reacts to a message in the channel. Only the first active responder
+/// with a matching pattern that is `ready` replies, so a message never gets several automatic
+/// replies
+pub fn find_responder<'a>(
+    responders: &'a [AutoResponder],
+    content: &str,
+    channel: serenity::ChannelId,
+    guildid: Option<serenity::GuildId>,
+    ready: impl Fn(&AutoResponder) -> bool,
+) -> Option<&'a AutoResponder> {
+    responders
+        .iter()
+        .find(|r| is_active(r, channel) && matches(r, content, guildid) && ready(r))
+}
+
+/// Describes the reply of a responder in a single line
+fn reply_summary(reply: &ResponderReply) -> String {
+    match reply {
+        ResponderReply::Text(text) => {
+            let flat = text.split_whitespace().collect::<Vec<&str>>().join(" ");
+            match flat.chars().count() > 100 {
+                true => format!("{}…", flat.chars().take(99).collect::<String>()),
+                false => flat,
+            }
+        }
+        ResponderReply::Tag(name) => format!("Tag {}", name),
+    }
+}
+
+/// Auto-responder parent command
+///
+/// Auto-responders reply to messages that contain a phrase or match a regex, e.g. pointing members
+/// who ask "how do I get roles" to the role channel. They reply with a text or with a tag.
+#[poise::command(
+    slash_command,
+    default_member_permissions = "MANAGE_MESSAGES",
+    subcommands(
+        "add_responder",
+        "remove_responder",
+        "list_responders",
+        "enable_responder",
+        "responder_channel",
+        "responder_cooldown",
+        "test_responders",
+    )
+)]
+pub async fn responder(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Add an auto-responder
+///
+/// The pattern is a phrase that has to appear in the message as whole words, ignoring case. Set
+/// `regex` to use a regular expression instead. Give either a text or a tag to reply with.
+#[poise::command(
+    slash_command,
+    required_permissions = "MANAGE_MESSAGES",
+    category = "Responders",
+    rename = "add",
+    guild_only
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn add_responder(
+    ctx: Context<'_>,
+    #[description = "Name of the responder, one word without spaces"] name: String,
+    #[description = "Phrase or regex to react to"] pattern: String,
+    #[description = "Treat the pattern as a regex"] regex: Option<bool>,
+    #[description = "Text to reply with"] response: Option<String>,
+    #[description = "Tag to reply with"]
+    #[autocomplete = "autocomplete_tagname"]
+    tag: Option<String>,
+    #[description = "Seconds before replying again in the same channel"]
+    #[max = 86400]
+    cooldown: Option<u64>,
+) -> Result<(), Error> {
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        ctx.say("Responder names can't be empty or contain spaces.")
+            .await?;
+        return Ok(());
+    }
+    if pattern.trim().is_empty() {
+        ctx.say("The pattern can't be empty.").await?;
+        return Ok(());
+    }
+    let regex = regex.unwrap_or(false);
+    if let Err(e) = compile_pattern(&pattern, regex) {
+        ctx.say(format!("The pattern is not valid: {}", e)).await?;
+        return Ok(());
+    }
+    let reply = match (response, tag) {
+        (Some(text), None) => {
+            if text.chars().count() > constants::MESSAGE_CHAR_LIMIT {
+                ctx.say(format!(
+                    "The response can be at most {} characters long.",
+                    constants::MESSAGE_CHAR_LIMIT
+                ))
+                .await?;
+                return Ok(());
+            }
+            if output::contains_mass_mention(&text) {
+                ctx.say("Responses can't mention @everyone or @here.")
+                    .await?;
+                return Ok(());
+            }
+            ResponderReply::Text(text)
+        }
+        (None, Some(tagname)) => match dbi::get_tag(&tagname, ctx.guild_id()).await {
+            Ok(t) => ResponderReply::Tag(t.name),
+            Err(e) => {
+                ctx.say(format!("{}", e)).await?;
+                return Ok(());
+            }
+        },
+        _ => {
+            ctx.say("Give either a response text or a tag to reply with.")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let responder = AutoResponder {
+        name,
+        pattern,
+        regex,
+        response: reply,
+        channels: vec![],
+        cooldown: cooldown.unwrap_or(constants::DEFAULT_RESPONDER_COOLDOWN),
+        enabled: true,
+        creator: ctx.author().to_owned(),
+    };
+    match dbi::create_responder(responder, ctx.guild_id()).await {
+        Ok(r) => {
+            forget_patterns(ctx.guild_id());
+            ctx.say(format!("Auto-responder {} added sucessfully!", r.name))
+                .await?
+        }
+        Err(e) => ctx.say(format!("{}", e)).await?,
+    };
+
+    Ok(())
+}
+
+/// Remove an auto-responder
+#[poise::command(
+    slash_command,
+    required_permissions = "MANAGE_MESSAGES",
+    category = "Responders",
+    rename = "remove",
+    guild_only
+)]
+pub async fn remove_responder(
+    ctx: Context<'_>,
+    #[description = "Responder to remove"]
+    #[autocomplete = "autocomplete_responder"]
+    name: String,
+) -> Result<(), Error> {
+    match dbi::remove_responder(&name, ctx.guild_id()).await {
+        Ok(r) => {
+            forget_patterns(ctx.guild_id());
+            ctx.say(format!("Auto-responder {} removed sucessfully!", r.name))
+                .await?
+        }
+        Err(e) => ctx.say(format!("{}", e)).await?,
+    };
+
+    Ok(())
+}
+
+/// List all auto-responders
+///
+/// Shows the pattern, reply, channels and cooldown of every auto-responder on this server.
+#[poise::command(
+    slash_command,
+    required_permissions = "MANAGE_MESSAGES",
+    category = "Responders",
+    rename = "list",
+    guild_only
+)]
+pub async fn list_responders(ctx: Context<'_>) -> Result<(), Error> {
+    let responders = dbi::get_all_responders(ctx.guild_id()).await?;
+    if responders.is_empty() {
+        ctx.say("There are no auto-responders on this server.")
+            .await?;
+        return Ok(());
+    }
+
+    let pages = responders.chunks(10).count();
+    let embeds: Vec<serenity::CreateEmbed> = responders
+        .chunks(10)
+        .enumerate()
+        .map(|(idx, chunk)| {
+            serenity::CreateEmbed::default()
+                .title(format!("Auto-responders ({}/{})", idx + 1, pages))
+                .fields(chunk.iter().map(|r| {
+                    let channels = match r.channels.is_empty() {
+                        true => String::from("All"),
+                        false => r
+                            .channels
+                            .iter()
+                            .map(|c| c.mention().to_string())
+                            .collect::<Vec<_>>()
+                            .join(", "),
+                    };
+                    (
+                        format!(
+                            "{} ({})",
+                            r.name,
+                            match r.enabled {
+                                true => "enabled",
+                                false => "disabled",
+                            }
+                        ),
+                        format!(
+                            "*{}:* `{}`\n*Reply:* {}\n*Channels:* {} | *Cooldown:* {}s",
+                            match r.regex {
+                                true => "Regex",
+                                false => "Phrase",
+                            },
+                            r.pattern,
+                            reply_summary(&r.response),
+                            channels,
+                            r.cooldown
+                        ),
+                        false,
+                    )
+                }))
+                .colour(serenity::Colour::BLUE)
+                .footer(serenity::CreateEmbedFooter::new(format!(
+                    "Requsted by {}. Only they can change pages.",
+                    ctx.author().name
+                )))
+        })
+        .collect();
+    paginate_with_embeds(ctx, embeds).await?;
+
+    Ok(())
+}
+
+/// Enable or disable an auto-responder
+#[poise::command(
+    slash_command,
+    required_permissions = "MANAGE_MESSAGES",
+    category = "Responders",
+    rename = "enable",
+    guild_only
+)]
+pub async fn enable_responder(
+    ctx: Context<'_>,
+    #[description = "Responder to change"]
+    #[autocomplete = "autocomplete_responder"]
+    name: String,
+    #[description = "Whether the responder replies to messages"] enabled: bool,
+) -> Result<(), Error> {
+    let responder = match dbi::get_responder(&name, ctx.guild_id()).await {
+        Ok(r) => r,
+        Err(e) => {
+            ctx.say(format!("{}", e)).await?;
+            return Ok(());
+        }
+    };
+    let responder = dbi::update_responder(
+        AutoResponder {
+            enabled,
+            ..responder
+        },
+        ctx.guild_id(),
+    )
+    .await?;
+
+    ctx.say(format!(
+        "Auto-responder {} is now {}.",
+        responder.name,
+        match responder.enabled {
+            true => "enabled",
+            false => "disabled",
+        }
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// Limit an auto-responder to a channel
+///
+/// If at least one channel is allowed, the responder only replies in the allowed channels. Set
+/// `allowed` to false to remove the channel from the list again.
+#[poise::command(
+    slash_command,
+    required_permissions = "MANAGE_MESSAGES",
+    category = "Responders",
+    rename = "channel",
+    guild_only
+)]
+pub async fn responder_channel(
+    ctx: Context<'_>,
+    #[description = "Responder to change"]
+    #[autocomplete = "autocomplete_responder"]
+    name: String,
+    #[description = "Channel to allow"] channel: serenity::Channel,
+    #[description = "Whether the responder replies in the channel"] allowed: bool,
+) -> Result<(), Error> {
+    let mut responder = match dbi::get_responder(&name, ctx.guild_id()).await {
+        Ok(r) => r,
+        Err(e) => {
+            ctx.say(format!("{}", e)).await?;
+            return Ok(());
+        }
+    };
+    responder.channels.retain(|c| *c != channel.id());
+    if allowed {
+        responder.channels.push(channel.id());
+    }
+    let responder = dbi::update_responder(responder, ctx.guild_id()).await?;
+
+    ctx.say(match responder.channels.is_empty() {
+        true => format!("Auto-responder {} replies in all channels.", responder.name),
+        false => format!(
+            "Auto-responder {} replies in {}.",
+            responder.name,
+            responder
+                .channels
+                .iter()
+                .map(|c| c.mention().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Set the cooldown of an auto-responder
+///
+/// After the responder replied, it doesn't reply again in the same channel until the cooldown is
+/// over.
+#[poise::command(
+    slash_command,
+    required_permissions = "MANAGE_MESSAGES",
+    category = "Responders",
+    rename = "cooldown",
+    guild_only
+)]
+pub async fn responder_cooldown(
+    ctx: Context<'_>,
+    #[description = "Responder to change"]
+    #[autocomplete = "autocomplete_responder"]
+    name: String,
+    #[description = "Cooldown in seconds"]
+    #[max = 86400]
+    seconds: u64,
+) -> Result<(), Error> {
+    let responder = match dbi::get_responder(&name, ctx.guild_id()).await {
+        Ok(r) => r,
+        Err(e) => {
+            ctx.say(format!("{}", e)).await?;
+            return Ok(());
+        }
+    };
+    let responder = dbi::update_responder(
+        AutoResponder {
+            cooldown: seconds,
+            ..responder
+        },
+        ctx.guild_id(),
+    )
+    .await?;
+
+    ctx.say(format!(
+        "Cooldown of auto-responder {} set to {} seconds.",
+        responder.name, responder.cooldown
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// Explains which responders match a message and which one of them would reply
+fn dry_run(
+    responders: &[AutoResponder],
+    content: &str,
+    channel: serenity::ChannelId,
+    guildid: Option<serenity::GuildId>,
+) -> String {
+    let replying = find_responder(responders, content, channel, guildid, |_| true);
+    let lines: Vec<String> = responders
+        .iter()
+        .filter(|r| matches(r, content, guildid))
+        .map(|r| {
+            let state = match (
+                replying.is_some_and(|rr| rr.name == r.name),
+                is_active(r, channel),
+            ) {
+                (true, _) => format!("replies with {}", reply_summary(&r.response)),
+                (false, true) => String::from("matches, but an earlier responder replies"),
+                (false, false) if !r.enabled => String::from("matches, but is disabled"),
+                (false, false) => String::from("matches, but doesn't reply in this channel"),
+            };
+            format!("- **{}** {}", r.name, state)
+        })
+        .collect();
+
+    match lines.is_empty() {
+        true => String::from("No auto-responder matches this message."),
+        false => lines.join("\n"),
+    }
+}
+
+/// Test which auto-responders react to a message
+///
+/// Nothing is posted. Shows which responders match the text and which one would reply in this
+/// channel, ignoring cooldowns.
+#[poise::command(
+    slash_command,
+    required_permissions = "MANAGE_MESSAGES",
+    category = "Responders",
+    rename = "test",
+    guild_only
+)]
+pub async fn test_responders(
+    ctx: Context<'_>,
+    #[description = "Message text to test"] text: String,
+) -> Result<(), Error> {
+    let responders = dbi::get_all_responders(ctx.guild_id()).await?;
+    ctx.send(
+        poise::CreateReply::default()
+            .content(dry_run(
+                &responders,
+                &text,
+                ctx.channel_id(),
+                ctx.guild_id(),
+            ))
+            .allowed_mentions(serenity::CreateAllowedMentions::new())
+            .ephemeral(true),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Test which auto-responders react to this message
+///
+/// Nothing is posted. Shows which responders match the message and which one would reply to it,
+/// ignoring cooldowns.
+#[poise::command(
+    context_menu_command = "Test auto-responders",
+    default_member_permissions = "MANAGE_MESSAGES",
+    required_permissions = "MANAGE_MESSAGES",
+    category = "Responders",
+    guild_only
+)]
+pub async fn test_message_responders(
+    ctx: Context<'_>,
+    #[description = "Message to test"] message: serenity::Message,
+) -> Result<(), Error> {
+    let responders = dbi::get_all_responders(ctx.guild_id()).await?;
+    ctx.send(
+        poise::CreateReply::default()
+            .content(dry_run(
+                &responders,
+                &message.content,
+                message.channel_id,
+                ctx.guild_id(),
+            ))
+            .allowed_mentions(serenity::CreateAllowedMentions::new())
+            .ephemeral(true),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn phrase_matches(pattern: &str, content: &str) -> bool {
+        compile_pattern(pattern, false).unwrap().is_match(content)
+    }
+
+    #[test]
+    fn phrase_matches_whole_words() {
+        assert!(phrase_matches("get roles", "How do I get roles?"));
+        assert!(phrase_matches("get roles", "get roles"));
+        assert!(!phrase_matches("get roles", "I forget rolesets"));
+    }
+
+    #[test]
+    fn phrase_ignores_case_and_spacing() {
+        assert!(phrase_matches("get roles", "How do I GET   Roles"));
+        assert!(phrase_matches("  get  roles ", "get\nroles"));
+    }
+
+    #[test]
+    fn phrase_is_no_regex() {
+        assert!(phrase_matches("what?", "so what?"));
+        assert!(!phrase_matches("a.c", "abc"));
+        assert!(!phrase_matches("what?", "so wha"));
+    }
+
+    #[test]
+    fn regex_is_used_as_given() {
+        let pattern = compile_pattern(r"^\d+ points?$", true).unwrap();
+        assert!(pattern.is_match("5 points"));
+        assert!(!pattern.is_match("I have 5 points"));
+    }
+
+    #[test]
+    fn refuses_invalid_and_huge_regexes() {
+        assert!(compile_pattern("(unclosed", true).is_err());
+        assert!(compile_pattern(r"\w{1000}\w{1000}\w{1000}", true).is_err());
+    }
+}
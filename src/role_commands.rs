@@ -1,13 +1,20 @@
+use crate::commands_util::{emote_argument, split_into_messages};
+use crate::constants;
 use crate::dbi;
+use crate::embed_tools::paginate_with_embeds;
+use crate::output;
 use crate::types::*;
-use poise::serenity_prelude as serenity;
+use log::{error, warn};
+use poise::serenity_prelude::{self as serenity, Mentionable};
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
 
 /// Role parent command
 ///
 /// This bot allows for assigning user roles via reactions to a message. With the commands in this
-/// category, you can create a custom message, assign different roles different emotes and then
-/// post the message. From that point, the bot will assign roles to users that react with the
-/// emote to said message and it'll remove it when the reaction is removed.
+/// category, you can create role panels with a custom message, assign different roles different
+/// emotes and then post the panels. From that point, the bot will assign roles to users that react
+/// with the emote to a panel and it'll remove it when the reaction is removed.
 #[poise::command(
     slash_command,
     default_member_permissions = "MANAGE_ROLES",
@@ -17,17 +24,206 @@ use poise::serenity_prelude as serenity;
         "show_msg_role",
         "set_msg_role",
         "post_msg_role",
+        "activate_msg_role",
+        "remove_panel_role",
+        "set_group_role",
+        "remove_group_role",
+        "group_role",
+        "list_groups_role",
+        "require_role",
+        "forbid_role",
+        "refresh_role",
+        "reconcile_role",
+        "duration_role",
+        "grant_role",
+        "list_expiries_role",
+        "cancel_expiry_role",
+        "join_role",
+        "sticky_role",
+        "reset_roles",
     )
 )]
 pub async fn role(_ctx: Context<'_>) -> Result<(), Error> {
     Ok(())
 }
 
+async fn autocomplete_panel(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    dbi::get_all_role_messages(ctx.guild_id())
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .map(|p| p.name)
+        .filter(|n| n.to_lowercase().contains(&partial.to_lowercase()))
+        .collect()
+}
+
+async fn autocomplete_group(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    dbi::get_all_role_groups(ctx.guild_id())
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .map(|g| g.name)
+        .filter(|n| n.to_lowercase().contains(&partial.to_lowercase()))
+        .collect()
+}
+
+/// Name of a role of the server for messages to members, falls back to the id of roles that
+/// aren't cached
+pub fn guild_role_name(
+    ctx: &serenity::Context,
+    guildid: Option<serenity::GuildId>,
+    role_id: serenity::RoleId,
+) -> String {
+    guildid
+        .and_then(|gid| ctx.cache.guild(gid))
+        .and_then(|g| g.roles.get(&role_id).map(|r| r.name.to_owned()))
+        .unwrap_or_else(|| role_id.to_string())
+}
+
+/// Removes the reactions of a member for the given roles from the posted role panels, so the
+/// reactions don't claim roles the member no longer has
+pub async fn remove_stale_reactions(
+    ctx: &serenity::Context,
+    guildid: Option<serenity::GuildId>,
+    user_id: serenity::UserId,
+    roles: &[&UserRole],
+) -> Result<(), Error> {
+    if roles.is_empty() {
+        return Ok(());
+    }
+
+    let panels = dbi::get_all_role_messages(guildid).await?;
+    for role in roles.iter() {
+        let posted = panels
+            .iter()
+            .filter(|p| p.name == role.panel && p.active && p.style == RoleMessageStyle::Reactions)
+            .find_map(|p| p.guild_message.as_ref());
+        if let Some(gm) = posted {
+            if let Err(e) = gm
+                .channel_id
+                .delete_reaction(ctx, gm.id, Some(user_id), role.emote.to_owned())
+                .await
+            {
+                error!(
+                    "In {}, role_commands::remove_stale_reactions: couldn't remove reaction for role {}: {}",
+                    guildid.unwrap().get(),
+                    role.guild_role.name,
+                    e
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks that a member with the roles `held` may pick `role`, i.e. that they have all its required
+/// roles and none of its forbidden roles. `role_name` looks up the names for the reason given when
+/// the member may not pick the role
+pub fn check_requirements(
+    role: &UserRole,
+    held: &[serenity::RoleId],
+    role_name: impl Fn(serenity::RoleId) -> String,
+) -> Result<(), String> {
+    let missing: Vec<String> = role
+        .required
+        .iter()
+        .filter(|r| !held.contains(r))
+        .map(|r| role_name(*r))
+        .collect();
+    if !missing.is_empty() {
+        return Err(format!(
+            "You need the role {} to pick it.",
+            missing.join(", ")
+        ));
+    }
+
+    let blocking: Vec<String> = role
+        .forbidden
+        .iter()
+        .filter(|r| held.contains(r))
+        .map(|r| role_name(*r))
+        .collect();
+    if !blocking.is_empty() {
+        return Err(format!(
+            "It can't be picked while you have the role {}.",
+            blocking.join(", ")
+        ));
+    }
+
+    Ok(())
+}
+
+/// Returns the roles a member has to give up when picking `role`, because they are in the same
+/// exclusive group
+pub fn exclusive_conflicts<'a>(
+    role: &UserRole,
+    held: &[serenity::RoleId],
+    all_roles: &'a [UserRole],
+    groups: &[RoleGroup],
+) -> Vec<&'a UserRole> {
+    let exclusive = groups
+        .iter()
+        .any(|g| g.exclusive && Some(&g.name) == role.role_group.as_ref());
+    if !exclusive {
+        return vec![];
+    }
+
+    all_roles
+        .iter()
+        .filter(|r| r.guild_role.id != role.guild_role.id && r.role_group == role.role_group)
+        .filter(|r| held.contains(&r.guild_role.id))
+        .collect()
+}
+
+/// Checks that going from the roles `before` to the roles `after` keeps the member within the
+/// limits of every role group. The maximum only stops members from gaining roles of a group and the
+/// minimum only stops them from dropping roles, so members who are already outside the limits can
+/// still move towards them. Returns the reason if the change isn't allowed
+pub fn check_role_groups(
+    before: &[serenity::RoleId],
+    after: &[serenity::RoleId],
+    all_roles: &[UserRole],
+    groups: &[RoleGroup],
+) -> Result<(), String> {
+    for group in groups.iter() {
+        let count = |held: &[serenity::RoleId]| {
+            all_roles
+                .iter()
+                .filter(|r| r.role_group.as_ref() == Some(&group.name))
+                .filter(|r| held.contains(&r.guild_role.id))
+                .count() as u32
+        };
+        let (count_before, count_after) = (count(before), count(after));
+
+        match (group.max_roles(), group.min) {
+            (Some(max), _) if count_after > count_before && count_after > max => {
+                return Err(match group.exclusive {
+                    true => format!("You can only have one role of the group {}.", group.name),
+                    false => format!(
+                        "You can have at most {} roles of the group {}.",
+                        max, group.name
+                    ),
+                });
+            }
+            (_, Some(min)) if count_after < count_before && count_after < min => {
+                return Err(format!(
+                    "You need at least {} roles of the group {}.",
+                    min, group.name
+                ));
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
 /// Adds a role as user assignable
 ///
-/// With this command you can add a role to the list of roles that users can assign to themselves.
-/// Additionally an emote is associated with the role, as well as a description which explains what
-/// the function of the role is.
+/// With this command you can add a role to the list of roles that users can assign to themselves
+/// on a role panel. Additionally an emote is associated with the role, as well as a description
+/// which explains what the function of the role is.
 #[poise::command(
     slash_command,
     required_permissions = "MANAGE_ROLES",
@@ -37,20 +233,60 @@ pub async fn role(_ctx: Context<'_>) -> Result<(), Error> {
 )]
 pub async fn add_role(
     ctx: Context<'_>,
+    #[description = "Role panel to offer the role on"]
+    #[autocomplete = "autocomplete_panel"]
+    panel: String,
     #[description = "Role on this server"] role: serenity::Role,
-    #[description = "Emote for the role"] emote: serenity::Emoji,
+    #[description = "Emote for the role, custom or Unicode"] emote: String,
     #[description = "Role description"] desc: String,
 ) -> Result<(), Error> {
+    let emote = match emote_argument(ctx, &emote).await? {
+        Some(e) => e,
+        None => {
+            ctx.say("That's neither an emote of this server nor a Unicode emoji.")
+                .await?;
+            return Ok(());
+        }
+    };
+    let cur_panel = match dbi::get_role_message(&panel, ctx.guild_id()).await? {
+        Some(p) => p,
+        None => {
+            ctx.say(format!("{}", DBIError::RolePanelNotFound)).await?;
+            return Ok(());
+        }
+    };
+    let posted_with_components =
+        cur_panel.guild_message.is_some() && cur_panel.style != RoleMessageStyle::Reactions;
+    if posted_with_components
+        && dbi::get_panel_roles(&panel, ctx.guild_id()).await?.len()
+            >= constants::ROLE_COMPONENT_LIMIT
+    {
+        ctx.say(format!(
+            "Buttons and select menus can hold at most {} roles.",
+            constants::ROLE_COMPONENT_LIMIT
+        ))
+        .await?;
+        return Ok(());
+    }
     let ur = UserRole {
         guild_role: role,
         emote,
         desc,
+        panel,
+        role_group: None,
+        required: vec![],
+        forbidden: vec![],
+        duration: None,
     };
 
     match dbi::add_role(ur, ctx.guild_id()).await {
         Ok(ur) => {
-            ctx.say(format!("Role {} added sucessfully!", &ur.guild_role))
-                .await?;
+            ctx.say(format!(
+                "Role {} added sucessfully to the {} panel!",
+                &ur.guild_role, &ur.panel
+            ))
+            .await?;
+            sync_after_change(ctx, &ur.panel).await?;
         }
         Err(e) => {
             ctx.say(format!("{}", e)).await?;
@@ -73,9 +309,18 @@ pub async fn add_role(
 )]
 pub async fn remove_role(
     ctx: Context<'_>,
+    #[description = "Role panel the role is on"]
+    #[autocomplete = "autocomplete_panel"]
+    panel: String,
     #[description = "Role to remove"] role: serenity::Role,
 ) -> Result<(), Error> {
-    let user_role = dbi::get_role(role.id.to_string(), ctx.guild_id()).await?;
+    let user_role = match dbi::get_role(role.id.to_string(), ctx.guild_id()).await {
+        Ok(ur) if ur.panel == panel => ur,
+        _ => {
+            ctx.say(format!("{}", DBIError::RoleNotFound)).await?;
+            return Ok(());
+        }
+    };
 
     match dbi::remove_role(user_role, ctx.guild_id()).await {
         Ok(ur) => {
@@ -84,6 +329,7 @@ pub async fn remove_role(
                 ur.guild_role
             ))
             .await?;
+            sync_after_change(ctx, &ur.panel).await?;
         }
         Err(e) => {
             ctx.say(format!("{}", e)).await?;
@@ -93,10 +339,12 @@ pub async fn remove_role(
     Ok(())
 }
 
-/// Resets all the roles
+/// Removes roles that were deleted from the server
 ///
-/// This command removes all the roles from the role message this is mostly for the
-/// purpose of getting rid of roles that have already been deleted from the server.
+/// This command removes the roles that were deleted from the server, or whose emote was, from the
+/// role panels and drops deleted roles from the requirements, the join roles and the timed roles.
+/// The bot does this on its own when roles or emotes are deleted, so this is mostly for the purpose
+/// of catching up with deletions that happened while it was offline.
 #[poise::command(
     slash_command,
     required_permissions = "MANAGE_ROLES",
@@ -105,24 +353,119 @@ pub async fn remove_role(
     guild_only
 )]
 pub async fn reset_roles(ctx: Context<'_>) -> Result<(), Error> {
-    let removed_roles = dbi::remove_all_roles(ctx.guild_id()).await?;
-    let removed_roles: String = removed_roles
-        .iter()
-        .map(|r| r.guild_role.name.to_owned())
-        .collect::<Vec<String>>()
-        .join(" ");
+    let changes = purge_deleted_roles(ctx.serenity_context(), ctx.guild_id().unwrap()).await?;
 
-    if removed_roles.len() > 0 {
-        ctx.say(removed_roles).await?;
+    if changes.is_empty() {
+        ctx.say("All roles and emotes still exist, nothing was removed.")
+            .await?;
+    }
+    for message in split_into_messages(&changes) {
+        ctx.say(message).await?;
     }
 
     Ok(())
 }
 
-/// Sets the text for the role selection message
+/// Removes the self-assignable roles whose role or emote was deleted from the server and drops
+/// deleted roles from requirements, join roles and role expiries. Emotes that were renamed are
+/// updated. Returns a line for every change, for the moderation log
+pub async fn purge_deleted_roles(
+    ctx: &serenity::Context,
+    guildid: serenity::GuildId,
+) -> Result<Vec<String>, Error> {
+    let gid = Some(guildid);
+    let guild_roles = guildid.roles(ctx).await?;
+    let guild_emojis: HashMap<serenity::EmojiId, serenity::Emoji> = guildid
+        .emojis(ctx)
+        .await?
+        .into_iter()
+        .map(|e| (e.id, e))
+        .collect();
+    let mut changes: Vec<String> = vec![];
+    let mut changed_panels: HashSet<String> = HashSet::new();
+
+    for ur in dbi::get_all_roles(gid).await? {
+        let emote = match &ur.emote {
+            ReactionEmote::Custom(e) => Some(guild_emojis.get(&e.id)),
+            ReactionEmote::Unicode(_) => None,
+        };
+        if !guild_roles.contains_key(&ur.guild_role.id) {
+            changes.push(format!(
+                "The role **{}** was deleted, so it was removed from the role panel \"{}\".",
+                ur.guild_role.name, ur.panel
+            ));
+        } else if let Some(None) = emote {
+            changes.push(format!(
+                "The emote of the role **{}** was deleted, so the role was removed from the role panel \"{}\". Add it again with a new emote with `/role add`.",
+                ur.guild_role.name, ur.panel
+            ));
+        } else {
+            let mut updated = ur.to_owned();
+            updated.required.retain(|id| guild_roles.contains_key(id));
+            updated.forbidden.retain(|id| guild_roles.contains_key(id));
+            let mut changed = updated.required.len() != ur.required.len()
+                || updated.forbidden.len() != ur.forbidden.len();
+            if let (ReactionEmote::Custom(old), Some(Some(cur))) = (&ur.emote, emote) {
+                if old.name != cur.name {
+                    updated.emote = ReactionEmote::Custom(Box::new(cur.to_owned()));
+                    changed_panels.insert(ur.panel.to_owned());
+                    changed = true;
+                }
+            }
+            if changed {
+                dbi::update_role(updated, gid).await?;
+            }
+            continue;
+        }
+        changed_panels.insert(ur.panel.to_owned());
+        dbi::remove_role(ur, gid).await?;
+    }
+
+    let mut join_config = dbi::get_join_role_config(gid).await?;
+    let join_roles = join_config.roles.len();
+    join_config.roles.retain(|id| guild_roles.contains_key(id));
+    if join_config.roles.len() != join_roles {
+        changes.push(format!(
+            "Removed {} deleted role(s) from the roles new members get.",
+            join_roles - join_config.roles.len()
+        ));
+        dbi::set_join_role_config(join_config, gid).await?;
+    }
+
+    for expiry in dbi::get_role_expiries(gid).await? {
+        if !guild_roles.contains_key(&expiry.role.id) {
+            dbi::remove_role_expiry(&expiry.expiry_id, gid).await?;
+            changes.push(format!(
+                "The role **{}** of {} was deleted, so its expiry was cancelled.",
+                expiry.role.name, expiry.user.name
+            ));
+        }
+    }
+
+    for panel in changed_panels {
+        if let Err(e) = sync_role_panel(ctx, &panel, gid).await {
+            changes.push(format!(
+                "The posted message of the role panel \"{}\" couldn't be updated: {}",
+                panel, e
+            ));
+        }
+    }
+    if !changes.is_empty() {
+        warn!(
+            "In {}, role_commands::purge_deleted_roles: {} changes",
+            guildid.get(),
+            changes.len()
+        );
+    }
+
+    Ok(changes)
+}
+
+/// Sets the text for a role panel
 ///
-/// With this command the text shown in the role selection message can be set. This text will then
-/// show up on the message which lets the users select their roles.
+/// With this command the text shown on a role panel can be set. This text will then show up on the
+/// message which lets the users select their roles. If there is no panel with the name yet, a new
+/// panel is created.
 #[poise::command(
     slash_command,
     required_permissions = "MANAGE_ROLES",
@@ -132,11 +475,22 @@ pub async fn reset_roles(ctx: Context<'_>) -> Result<(), Error> {
 )]
 pub async fn set_msg_role(
     ctx: Context<'_>,
+    #[description = "Name of the role panel"]
+    #[autocomplete = "autocomplete_panel"]
+    #[max_length = 100]
+    panel: String,
     #[description = "Desired message text"] msg: String,
 ) -> Result<(), Error> {
-    match dbi::set_role_message(msg, ctx.author(), ctx.guild_id()).await {
+    let panel = panel.trim();
+    if panel.is_empty() {
+        ctx.say("The panel name can't be empty.").await?;
+        return Ok(());
+    }
+
+    match dbi::set_role_message(panel, msg, ctx.author(), ctx.guild_id()).await {
         Ok(_) => {
-            ctx.say(format!("Role message set successfully.")).await?;
+            ctx.say("Role message set successfully.").await?;
+            sync_after_change(ctx, panel).await?;
         }
         Err(e) => {
             ctx.say(format!("{}", e)).await?;
@@ -146,9 +500,9 @@ pub async fn set_msg_role(
     Ok(())
 }
 
-/// Shows the currently set role message
+/// Shows a role panel
 ///
-/// With this commmand you can print the currently set up role message.
+/// With this commmand you can print the text and the roles of a role panel.
 #[poise::command(
     slash_command,
     required_permissions = "MANAGE_ROLES",
@@ -156,29 +510,31 @@ pub async fn set_msg_role(
     rename = "message_show",
     guild_only
 )]
-pub async fn show_msg_role(ctx: Context<'_>) -> Result<(), Error> {
-    let cur_roles = dbi::get_all_roles(ctx.guild_id()).await?;
+pub async fn show_msg_role(
+    ctx: Context<'_>,
+    #[description = "Role panel to show"]
+    #[autocomplete = "autocomplete_panel"]
+    panel: String,
+) -> Result<(), Error> {
+    let cur_roles = dbi::get_panel_roles(&panel, ctx.guild_id()).await?;
     let roles_string: String = cur_roles
         .iter()
-        .map(|r| String::from(format!("{} {}: {}\n", r.emote, r.guild_role, r.desc)))
+        .map(|r| format!("{} {}: {}\n", r.emote, r.guild_role, r.desc))
         .collect();
-    match dbi::get_role_message(ctx.guild_id()).await? {
+    match dbi::get_role_message(&panel, ctx.guild_id()).await? {
         Some(msg) => {
             ctx.send(
                 poise::CreateReply::default().embed(
                     serenity::CreateEmbed::default()
-                        .title("Current role message")
+                        .title(format!("Role panel {}", msg.name))
                         .description(&msg.messagetext)
                         .field("Roles", roles_string, false)
                         .field(
                             "Message link",
-                            format!(
-                                "{}",
-                                match msg.guild_message {
-                                    Some(msg) => msg.link(),
-                                    None => "None".to_string(),
-                                }
-                            ),
+                            match msg.guild_message {
+                                Some(msg) => msg.link(),
+                                None => "None".to_string(),
+                            },
                             true,
                         )
                         .field("Is active", msg.active.to_string(), true)
@@ -200,19 +556,186 @@ pub async fn show_msg_role(ctx: Context<'_>) -> Result<(), Error> {
             .await?;
         }
         None => {
-            ctx.say(format!("No role message set on this server"))
-                .await?;
+            ctx.say(format!("{}", DBIError::RolePanelNotFound)).await?;
+        }
+    };
+
+    Ok(())
+}
+
+/// Builds the buttons or the select menu for picking roles on a role panel. The custom ids only
+/// depend on the roles, so the components keep working after the bot restarts
+pub fn role_components(
+    roles: &[UserRole],
+    style: RoleMessageStyle,
+) -> Vec<serenity::CreateActionRow> {
+    match style {
+        _ if roles.is_empty() => vec![],
+        RoleMessageStyle::Reactions => vec![],
+        RoleMessageStyle::Buttons => roles
+            .chunks(5)
+            .map(|chunk| {
+                serenity::CreateActionRow::Buttons(
+                    chunk
+                        .iter()
+                        .map(|r| {
+                            serenity::CreateButton::new(format!(
+                                "{}:button:{}",
+                                constants::ROLE_MESSAGE_ID,
+                                r.guild_role.id
+                            ))
+                            .style(serenity::ButtonStyle::Secondary)
+                            .label(&r.guild_role.name)
+                            .emoji(r.emote.to_owned())
+                        })
+                        .collect(),
+                )
+            })
+            .collect(),
+        RoleMessageStyle::SelectMenu => {
+            let options: Vec<serenity::CreateSelectMenuOption> = roles
+                .iter()
+                .map(|r| {
+                    let mut option = serenity::CreateSelectMenuOption::new(
+                        &r.guild_role.name,
+                        r.guild_role.id.to_string(),
+                    )
+                    .emoji(r.emote.to_owned());
+                    if !r.desc.is_empty() {
+                        option = option.description(r.desc.chars().take(100).collect::<String>());
+                    }
+                    option
+                })
+                .collect();
+            vec![serenity::CreateActionRow::SelectMenu(
+                serenity::CreateSelectMenu::new(
+                    // The panel is looked up by the message, so the id doesn't need its name and
+                    // stays below the length limit of custom ids
+                    format!("{}:select:roles", constants::ROLE_MESSAGE_ID),
+                    serenity::CreateSelectMenuKind::String { options },
+                )
+                .placeholder("Select your roles")
+                .min_values(0)
+                .max_values(roles.len() as u8),
+            )]
         }
+    }
+}
+
+/// Text of a posted role panel with the list of its roles
+fn panel_text(panel: &RoleMessage, roles: &[UserRole], style: RoleMessageStyle) -> String {
+    let role_list: String = roles
+        .iter()
+        .map(|r| format!("{} {}: {}\n", r.emote, r.guild_role, r.desc))
+        .collect();
+
+    format!(
+        "# {}\n\
+{}\n\
+## Available roles\n\
+{}",
+        match style {
+            RoleMessageStyle::Reactions => "Reaction roles",
+            _ => "Roles",
+        },
+        panel.messagetext,
+        role_list
+    )
+}
+
+/// Brings the posted message of a role panel up to date with the text and roles of the panel. The
+/// message is edited in place and, for panels that use reactions, the reactions of removed roles
+/// are taken off and the ones of new roles are added. Returns false if the panel isn't posted
+pub async fn sync_role_panel(
+    ctx: &serenity::Context,
+    panel: &str,
+    guildid: Option<serenity::GuildId>,
+) -> Result<bool, Error> {
+    let (panel, gm) = match dbi::get_role_message(panel, guildid).await? {
+        Some(p) => match p.guild_message.to_owned() {
+            Some(gm) => (p, gm),
+            None => return Ok(false),
+        },
+        None => return Err(Box::new(DBIError::RolePanelNotFound)),
     };
+    let roles = dbi::get_panel_roles(&panel.name, guildid).await?;
+
+    let guild_message = gm
+        .channel_id
+        .edit_message(
+            ctx,
+            gm.id,
+            output::user_text_edit(&panel_text(&panel, &roles, panel.style), guildid)
+                .await?
+                .components(role_components(&roles, panel.style)),
+        )
+        .await?;
+
+    if panel.style == RoleMessageStyle::Reactions {
+        for reaction in guild_message.reactions.iter().filter(|r| r.me) {
+            if roles
+                .iter()
+                .any(|r| r.emote.matches(&reaction.reaction_type))
+            {
+                continue;
+            }
+            // Taking off the reactions of all members needs the manage messages permission, so
+            // the bot at least takes off its own reaction if it can't
+            if let Err(e) = gm
+                .channel_id
+                .delete_reaction_emoji(ctx, gm.id, reaction.reaction_type.to_owned())
+                .await
+            {
+                warn!(
+                    "In {}, role_commands::sync_role_panel: couldn't remove all reactions {}: {}",
+                    guildid.unwrap().get(),
+                    reaction.reaction_type,
+                    e
+                );
+                gm.channel_id
+                    .delete_reaction(ctx, gm.id, None, reaction.reaction_type.to_owned())
+                    .await?;
+            }
+        }
+        for role in roles.iter() {
+            let reacted = guild_message
+                .reactions
+                .iter()
+                .any(|r| r.me && role.emote.matches(&r.reaction_type));
+            if !reacted {
+                guild_message.react(ctx, role.emote.to_owned()).await?;
+            }
+        }
+    }
+    warn!(
+        "In {}, role_commands::sync_role_panel: updated posted role panel {}",
+        guildid.unwrap().get(),
+        panel.name
+    );
+
+    Ok(true)
+}
+
+/// Keeps the posted role panel in sync after a command changed it. The change itself already
+/// went through, so failing to update the message is only reported
+async fn sync_after_change(ctx: Context<'_>, panel: &str) -> Result<(), Error> {
+    if let Err(e) = sync_role_panel(ctx.serenity_context(), panel, ctx.guild_id()).await {
+        ctx.say(format!(
+            "The posted message of the role panel couldn't be updated: {}",
+            e
+        ))
+        .await?;
+    }
 
     Ok(())
 }
 
-/// Post the role message in the specified chat
+/// Post a role panel in the specified chat
 ///
-/// This command will attempt to post the full role message in the specified channel. It will
-/// contain the role message and all the roles with their correspoding emotes. The reactions will
-/// be aded automatically and from that point on, the reaction roles will be active.
+/// This command will attempt to post the full role panel in the specified channel. It will
+/// contain the panel text and all the roles of the panel with their correspoding emotes. Depending
+/// on the style, members pick their roles with reactions, buttons or a select menu. Reactions are
+/// added automatically and from that point on, the roles will be assigned.
 #[poise::command(
     slash_command,
     required_permissions = "MANAGE_ROLES",
@@ -222,48 +745,71 @@ pub async fn show_msg_role(ctx: Context<'_>) -> Result<(), Error> {
 )]
 pub async fn post_msg_role(
     ctx: Context<'_>,
+    #[description = "Role panel to post"]
+    #[autocomplete = "autocomplete_panel"]
+    panel: String,
     #[description = "Channel to post in"] channel: serenity::Channel,
+    #[description = "How members pick their roles, reactions by default"] style: Option<
+        RoleMessageStyle,
+    >,
 ) -> Result<(), Error> {
+    let style = style.unwrap_or_default();
     // First we get the components we need to build the message for the current server
-    let cur_message = dbi::get_role_message(ctx.guild_id()).await?;
-    let cur_roles = dbi::get_all_roles(ctx.guild_id()).await?;
+    let cur_message = dbi::get_role_message(&panel, ctx.guild_id()).await?;
+    let cur_roles = dbi::get_panel_roles(&panel, ctx.guild_id()).await?;
+
+    if style != RoleMessageStyle::Reactions && cur_roles.len() > constants::ROLE_COMPONENT_LIMIT {
+        ctx.say(format!(
+            "Buttons and select menus can hold at most {} roles.",
+            constants::ROLE_COMPONENT_LIMIT
+        ))
+        .await?;
+        return Ok(());
+    }
 
     match (cur_message, &cur_roles) {
-        (Some(msg), roles) if roles.len() > 0 => {
+        (Some(msg), roles) if !roles.is_empty() => {
             // At this point we know we have a message and a list of roles that has a least one
             // role
-            let role_list: String = roles
-                .iter()
-                .map(|r| String::from(format!("{} {}: {}\n", r.emote, r.guild_role, r.desc)))
-                .collect();
-            let message: String = String::from(format!(
-                "# Reaction roles\n\
-{}\n\
-## Available roles\n\
-{}",
-                msg.messagetext, role_list
-            ));
+            let message = panel_text(&msg, roles, style);
 
-            let sent_message = channel.id().say(ctx.http(), message).await?;
-            for role in cur_roles.iter() {
-                sent_message.react(ctx, role.emote.to_owned()).await?;
-            }
-            dbi::set_active_role_message(&msg, sent_message, true, ctx.author(), ctx.guild_id())
+            let sent_message = channel
+                .id()
+                .send_message(
+                    ctx.http(),
+                    output::user_text_message(&message, ctx.guild_id())
+                        .await?
+                        .components(role_components(roles, style)),
+                )
                 .await?;
+            if style == RoleMessageStyle::Reactions {
+                for role in cur_roles.iter() {
+                    sent_message.react(ctx, role.emote.to_owned()).await?;
+                }
+            }
+            dbi::set_active_role_message(
+                &msg,
+                sent_message,
+                true,
+                style,
+                ctx.author(),
+                ctx.guild_id(),
+            )
+            .await?;
 
             ctx.say("Message posted sucessfully.").await?;
         }
         _ => {
-            ctx.say("Make sure that a role message is set and that at least one role was selected as a user assignable role.").await?;
+            ctx.say("Make sure that the role panel exists and that at least one role was added to it as a user assignable role.").await?;
         }
     };
 
     Ok(())
 }
 
-/// Set the active state of the role message
+/// Set the active state of a role panel
 ///
-/// This command let's you set the active state of the role message. If it is set to true, the bot
+/// This command let's you set the active state of a role panel. If it is set to true, the bot
 /// will assign roles upon reaction, and if it's set to false, it won't.
 #[poise::command(
     slash_command,
@@ -274,18 +820,30 @@ pub async fn post_msg_role(
 )]
 pub async fn activate_msg_role(
     ctx: Context<'_>,
+    #[description = "Role panel to change"]
+    #[autocomplete = "autocomplete_panel"]
+    panel: String,
     #[description = "New state"] state: bool,
 ) -> Result<(), Error> {
-    if let Some(cur_msg) = dbi::get_role_message(ctx.guild_id()).await?.as_ref() {
+    if let Some(cur_msg) = dbi::get_role_message(&panel, ctx.guild_id())
+        .await?
+        .as_ref()
+    {
         if let Some(guild_msg) = &cur_msg.guild_message {
             dbi::set_active_role_message(
                 cur_msg,
                 guild_msg.to_owned(),
                 state,
+                cur_msg.style,
                 ctx.author(),
                 ctx.guild_id(),
             )
             .await?;
+            ctx.say(match state {
+                true => format!("Role panel {} now assigns roles.", cur_msg.name),
+                false => format!("Role panel {} no longer assigns roles.", cur_msg.name),
+            })
+            .await?;
         } else {
             ctx.say("Role message exists but it's not posted anywhere. Post it to a channel first using the `/role post` command first before attempting to change its status.").await?;
         }
@@ -295,3 +853,1412 @@ pub async fn activate_msg_role(
 
     Ok(())
 }
+
+/// Removes a role panel
+///
+/// This command removes a role panel together with the roles offered on it. A posted panel stops
+/// assigning roles, the message itself is left in the channel.
+#[poise::command(
+    slash_command,
+    required_permissions = "MANAGE_ROLES",
+    category = "Roles",
+    rename = "panel_remove",
+    guild_only
+)]
+pub async fn remove_panel_role(
+    ctx: Context<'_>,
+    #[description = "Role panel to remove"]
+    #[autocomplete = "autocomplete_panel"]
+    panel: String,
+) -> Result<(), Error> {
+    match dbi::remove_role_message(&panel, ctx.guild_id()).await {
+        Ok(removed) => {
+            ctx.say(format!("Role panel {} removed.", removed.name))
+                .await?;
+        }
+        Err(e) => {
+            ctx.say(format!("{}", e)).await?;
+        }
+    };
+
+    Ok(())
+}
+
+/// Creates or changes a role group
+///
+/// Role groups put limits on how many roles of the group a member can have. In an exclusive group,
+/// members can only have one role, and picking another role of the group replaces it. Optionally,
+/// a minimum and a maximum number of roles can be set.
+#[poise::command(
+    slash_command,
+    required_permissions = "MANAGE_ROLES",
+    category = "Roles",
+    rename = "group_set",
+    guild_only
+)]
+pub async fn set_group_role(
+    ctx: Context<'_>,
+    #[description = "Name of the role group"]
+    #[autocomplete = "autocomplete_group"]
+    #[max_length = 100]
+    name: String,
+    #[description = "Members can only have one role of the group"] exclusive: bool,
+    #[description = "Least number of roles a member has to keep"] min: Option<u32>,
+    #[description = "Most roles a member can have"] max: Option<u32>,
+) -> Result<(), Error> {
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        ctx.say("The group name can't be empty.").await?;
+        return Ok(());
+    }
+    let group = RoleGroup {
+        name,
+        exclusive,
+        min,
+        max,
+    };
+    match (group.min, group.max_roles()) {
+        (Some(min), Some(max)) if min > max => {
+            ctx.say(format!(
+                "The minimum can't be above the maximum of {} roles.",
+                max
+            ))
+            .await?;
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    match dbi::set_role_group(group, ctx.guild_id()).await {
+        Ok(g) => {
+            ctx.say(format!("Role group {} saved.", g.name)).await?;
+        }
+        Err(e) => {
+            ctx.say(format!("{}", e)).await?;
+        }
+    };
+
+    Ok(())
+}
+
+/// Removes a role group
+///
+/// The roles of the group stay user assignable, but they no longer belong to any group.
+#[poise::command(
+    slash_command,
+    required_permissions = "MANAGE_ROLES",
+    category = "Roles",
+    rename = "group_remove",
+    guild_only
+)]
+pub async fn remove_group_role(
+    ctx: Context<'_>,
+    #[description = "Role group to remove"]
+    #[autocomplete = "autocomplete_group"]
+    name: String,
+) -> Result<(), Error> {
+    match dbi::remove_role_group(&name, ctx.guild_id()).await {
+        Ok(g) => {
+            ctx.say(format!("Role group {} removed.", g.name)).await?;
+        }
+        Err(e) => {
+            ctx.say(format!("{}", e)).await?;
+        }
+    };
+
+    Ok(())
+}
+
+/// Puts a role into a role group
+///
+/// Puts a user assignable role into a role group, so the limits of the group apply to it. Leave
+/// out the group to take the role out of its group.
+#[poise::command(
+    slash_command,
+    required_permissions = "MANAGE_ROLES",
+    category = "Roles",
+    rename = "group_role",
+    guild_only
+)]
+pub async fn group_role(
+    ctx: Context<'_>,
+    #[description = "Role panel the role is on"]
+    #[autocomplete = "autocomplete_panel"]
+    panel: String,
+    #[description = "Role to put into the group"] role: serenity::Role,
+    #[description = "Role group, leave out to remove the role from its group"]
+    #[autocomplete = "autocomplete_group"]
+    group: Option<String>,
+) -> Result<(), Error> {
+    let user_role = match dbi::get_role(role.id.to_string(), ctx.guild_id()).await {
+        Ok(ur) if ur.panel == panel => ur,
+        _ => {
+            ctx.say(format!("{}", DBIError::RoleNotFound)).await?;
+            return Ok(());
+        }
+    };
+    if let Some(name) = &group {
+        if let Err(e) = dbi::get_role_group(name, ctx.guild_id()).await {
+            ctx.say(format!("{}", e)).await?;
+            return Ok(());
+        }
+    }
+
+    let updated = dbi::update_role(
+        UserRole {
+            role_group: group,
+            ..user_role
+        },
+        ctx.guild_id(),
+    )
+    .await?;
+    match &updated.role_group {
+        Some(g) => {
+            ctx.say(format!(
+                "Role {} is now in the group {}.",
+                updated.guild_role, g
+            ))
+            .await?
+        }
+        None => {
+            ctx.say(format!(
+                "Role {} is no longer in a group.",
+                updated.guild_role
+            ))
+            .await?
+        }
+    };
+
+    Ok(())
+}
+
+/// Lists the role groups
+///
+/// Shows all the role groups of the server with their limits and roles.
+#[poise::command(
+    slash_command,
+    required_permissions = "MANAGE_ROLES",
+    category = "Roles",
+    rename = "groups",
+    guild_only
+)]
+pub async fn list_groups_role(ctx: Context<'_>) -> Result<(), Error> {
+    let groups = dbi::get_all_role_groups(ctx.guild_id()).await?;
+    if groups.is_empty() {
+        ctx.say("There are no role groups on this server.").await?;
+        return Ok(());
+    }
+    let all_roles = dbi::get_all_roles(ctx.guild_id()).await?;
+
+    let mut embed = serenity::CreateEmbed::default()
+        .title("Role groups")
+        .colour(serenity::Colour::BLUE)
+        .footer(serenity::CreateEmbedFooter::new(format!(
+            "Requested by: {}",
+            ctx.author().name
+        )));
+    for group in groups.iter() {
+        let limits = match (group.exclusive, group.min, group.max) {
+            (true, Some(min), _) if min > 0 => "exactly one role".to_string(),
+            (true, _, _) => "at most one role".to_string(),
+            (false, None, None) => "no limits".to_string(),
+            (false, Some(min), None) => format!("at least {} roles", min),
+            (false, None, Some(max)) => format!("at most {} roles", max),
+            (false, Some(min), Some(max)) => format!("{} to {} roles", min, max),
+        };
+        let roles: String = all_roles
+            .iter()
+            .filter(|r| r.role_group.as_ref() == Some(&group.name))
+            .map(|r| format!("{} {}\n", r.emote, r.guild_role))
+            .collect();
+        embed = embed.field(
+            format!("{} ({})", group.name, limits),
+            match roles.is_empty() {
+                true => "No roles".to_string(),
+                false => roles,
+            },
+            false,
+        );
+    }
+    ctx.send(poise::CreateReply::default().embed(embed)).await?;
+
+    Ok(())
+}
+
+fn requirements_summary(role: &UserRole) -> String {
+    let mention_all = |roles: &[serenity::RoleId]| {
+        roles
+            .iter()
+            .map(|r| r.mention().to_string())
+            .collect::<Vec<String>>()
+            .join(", ")
+    };
+    let mut rules: Vec<String> = Vec::new();
+    if !role.required.is_empty() {
+        rules.push(format!("Requires: {}", mention_all(&role.required)));
+    }
+    if !role.forbidden.is_empty() {
+        rules.push(format!("Not with: {}", mention_all(&role.forbidden)));
+    }
+
+    match rules.is_empty() {
+        true => String::from("Anyone can pick it"),
+        false => rules.join("\n"),
+    }
+}
+
+/// Applies a change to the requirements of a user assignable role and reports the new requirements
+async fn change_role_requirements(
+    ctx: Context<'_>,
+    panel: &str,
+    role: &serenity::Role,
+    change: impl FnOnce(&mut UserRole),
+) -> Result<(), Error> {
+    let mut user_role = match dbi::get_role(role.id.to_string(), ctx.guild_id()).await {
+        Ok(ur) if ur.panel == panel => ur,
+        _ => {
+            ctx.say(format!("{}", DBIError::RoleNotFound)).await?;
+            return Ok(());
+        }
+    };
+    change(&mut user_role);
+
+    match dbi::update_role(user_role, ctx.guild_id()).await {
+        Ok(ur) => {
+            ctx.send(
+                poise::CreateReply::default()
+                    .content(format!(
+                        "Role {}:\n{}",
+                        ur.guild_role.mention(),
+                        requirements_summary(&ur)
+                    ))
+                    .allowed_mentions(serenity::CreateAllowedMentions::new()),
+            )
+            .await?;
+        }
+        Err(e) => {
+            ctx.say(format!("{}", e)).await?;
+        }
+    };
+
+    Ok(())
+}
+
+/// Require another role to pick a role
+///
+/// Members need all the required roles, e.g. a "Verified" role, to pick the role. Set `required` to
+/// false to remove the role from the requirements again.
+#[poise::command(
+    slash_command,
+    required_permissions = "MANAGE_ROLES",
+    category = "Roles",
+    rename = "require",
+    guild_only
+)]
+pub async fn require_role(
+    ctx: Context<'_>,
+    #[description = "Role panel the role is on"]
+    #[autocomplete = "autocomplete_panel"]
+    panel: String,
+    #[description = "Role to restrict"] role: serenity::Role,
+    #[description = "Role members need to have"] other: serenity::Role,
+    #[description = "Whether the role is required"] required: bool,
+) -> Result<(), Error> {
+    change_role_requirements(ctx, &panel, &role, |ur| {
+        ur.required.retain(|r| *r != other.id);
+        if required {
+            ur.required.push(other.id);
+        }
+    })
+    .await
+}
+
+/// Keep members with another role from picking a role
+///
+/// Members that have any of the forbidden roles, e.g. a "Muted" role, can't pick the role. Set
+/// `forbidden` to false to remove the role from the list again.
+#[poise::command(
+    slash_command,
+    required_permissions = "MANAGE_ROLES",
+    category = "Roles",
+    rename = "forbid",
+    guild_only
+)]
+pub async fn forbid_role(
+    ctx: Context<'_>,
+    #[description = "Role panel the role is on"]
+    #[autocomplete = "autocomplete_panel"]
+    panel: String,
+    #[description = "Role to restrict"] role: serenity::Role,
+    #[description = "Role that keeps members from picking it"] other: serenity::Role,
+    #[description = "Whether the role is forbidden"] forbidden: bool,
+) -> Result<(), Error> {
+    change_role_requirements(ctx, &panel, &role, |ur| {
+        ur.forbidden.retain(|r| *r != other.id);
+        if forbidden {
+            ur.forbidden.push(other.id);
+        }
+    })
+    .await
+}
+
+/// Updates a posted role panel
+///
+/// The posted message of a role panel is updated whenever its text or roles change. This command
+/// updates the message and its reactions manually, e.g. after reactions were cleared by accident.
+#[poise::command(
+    slash_command,
+    required_permissions = "MANAGE_ROLES",
+    category = "Roles",
+    rename = "refresh",
+    guild_only
+)]
+pub async fn refresh_role(
+    ctx: Context<'_>,
+    #[description = "Role panel to update"]
+    #[autocomplete = "autocomplete_panel"]
+    panel: String,
+) -> Result<(), Error> {
+    match sync_role_panel(ctx.serenity_context(), &panel, ctx.guild_id()).await {
+        Ok(true) => {
+            ctx.say(format!("Role panel {} updated.", panel)).await?;
+        }
+        Ok(false) => {
+            ctx.say("The role panel isn't posted anywhere. Post it using the `/role post` command first.").await?;
+        }
+        Err(e) => {
+            ctx.say(format!("{}", e)).await?;
+        }
+    };
+
+    Ok(())
+}
+
+/// Changes that bring the roles of the members in line with the reactions on role panels
+#[derive(Default)]
+pub struct ReconcileReport {
+    /// Member and role name of every role that was (or would be) added
+    pub added: Vec<(String, String)>,
+    /// Member and role name of every role that was (or would be) removed
+    pub removed: Vec<(String, String)>,
+    /// Member name and reason for every member whose roles were left alone
+    pub skipped: Vec<(String, String)>,
+}
+
+impl ReconcileReport {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.skipped.is_empty()
+    }
+}
+
+/// All the members who reacted with the emote on the message, except for bots
+async fn reaction_users(
+    ctx: &serenity::Context,
+    message: &serenity::Message,
+    emote: &ReactionEmote,
+) -> Result<HashSet<serenity::UserId>, Error> {
+    let mut users: HashSet<serenity::UserId> = HashSet::new();
+    let mut after: Option<serenity::UserId> = None;
+    loop {
+        let page = message
+            .channel_id
+            .reaction_users(ctx, message.id, emote.to_owned(), Some(100), after)
+            .await?;
+        after = page.last().map(|u| u.id);
+        let page_len = page.len();
+        users.extend(page.into_iter().filter(|u| !u.bot).map(|u| u.id));
+        if page_len < 100 {
+            break;
+        }
+    }
+
+    Ok(users)
+}
+
+async fn all_members(
+    ctx: &serenity::Context,
+    guildid: serenity::GuildId,
+) -> Result<Vec<serenity::Member>, Error> {
+    let mut members: Vec<serenity::Member> = Vec::new();
+    let mut after: Option<serenity::UserId> = None;
+    loop {
+        let page = guildid.members(ctx, Some(1000), after).await?;
+        after = page.last().map(|m| m.user.id);
+        let page_len = page.len();
+        members.extend(page);
+        if page_len < 1000 {
+            break;
+        }
+    }
+
+    Ok(members)
+}
+
+/// Gives a member a panel role they reacted for and starts its timer
+async fn reconcile_add(
+    ctx: &serenity::Context,
+    member: &serenity::Member,
+    role: &UserRole,
+    guildid: serenity::GuildId,
+) -> Result<(), Error> {
+    member.add_role(ctx, role.guild_role.id).await?;
+    start_role_timer(&member.user, role, Some(guildid)).await?;
+
+    Ok(())
+}
+
+/// Takes a panel role away from a member who doesn't react for it and stops its timer
+async fn reconcile_remove(
+    ctx: &serenity::Context,
+    member: &serenity::Member,
+    role: &UserRole,
+    guildid: serenity::GuildId,
+) -> Result<(), Error> {
+    member.remove_role(ctx, role.guild_role.id).await?;
+    stop_role_timer(member.user.id, role.guild_role.id, Some(guildid)).await?;
+
+    Ok(())
+}
+
+/// Compares the reactions on a posted role panel with the roles the members have. Members who
+/// reacted get the role and members who hold the role without a reaction lose it, as long as the
+/// requirements and role groups allow it. Roles that are join roles or that run out at a set time
+/// are never taken away, since members get those without reacting. Changes that fail are reported
+/// as skipped. Nothing is changed on a dry run
+pub async fn reconcile_role_panel(
+    ctx: &serenity::Context,
+    panel: &RoleMessage,
+    dry_run: bool,
+    guildid: serenity::GuildId,
+) -> Result<ReconcileReport, Error> {
+    let mut report = ReconcileReport::default();
+    let gm = match (&panel.guild_message, panel.style) {
+        (Some(gm), RoleMessageStyle::Reactions) => gm,
+        _ => return Ok(report),
+    };
+
+    let all_roles = dbi::get_all_roles(Some(guildid)).await?;
+    let groups = dbi::get_all_role_groups(Some(guildid)).await?;
+    let join_roles = dbi::get_join_role_config(Some(guildid)).await?.roles;
+    let now = serenity::Timestamp::now();
+    let timed: HashSet<(serenity::UserId, serenity::RoleId)> =
+        dbi::get_role_expiries(Some(guildid))
+            .await?
+            .into_iter()
+            .filter(|e| e.expires_at > now)
+            .map(|e| (e.user.id, e.role.id))
+            .collect();
+    let panel_roles: Vec<&UserRole> = all_roles.iter().filter(|r| r.panel == panel.name).collect();
+    let mut reacted: HashMap<serenity::RoleId, HashSet<serenity::UserId>> = HashMap::new();
+    for role in panel_roles.iter() {
+        reacted.insert(
+            role.guild_role.id,
+            reaction_users(ctx, gm, &role.emote).await?,
+        );
+    }
+    let has_reacted = |role: &UserRole, member: &serenity::Member| {
+        reacted
+            .get(&role.guild_role.id)
+            .is_some_and(|users| users.contains(&member.user.id))
+    };
+
+    for member in all_members(ctx, guildid).await?.iter() {
+        if member.user.bot {
+            continue;
+        }
+        let (to_add, mut to_remove): (Vec<&UserRole>, Vec<&UserRole>) = panel_roles
+            .iter()
+            .copied()
+            .filter(|r| has_reacted(r, member) != member.roles.contains(&r.guild_role.id))
+            .partition(|r| has_reacted(r, member));
+        to_remove.retain(|r| {
+            !join_roles.contains(&r.guild_role.id)
+                && !timed.contains(&(member.user.id, r.guild_role.id))
+        });
+        if to_add.is_empty() && to_remove.is_empty() {
+            continue;
+        }
+
+        let after: Vec<serenity::RoleId> = member
+            .roles
+            .iter()
+            .filter(|id| !to_remove.iter().any(|r| r.guild_role.id == **id))
+            .copied()
+            .chain(to_add.iter().map(|r| r.guild_role.id))
+            .collect();
+        let allowed = to_add
+            .iter()
+            .try_for_each(|ur| {
+                check_requirements(ur, &member.roles, |id| {
+                    guild_role_name(ctx, Some(guildid), id)
+                })
+            })
+            .and_then(|_| check_role_groups(&member.roles, &after, &all_roles, &groups));
+        if let Err(reason) = allowed {
+            report.skipped.push((member.user.name.to_owned(), reason));
+            continue;
+        }
+
+        // A failed change, e.g. for a member the bot can't manage, doesn't stop the others
+        for ur in to_add.iter() {
+            let change = (member.user.name.to_owned(), ur.guild_role.name.to_owned());
+            if dry_run {
+                report.added.push(change);
+                continue;
+            }
+            match reconcile_add(ctx, member, ur, guildid).await {
+                Ok(()) => report.added.push(change),
+                Err(e) => report.skipped.push((
+                    member.user.name.to_owned(),
+                    format!("couldn't add {}: {}", ur.guild_role.name, e),
+                )),
+            }
+        }
+        for ur in to_remove.iter() {
+            let change = (member.user.name.to_owned(), ur.guild_role.name.to_owned());
+            if dry_run {
+                report.removed.push(change);
+                continue;
+            }
+            match reconcile_remove(ctx, member, ur, guildid).await {
+                Ok(()) => report.removed.push(change),
+                Err(e) => report.skipped.push((
+                    member.user.name.to_owned(),
+                    format!("couldn't remove {}: {}", ur.guild_role.name, e),
+                )),
+            }
+        }
+    }
+    if !dry_run && !report.is_empty() {
+        warn!(
+            "In {}, role_commands::reconcile_role_panel: on panel {} added {}, removed {} and skipped {} roles",
+            guildid.get(),
+            panel.name,
+            report.added.len(),
+            report.removed.len(),
+            report.skipped.len()
+        );
+    }
+
+    Ok(report)
+}
+
+/// Reconciles the active reaction role panels of every server the bot is in. Meant to run once on
+/// startup, to catch up with reactions that were added or removed while the bot was offline
+pub async fn reconcile_on_startup(ctx: serenity::Context, guilds: Vec<serenity::GuildId>) {
+    for guildid in guilds {
+        // Roles that expired while the bot was offline go first, so their reactions are gone and
+        // they aren't handed out again
+        if let Err(e) = expire_roles(&ctx, guildid).await {
+            error!(
+                "In {}, role_commands::reconcile_on_startup: {}",
+                guildid.get(),
+                e
+            );
+        }
+        let panels = match dbi::get_all_role_messages(Some(guildid)).await {
+            Ok(p) => p,
+            Err(e) => {
+                error!(
+                    "In {}, role_commands::reconcile_on_startup: {}",
+                    guildid.get(),
+                    e
+                );
+                continue;
+            }
+        };
+        for panel in panels.iter().filter(|p| p.active) {
+            if let Err(e) = reconcile_role_panel(&ctx, panel, false, guildid).await {
+                error!(
+                    "In {}, role_commands::reconcile_on_startup: panel {}: {}",
+                    guildid.get(),
+                    panel.name,
+                    e
+                );
+            }
+        }
+    }
+}
+
+/// Lists report entries for an embed field, cut off to fit into the field
+fn report_field(entries: &[(String, String)], separator: &str) -> String {
+    let mut field = String::new();
+    for (idx, (member, detail)) in entries.iter().enumerate() {
+        let line = format!("{}{}{}\n", member, separator, detail);
+        if field.len() + line.len() > 1000 {
+            field.push_str(&format!("… and {} more", entries.len() - idx));
+            break;
+        }
+        field.push_str(&line);
+    }
+
+    match field.is_empty() {
+        true => String::from("None"),
+        false => field,
+    }
+}
+
+/// Updates member roles to match the reactions
+///
+/// Reactions that were added or removed while the bot was offline aren't processed. This command
+/// gives members the roles they reacted for and takes away the roles they no longer react for.
+/// Join roles and roles that run out at a set time are kept. The same happens for all active
+/// panels when the bot starts. Use `dry_run` to only see the changes.
+#[poise::command(
+    slash_command,
+    required_permissions = "MANAGE_ROLES",
+    category = "Roles",
+    rename = "reconcile",
+    guild_only
+)]
+pub async fn reconcile_role(
+    ctx: Context<'_>,
+    #[description = "Role panel to reconcile, all active panels by default"]
+    #[autocomplete = "autocomplete_panel"]
+    panel: Option<String>,
+    #[description = "Only show the changes without applying them"] dry_run: Option<bool>,
+) -> Result<(), Error> {
+    let dry_run = dry_run.unwrap_or(false);
+    let guildid = ctx.guild_id().unwrap();
+    let panels: Vec<RoleMessage> = match &panel {
+        Some(name) => match dbi::get_role_message(name, Some(guildid)).await? {
+            Some(p) => vec![p],
+            None => {
+                ctx.say(format!("{}", DBIError::RolePanelNotFound)).await?;
+                return Ok(());
+            }
+        },
+        None => dbi::get_all_role_messages(Some(guildid))
+            .await?
+            .into_iter()
+            .filter(|p| p.active)
+            .collect(),
+    };
+    if !panels
+        .iter()
+        .any(|p| p.guild_message.is_some() && p.style == RoleMessageStyle::Reactions)
+    {
+        ctx.say("There is no posted role panel that uses reactions.")
+            .await?;
+        return Ok(());
+    }
+
+    // Fetching all the reactions and members can take a while
+    ctx.defer().await?;
+    let mut report = ReconcileReport::default();
+    for p in panels.iter() {
+        let panel_report =
+            reconcile_role_panel(ctx.serenity_context(), p, dry_run, guildid).await?;
+        report.added.extend(panel_report.added);
+        report.removed.extend(panel_report.removed);
+        report.skipped.extend(panel_report.skipped);
+    }
+
+    if report.is_empty() {
+        ctx.say("All member roles already match the reactions.")
+            .await?;
+        return Ok(());
+    }
+    ctx.send(
+        poise::CreateReply::default().embed(
+            serenity::CreateEmbed::default()
+                .title(match dry_run {
+                    true => "Role changes (dry run, nothing was changed)",
+                    false => "Role changes",
+                })
+                .field(
+                    format!("Added ({})", report.added.len()),
+                    report_field(&report.added, ": "),
+                    false,
+                )
+                .field(
+                    format!("Removed ({})", report.removed.len()),
+                    report_field(&report.removed, ": "),
+                    false,
+                )
+                .field(
+                    format!("Skipped ({})", report.skipped.len()),
+                    report_field(&report.skipped, " - "),
+                    false,
+                )
+                .colour(serenity::Colour::BLUE)
+                .footer(serenity::CreateEmbedFooter::new(format!(
+                    "Requested by: {}",
+                    ctx.author().name
+                ))),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Parses a duration like "7d", "12h" or "1d 12h" into seconds. The units are w(eeks), d(ays),
+/// h(ours) and m(inutes)
+pub fn parse_duration(input: &str) -> Option<u64> {
+    let mut total: u64 = 0;
+    let mut number = String::new();
+    for c in input.chars().filter(|c| !c.is_whitespace()) {
+        if c.is_ascii_digit() {
+            number.push(c);
+            continue;
+        }
+        let unit: u64 = match c.to_ascii_lowercase() {
+            'w' => 7 * 24 * 60 * 60,
+            'd' => 24 * 60 * 60,
+            'h' => 60 * 60,
+            'm' => 60,
+            _ => return None,
+        };
+        total = total.checked_add(number.parse::<u64>().ok()?.checked_mul(unit)?)?;
+        number.clear();
+    }
+
+    match number.is_empty() && total > 0 {
+        true => Some(total),
+        false => None,
+    }
+}
+
+/// Formats seconds the way `parse_duration` reads them, e.g. "1d 12h"
+pub fn format_duration(secs: u64) -> String {
+    let parts: Vec<String> = [(24 * 60 * 60, "d"), (60 * 60, "h"), (60, "m")]
+        .iter()
+        .scan(secs, |rest, (unit, suffix)| {
+            let count = *rest / unit;
+            *rest %= unit;
+            Some((count, suffix))
+        })
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, suffix)| format!("{}{}", count, suffix))
+        .collect();
+
+    match parts.is_empty() {
+        true => String::from("0m"),
+        false => parts.join(" "),
+    }
+}
+
+/// Parses a duration argument and checks it against the limit. Returns the reason if it's invalid
+fn duration_argument(input: &str) -> Result<u64, String> {
+    match parse_duration(input) {
+        Some(secs) if secs <= constants::ROLE_DURATION_LIMIT_SECS => Ok(secs),
+        Some(_) => Err(format!(
+            "Roles can be handed out for at most {}.",
+            format_duration(constants::ROLE_DURATION_LIMIT_SECS)
+        )),
+        None => Err(String::from(
+            "That's not a valid duration. Use something like `7d`, `12h` or `1d 12h`.",
+        )),
+    }
+}
+
+/// Schedules the removal of a role that was handed out for a limited time
+async fn schedule_role_expiry(
+    user: &serenity::User,
+    role: &serenity::Role,
+    secs: u64,
+    granted_by: Option<&serenity::User>,
+    guildid: Option<serenity::GuildId>,
+) -> Result<RoleExpiry, DBIError> {
+    let expires_at = serenity::Timestamp::from_unix_timestamp(
+        serenity::Timestamp::now().unix_timestamp() + secs as i64,
+    )
+    .unwrap_or_else(|_| serenity::Timestamp::now());
+
+    dbi::set_role_expiry(
+        RoleExpiry {
+            expiry_id: RoleExpiry::id_for(user.id, role.id),
+            user: user.to_owned(),
+            role: role.to_owned(),
+            expires_at,
+            granted_by: granted_by.cloned(),
+        },
+        guildid,
+    )
+    .await
+}
+
+/// Starts the timer of a user assignable role with a duration after a member picked it
+pub async fn start_role_timer(
+    user: &serenity::User,
+    role: &UserRole,
+    guildid: Option<serenity::GuildId>,
+) -> Result<(), DBIError> {
+    if let Some(secs) = role.duration {
+        schedule_role_expiry(user, &role.guild_role, secs, None, guildid).await?;
+    }
+
+    Ok(())
+}
+
+/// Stops the timer of a role after the member dropped it
+pub async fn stop_role_timer(
+    user_id: serenity::UserId,
+    role_id: serenity::RoleId,
+    guildid: Option<serenity::GuildId>,
+) -> Result<(), DBIError> {
+    match dbi::remove_role_expiry(&RoleExpiry::id_for(user_id, role_id), guildid).await {
+        Ok(_) | Err(DBIError::RoleExpiryNotFound) => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Takes away the expired roles of the members of a server. The expiries of members who left in
+/// the meantime are dropped. Returns the number of expired roles
+pub async fn expire_roles(
+    ctx: &serenity::Context,
+    guildid: serenity::GuildId,
+) -> Result<usize, Error> {
+    let now = serenity::Timestamp::now();
+    let expired: Vec<RoleExpiry> = dbi::get_role_expiries(Some(guildid))
+        .await?
+        .into_iter()
+        .filter(|e| e.expires_at <= now)
+        .collect();
+
+    let mut count = 0;
+    for expiry in expired.iter() {
+        match guildid.member(ctx, expiry.user.id).await {
+            Ok(member) => {
+                // The expiry is kept when the role can't be taken away, so it's tried again on the
+                // next check
+                if let Err(e) = member.remove_role(ctx, expiry.role.id).await {
+                    error!(
+                        "In {}, role_commands::expire_roles: couldn't take role {} from member {}: {}",
+                        guildid.get(),
+                        expiry.role.name,
+                        expiry.user.name,
+                        e
+                    );
+                    continue;
+                }
+                // Reaction roles lose their reaction too, so the role isn't handed out again
+                if let Ok(ur) = dbi::get_role(expiry.role.id.to_string(), Some(guildid)).await {
+                    if let Err(e) =
+                        remove_stale_reactions(ctx, Some(guildid), member.user.id, &[&ur]).await
+                    {
+                        warn!(
+                            "In {}, role_commands::expire_roles: couldn't remove reaction of member {}: {}",
+                            guildid.get(),
+                            expiry.user.name,
+                            e
+                        );
+                    }
+                }
+                let _ = member
+                    .user
+                    .direct_message(
+                        ctx,
+                        serenity::CreateMessage::new()
+                            .content(format!("The role {} has expired.", expiry.role.name)),
+                    )
+                    .await;
+            }
+            // A member who left has nothing to take away. Their snapshot keeps the expiry, so
+            // the role doesn't come back when they rejoin
+            Err(serenity::Error::Http(serenity::HttpError::UnsuccessfulRequest(response)))
+                if response.error.code == constants::DISCORD_UNKNOWN_MEMBER => {}
+            // Other errors might be temporary, so the expiry is tried again on the next check
+            Err(e) => {
+                error!(
+                    "In {}, role_commands::expire_roles: couldn't get member {}: {}",
+                    guildid.get(),
+                    expiry.user.name,
+                    e
+                );
+                continue;
+            }
+        }
+        stop_role_timer(expiry.user.id, expiry.role.id, Some(guildid)).await?;
+        count += 1;
+        warn!(
+            "In {}, role_commands::expire_roles: role {} of member {} expired",
+            guildid.get(),
+            expiry.role.name,
+            expiry.user.name
+        );
+    }
+
+    Ok(count)
+}
+
+/// Takes away expired roles on every server the bot is in, once per check interval. The first
+/// check is one interval after the start, roles that expired while the bot was offline are taken
+/// away by `reconcile_on_startup`
+pub async fn expire_roles_periodically(ctx: serenity::Context) {
+    let period = Duration::from_secs(constants::ROLE_EXPIRY_CHECK_INTERVAL_SECS);
+    let mut interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+    loop {
+        interval.tick().await;
+        for guildid in ctx.cache.guilds() {
+            if let Err(e) = expire_roles(&ctx, guildid).await {
+                error!(
+                    "In {}, role_commands::expire_roles_periodically: {}",
+                    guildid.get(),
+                    e
+                );
+            }
+        }
+    }
+}
+
+/// Sets how long a role lasts
+///
+/// When a member picks a role with a duration, the role is taken away again after the duration,
+/// e.g. `7d` for an event role. Durations are given in w(eeks), d(ays), h(ours) and m(inutes).
+/// Leave out the duration to make the role permanent again.
+#[poise::command(
+    slash_command,
+    required_permissions = "MANAGE_ROLES",
+    category = "Roles",
+    rename = "duration",
+    guild_only
+)]
+pub async fn duration_role(
+    ctx: Context<'_>,
+    #[description = "Role panel the role is on"]
+    #[autocomplete = "autocomplete_panel"]
+    panel: String,
+    #[description = "Role to change"] role: serenity::Role,
+    #[description = "How long the role lasts, e.g. 7d, leave out to make it permanent"]
+    duration: Option<String>,
+) -> Result<(), Error> {
+    let user_role = match dbi::get_role(role.id.to_string(), ctx.guild_id()).await {
+        Ok(ur) if ur.panel == panel => ur,
+        _ => {
+            ctx.say(format!("{}", DBIError::RoleNotFound)).await?;
+            return Ok(());
+        }
+    };
+    let duration = match duration.as_deref().map(duration_argument) {
+        Some(Ok(secs)) => Some(secs),
+        Some(Err(reason)) => {
+            ctx.say(reason).await?;
+            return Ok(());
+        }
+        None => None,
+    };
+
+    let updated = dbi::update_role(
+        UserRole {
+            duration,
+            ..user_role
+        },
+        ctx.guild_id(),
+    )
+    .await?;
+    match updated.duration {
+        Some(secs) => {
+            ctx.say(format!(
+                "Role {} now lasts {} after it was picked.",
+                updated.guild_role,
+                format_duration(secs)
+            ))
+            .await?
+        }
+        None => {
+            ctx.say(format!("Role {} is now permanent.", updated.guild_role))
+                .await?
+        }
+    };
+
+    Ok(())
+}
+
+/// Checks that the author of the command ranks above the role, so members can't hand out roles
+/// at or above their own highest role. The owner of the server can hand out any role
+async fn ranks_above(ctx: Context<'_>, role: &serenity::Role) -> bool {
+    let member = match ctx.author_member().await {
+        Some(m) => m.into_owned(),
+        None => return false,
+    };
+    let guild = match ctx.guild() {
+        Some(g) => g,
+        None => return false,
+    };
+
+    guild.owner_id == member.user.id
+        || member
+            .roles
+            .iter()
+            .filter_map(|id| guild.roles.get(id))
+            .any(|r| r.position > role.position)
+}
+
+/// Gives a member a role for a limited time
+///
+/// The role is taken away again after the duration, even if the bot restarts in the meantime.
+/// Durations are given in w(eeks), d(ays), h(ours) and m(inutes), e.g. `7d` or `1d 12h`.
+#[poise::command(
+    slash_command,
+    required_permissions = "MANAGE_ROLES",
+    category = "Roles",
+    rename = "grant",
+    guild_only
+)]
+pub async fn grant_role(
+    ctx: Context<'_>,
+    #[description = "Member to give the role"] member: serenity::Member,
+    #[description = "Role to give"] role: serenity::Role,
+    #[description = "How long the role lasts, e.g. 7d"] duration: String,
+) -> Result<(), Error> {
+    if !ranks_above(ctx, &role).await {
+        ctx.say("You can only give out roles below your highest role.")
+            .await?;
+        return Ok(());
+    }
+    let secs = match duration_argument(&duration) {
+        Ok(secs) => secs,
+        Err(reason) => {
+            ctx.say(reason).await?;
+            return Ok(());
+        }
+    };
+
+    member.add_role(ctx, role.id).await?;
+    let expiry = schedule_role_expiry(
+        &member.user,
+        &role,
+        secs,
+        Some(ctx.author()),
+        ctx.guild_id(),
+    )
+    .await?;
+    ctx.send(
+        poise::CreateReply::default()
+            .content(format!(
+                "Gave {} the role {} until <t:{}:f>.",
+                member.mention(),
+                role.mention(),
+                expiry.expires_at.unix_timestamp()
+            ))
+            .allowed_mentions(serenity::CreateAllowedMentions::new()),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Lists the roles that are going to expire
+///
+/// Shows all the roles that are taken away from members at a set time, the ones that expire first
+/// come first.
+#[poise::command(
+    slash_command,
+    required_permissions = "MANAGE_ROLES",
+    category = "Roles",
+    rename = "expiries",
+    guild_only
+)]
+pub async fn list_expiries_role(ctx: Context<'_>) -> Result<(), Error> {
+    let expiries = dbi::get_role_expiries(ctx.guild_id()).await?;
+    if expiries.is_empty() {
+        ctx.say("No roles are going to expire.").await?;
+        return Ok(());
+    }
+
+    let pages = expiries.chunks(10).count();
+    let embeds: Vec<serenity::CreateEmbed> = expiries
+        .chunks(10)
+        .enumerate()
+        .map(|(idx, chunk)| {
+            serenity::CreateEmbed::default()
+                .title(format!("Role expiries ({}/{})", idx + 1, pages))
+                .fields(chunk.iter().map(|e| {
+                    (
+                        format!("{}: {}", e.user.name, e.role.name),
+                        format!(
+                            "expires <t:{}:R>{}",
+                            e.expires_at.unix_timestamp(),
+                            match &e.granted_by {
+                                Some(u) => format!(", granted by {}", u.name),
+                                None => String::from(", picked by the member"),
+                            }
+                        ),
+                        false,
+                    )
+                }))
+                .colour(serenity::Colour::BLUE)
+                .footer(serenity::CreateEmbedFooter::new(format!(
+                    "Requsted by {}. Only they can change pages.",
+                    ctx.author().name
+                )))
+        })
+        .collect();
+    paginate_with_embeds(ctx, embeds).await?;
+
+    Ok(())
+}
+
+async fn autocomplete_expiry(ctx: Context<'_>, partial: &str) -> Vec<serenity::AutocompleteChoice> {
+    dbi::get_role_expiries(ctx.guild_id())
+        .await
+        .unwrap_or_default()
+        .iter()
+        .filter(|e| {
+            let partial = partial.to_lowercase();
+            e.user.name.to_lowercase().contains(&partial)
+                || e.role.name.to_lowercase().contains(&partial)
+        })
+        .take(25)
+        .map(|e| {
+            serenity::AutocompleteChoice::new(
+                format!(
+                    "{}: {} (expires {})",
+                    e.user.name,
+                    e.role.name,
+                    e.expires_at.format("%d. %b %Y %H:%M")
+                ),
+                e.expiry_id.to_owned(),
+            )
+        })
+        .collect()
+}
+
+/// Cancels the expiry of a role
+///
+/// The member keeps the role for good. To take it away right away, remove the role from the member
+/// instead.
+#[poise::command(
+    slash_command,
+    required_permissions = "MANAGE_ROLES",
+    category = "Roles",
+    rename = "expiry_cancel",
+    guild_only
+)]
+pub async fn cancel_expiry_role(
+    ctx: Context<'_>,
+    #[description = "Role expiry to cancel"]
+    #[autocomplete = "autocomplete_expiry"]
+    expiry: String,
+) -> Result<(), Error> {
+    match dbi::remove_role_expiry(&expiry, ctx.guild_id()).await {
+        Ok(e) => {
+            ctx.say(format!(
+                "{} keeps the role {} for good now.",
+                e.user.name, e.role.name
+            ))
+            .await?;
+        }
+        Err(e) => {
+            ctx.say(format!("{}", e)).await?;
+        }
+    };
+
+    Ok(())
+}
+
+fn join_roles_summary(config: &JoinRoleConfig) -> String {
+    let roles = match config.roles.is_empty() {
+        true => String::from("None"),
+        false => config
+            .roles
+            .iter()
+            .map(|r| r.mention().to_string())
+            .collect::<Vec<String>>()
+            .join(", "),
+    };
+
+    format!(
+        "Roles for new members: {}\nMembers who rejoin get their roles back: {}",
+        roles, config.sticky
+    )
+}
+
+/// Give new members a role
+///
+/// Every member who joins the server gets the join roles. Set `enabled` to false to remove the
+/// role from the join roles again.
+#[poise::command(
+    slash_command,
+    required_permissions = "MANAGE_ROLES",
+    category = "Roles",
+    rename = "join",
+    guild_only
+)]
+pub async fn join_role(
+    ctx: Context<'_>,
+    #[description = "Role to give new members"] role: serenity::Role,
+    #[description = "Whether new members get the role"] enabled: bool,
+) -> Result<(), Error> {
+    if enabled && !ranks_above(ctx, &role).await {
+        ctx.say("You can only give out roles below your highest role.")
+            .await?;
+        return Ok(());
+    }
+    let mut config = dbi::get_join_role_config(ctx.guild_id()).await?;
+    config.roles.retain(|r| *r != role.id);
+    if enabled {
+        config.roles.push(role.id);
+    }
+    let config = dbi::set_join_role_config(config, ctx.guild_id()).await?;
+
+    ctx.send(
+        poise::CreateReply::default()
+            .content(join_roles_summary(&config))
+            .allowed_mentions(serenity::CreateAllowedMentions::new()),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Give members their roles back when they rejoin
+///
+/// When enabled, the bot remembers the self-assignable roles of members who leave and gives them
+/// back when they rejoin. Roles a moderator took away before are not given back. Enabled by
+/// default.
+#[poise::command(
+    slash_command,
+    required_permissions = "MANAGE_ROLES",
+    category = "Roles",
+    rename = "sticky",
+    guild_only
+)]
+pub async fn sticky_role(
+    ctx: Context<'_>,
+    #[description = "Whether members get their roles back"] enabled: bool,
+) -> Result<(), Error> {
+    let config = dbi::get_join_role_config(ctx.guild_id()).await?;
+    let config = dbi::set_join_role_config(
+        JoinRoleConfig {
+            sticky: enabled,
+            ..config
+        },
+        ctx.guild_id(),
+    )
+    .await?;
+
+    ctx.send(
+        poise::CreateReply::default()
+            .content(join_roles_summary(&config))
+            .allowed_mentions(serenity::CreateAllowedMentions::new()),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn role(id: u64, group: &str) -> UserRole {
+        let mut guild_role = serenity::Role::default();
+        guild_role.id = serenity::RoleId::new(id);
+        UserRole {
+            guild_role,
+            emote: ReactionEmote::Unicode("👍".to_string()),
+            desc: String::new(),
+            panel: String::new(),
+            role_group: Some(group.to_string()),
+            required: Vec::new(),
+            forbidden: Vec::new(),
+            duration: None,
+        }
+    }
+
+    fn group(exclusive: bool, min: Option<u32>, max: Option<u32>) -> RoleGroup {
+        RoleGroup {
+            name: "colors".to_string(),
+            exclusive,
+            min,
+            max,
+        }
+    }
+
+    fn ids(ids: &[u64]) -> Vec<serenity::RoleId> {
+        ids.iter().map(|id| serenity::RoleId::new(*id)).collect()
+    }
+
+    #[test]
+    fn exclusive_group_allows_one_role() {
+        let roles = [role(1, "colors"), role(2, "colors"), role(3, "other")];
+        let groups = [group(true, None, None)];
+
+        assert!(check_role_groups(&ids(&[]), &ids(&[1]), &roles, &groups).is_ok());
+        assert!(check_role_groups(&ids(&[1]), &ids(&[1, 3]), &roles, &groups).is_ok());
+        assert!(check_role_groups(&ids(&[1]), &ids(&[1, 2]), &roles, &groups).is_err());
+    }
+
+    #[test]
+    fn group_limits_max_roles() {
+        let roles = [role(1, "colors"), role(2, "colors"), role(3, "colors")];
+        let groups = [group(false, None, Some(2))];
+
+        assert!(check_role_groups(&ids(&[1]), &ids(&[1, 2]), &roles, &groups).is_ok());
+        assert!(check_role_groups(&ids(&[1, 2]), &ids(&[1, 2, 3]), &roles, &groups).is_err());
+    }
+
+    #[test]
+    fn group_limits_min_roles() {
+        let roles = [role(1, "colors"), role(2, "colors")];
+        let groups = [group(false, Some(1), None)];
+
+        assert!(check_role_groups(&ids(&[1, 2]), &ids(&[1]), &roles, &groups).is_ok());
+        assert!(check_role_groups(&ids(&[1]), &ids(&[]), &roles, &groups).is_err());
+    }
+
+    #[test]
+    fn members_over_the_limit_can_move_towards_it() {
+        let roles = [role(1, "colors"), role(2, "colors"), role(3, "colors")];
+        let groups = [group(false, Some(2), Some(1))];
+
+        // Dropping a role is fine while still above the max, adding one below the min too
+        assert!(check_role_groups(&ids(&[1, 2, 3]), &ids(&[1, 2]), &roles, &groups).is_ok());
+        assert!(check_role_groups(&ids(&[]), &ids(&[1]), &roles, &groups).is_ok());
+    }
+
+    #[test]
+    fn parses_durations() {
+        assert_eq!(parse_duration("90m"), Some(90 * 60));
+        assert_eq!(parse_duration("12h"), Some(12 * 60 * 60));
+        assert_eq!(parse_duration("1w"), Some(7 * 24 * 60 * 60));
+        assert_eq!(parse_duration("1d 12H"), Some(36 * 60 * 60));
+        assert_eq!(parse_duration(" 1d12h "), Some(36 * 60 * 60));
+    }
+
+    #[test]
+    fn refuses_invalid_durations() {
+        assert_eq!(parse_duration(""), None);
+        assert_eq!(parse_duration("0d"), None);
+        assert_eq!(parse_duration("5"), None);
+        assert_eq!(parse_duration("d"), None);
+        assert_eq!(parse_duration("5s"), None);
+        assert_eq!(parse_duration("-1d"), None);
+        assert_eq!(parse_duration("99999999999999999999w"), None);
+    }
+
+    #[test]
+    fn formats_durations() {
+        assert_eq!(format_duration(0), "0m");
+        assert_eq!(format_duration(59), "0m");
+        assert_eq!(format_duration(90 * 60), "1h 30m");
+        assert_eq!(format_duration(7 * 24 * 60 * 60), "7d");
+        assert_eq!(format_duration(36 * 60 * 60 + 60), "1d 12h 1m");
+    }
+
+    #[test]
+    fn formatted_durations_parse_back() {
+        for secs in [
+            60,
+            90 * 60,
+            36 * 60 * 60,
+            constants::ROLE_DURATION_LIMIT_SECS,
+        ] {
+            assert_eq!(parse_duration(&format_duration(secs)), Some(secs));
+        }
+    }
+
+    #[test]
+    fn limits_duration_arguments() {
+        let limit = format_duration(constants::ROLE_DURATION_LIMIT_SECS);
+        assert_eq!(
+            duration_argument(&limit),
+            Ok(constants::ROLE_DURATION_LIMIT_SECS)
+        );
+        assert!(duration_argument(&format!("{} 1m", limit)).is_err());
+        assert!(duration_argument("soon").is_err());
+    }
+}
@@ -1,7 +1,17 @@
+use crate::attachment_store;
+use crate::constants;
 use crate::dbi;
+use crate::embed_tools::*;
+use crate::output;
 use crate::types::*;
+use log::error;
+use poise::serenity_prelude::{self as serenity, Mentionable};
+use poise::{ChoiceParameter, Modal};
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
 
-async fn autocomplete_tagname<'a>(ctx: Context<'_>, partial: &'a str) -> Vec<String> {
+async fn autocomplete_tagname(ctx: Context<'_>, partial: &str) -> Vec<String> {
     let tags = dbi::get_all_tags(ctx.guild_id()).await;
     match tags {
         Ok(t) => t
@@ -13,15 +23,257 @@ async fn autocomplete_tagname<'a>(ctx: Context<'_>, partial: &'a str) -> Vec<Str
     }
 }
 
+/// Autocompletes the tags that can be shown on this server, which includes the global tags
+async fn autocomplete_shown_tagname(
+    ctx: Context<'_>,
+    partial: &str,
+) -> Vec<serenity::AutocompleteChoice> {
+    shown_tags(ctx, partial)
+        .await
+        .unwrap_or_default()
+        .iter()
+        .map(|(t, source)| {
+            serenity::AutocompleteChoice::new(shown_label(t, *source), t.name.to_owned())
+        })
+        .collect()
+}
+
+/// All tags the author can use in this channel whose name or an alias contains the search text,
+/// server tags first. Server tags override global tags with the same name
+async fn shown_tags(ctx: Context<'_>, search: &str) -> Result<Vec<(Tag, TagSource)>, Error> {
+    let guild_tags = dbi::get_all_tags(ctx.guild_id()).await?;
+    let global_tags = dbi::get_all_tags(None).await?;
+    let roles = author_roles(ctx).await;
+
+    let matches = |t: &Tag| t.name.contains(search) || t.aliases.iter().any(|a| a.contains(search));
+    let mut shown: Vec<(Tag, TagSource)> = global_tags
+        .into_iter()
+        .filter(|t| matches(t) && !guild_tags.iter().any(|g| g.name == t.name))
+        .map(|t| (t, TagSource::Global))
+        .collect();
+    shown.splice(
+        0..0,
+        guild_tags
+            .into_iter()
+            .filter(|t| matches(t))
+            .map(|t| (t, TagSource::Guild)),
+    );
+    shown.retain(|(t, _)| t.access.allows(&roles, ctx.channel_id()));
+
+    Ok(shown)
+}
+
+/// The roles of the author on this server, used to check the access rules of tags
+async fn author_roles(ctx: Context<'_>) -> Vec<serenity::RoleId> {
+    match ctx.author_member().await {
+        Some(m) => m.roles.to_owned(),
+        None => vec![],
+    }
+}
+
+/// The name of a tag as it is offered to members, together with the languages it is available in.
+/// Global tags are marked as such
+fn shown_label(tag: &Tag, source: TagSource) -> String {
+    let mut label = tag.name.to_owned();
+    if !tag.variants.is_empty() {
+        let languages: Vec<&str> = tag.variants.keys().map(|l| l.as_str()).collect();
+        label.push_str(&format!(" [{}]", languages.join(", ")));
+    }
+    if source == TagSource::Global {
+        label.push_str(" (global)");
+    }
+
+    // Discord allows at most 100 characters for choices
+    label.chars().take(100).collect()
+}
+
+async fn autocomplete_language(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    let mut languages: Vec<String> = shown_tags(ctx, "")
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .flat_map(|(t, _)| t.variants.into_keys())
+        .filter(|l| l.contains(partial))
+        .collect();
+    languages.sort();
+    languages.dedup();
+
+    languages
+}
+
+/// Brings a language code into the form `xx` or `xx-YY`. Returns `None` if it isn't a valid code
+fn normalize_language(code: &str) -> Option<String> {
+    let mut parts = code.trim().split(['-', '_']);
+    let base = parts.next()?.to_lowercase();
+    let region = parts.next().map(|r| r.to_uppercase());
+    if parts.next().is_some()
+        || !(2..=3).contains(&base.len())
+        || !base.chars().all(|c| c.is_ascii_alphabetic())
+    {
+        return None;
+    }
+
+    match region {
+        None => Some(base),
+        Some(r) if (2..=4).contains(&r.len()) && r.chars().all(|c| c.is_ascii_alphanumeric()) => {
+            Some(format!("{}-{}", base, r))
+        }
+        Some(_) => None,
+    }
+}
+
+/// The languages to try when showing a tag to a user, from most to least preferred: an explicitly
+/// requested language, the language the user saved with `/tags language` and the locale of their
+/// Discord client
+pub async fn reader_languages(
+    user: serenity::UserId,
+    requested: Option<String>,
+    locale: Option<&str>,
+) -> Vec<String> {
+    let saved = dbi::get_user_language(user).await.unwrap_or_default();
+
+    // Languages are compared in the same form `/tags language` and `/tags translate` store them in
+    [requested, saved, locale.map(|l| l.to_string())]
+        .into_iter()
+        .flatten()
+        .filter_map(|l| normalize_language(&l))
+        .collect()
+}
+
+async fn autocomplete_global_tagname(_ctx: Context<'_>, partial: &str) -> Vec<String> {
+    match dbi::get_all_tags(None).await {
+        Ok(t) => t
+            .iter()
+            .filter(|t| t.name.contains(partial))
+            .map(|res| res.name.to_owned())
+            .collect(),
+        Err(_) => vec![],
+    }
+}
+
+async fn autocomplete_category(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    let tags = dbi::get_all_tags(ctx.guild_id()).await;
+    match tags {
+        Ok(t) => {
+            let mut categories: Vec<String> = t
+                .iter()
+                .filter_map(|t| t.category.to_owned())
+                .filter(|c| c.contains(partial))
+                .collect();
+            categories.sort();
+            categories.dedup();
+            categories
+        }
+        Err(_) => vec![],
+    }
+}
+
+#[derive(Debug, Modal)]
+#[name = "Tag"]
+struct TagModal {
+    #[name = "Name"]
+    #[placeholder = "One word without spaces"]
+    #[max_length = 100]
+    name: String,
+    #[name = "Content"]
+    #[paragraph]
+    #[max_length = 2000]
+    content: String,
+    #[name = "Category"]
+    #[placeholder = "Optional, used to group tags in /tags list"]
+    #[max_length = 100]
+    category: Option<String>,
+    #[name = "Aliases"]
+    #[placeholder = "Optional other names, separated by spaces"]
+    #[max_length = 200]
+    aliases: Option<String>,
+}
+
+/// Splits the aliases entered in the form into single names. Duplicates and the name of the tag
+/// itself are dropped
+fn parse_aliases(aliases: Option<String>, name: &str) -> Vec<String> {
+    let mut parsed: Vec<String> = Vec::new();
+    for alias in aliases
+        .unwrap_or_default()
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|a| !a.is_empty() && *a != name)
+    {
+        if !parsed.iter().any(|a| a == alias) {
+            parsed.push(alias.to_owned());
+        }
+    }
+
+    parsed
+}
+
+/// Check that the name and content of a tag can be stored and posted. Mentions of @everyone and
+/// @here are allowed, the output layer only lets them ping if the mention policy allows it
+pub fn validate_tag(name: &str, content: &str) -> Result<(), TagError> {
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return Err(TagError::InvalidName);
+    }
+    if content.trim().is_empty() {
+        return Err(TagError::EmptyContent);
+    }
+    if content.chars().count() > constants::MESSAGE_CHAR_LIMIT {
+        return Err(TagError::ContentTooLong);
+    }
+    Ok(())
+}
+
+#[derive(Debug, Modal)]
+#[name = "Tag embed"]
+struct TagEmbedModal {
+    #[name = "Title"]
+    #[max_length = 256]
+    title: Option<String>,
+    #[name = "Description"]
+    #[paragraph]
+    #[max_length = 4000]
+    description: Option<String>,
+    #[name = "Colour"]
+    #[placeholder = "#RRGGBB"]
+    #[max_length = 7]
+    colour: Option<String>,
+    #[name = "Footer"]
+    #[max_length = 2048]
+    footer: Option<String>,
+    #[name = "Image URL"]
+    image_url: Option<String>,
+}
+
 /// Tag parent command
 ///
-/// You can create pre-written message using a prefix command. These commands here let users
-/// display commands and admins to remove existing ones. You cannot change the content of a tag.
-/// You will have to remove it and re-create it.
+/// Tags are pre-written messages. These commands here let users display tags and admins create,
+/// edit and remove them. The creator of a tag can edit and remove it as well. Tags can optionally
+/// carry an embed, which is shown below the content.
 #[poise::command(
     slash_command,
-    default_member_permissions = "MANAGE_MESSAGES",
-    subcommands("remove_tag", "show_tag")
+    prefix_command,
+    subcommands(
+        "remove_tag",
+        "show_tag",
+        "list_tags",
+        "info_tag",
+        "new_tag",
+        "edit_tag",
+        "embed_tag",
+        "translate_tag",
+        "attach_tag",
+        "detach_tag",
+        "trash",
+        "restore_tag",
+        "trash_retention",
+        "language",
+        "access",
+        "transfer_tag",
+        "export_tags",
+        "import_tags",
+        "suggest_tag",
+        "review_channel",
+        "trigger",
+        "global"
+    )
 )]
 pub async fn tags(_ctx: Context<'_>) -> Result<(), Error> {
     Ok(())
@@ -29,23 +281,360 @@ pub async fn tags(_ctx: Context<'_>) -> Result<(), Error> {
 
 /// Show a pre-written Tag with prepared information.
 ///
-/// Specify the name and the tag will be displayed if it exists.
+/// Specify the name and the tag will be displayed if it exists. When a member is given, they are
+/// mentioned in front of the tag. Tags with translations are shown in the given language, your
+/// preferred language or the language of your Discord client, in that order.
 #[poise::command(slash_command, category = "Tags", rename = "show", guild_only)]
 pub async fn show_tag(
     ctx: Context<'_>,
     #[description = "Select a tag"]
-    #[autocomplete = "autocomplete_tagname"]
+    #[autocomplete = "autocomplete_shown_tagname"]
     tagname: String,
+    #[description = "Member to direct the tag at"] to: Option<serenity::Member>,
+    #[description = "Language to show the tag in"]
+    #[autocomplete = "autocomplete_language"]
+    lang: Option<String>,
 ) -> Result<(), Error> {
-    let tag = dbi::get_tag(&tagname, ctx.guild_id()).await;
-    match tag {
-        Ok(t) => {
-            ctx.say(&t.content).await?;
-        }
+    let (tag, source) = match dbi::find_tag(&tagname, ctx.guild_id()).await {
+        Ok(found) => found,
         Err(e) => {
             ctx.say(format!("{:?}", e)).await?;
+            return Ok(());
         }
     };
+    if !tag
+        .access
+        .allows(&author_roles(ctx).await, ctx.channel_id())
+    {
+        ctx.send(
+            poise::CreateReply::default()
+                .content(format!("You can't use tag {} here.", tag.name))
+                .ephemeral(true),
+        )
+        .await?;
+        return Ok(());
+    }
+    let tag = tag.localized(&reader_languages(ctx.author().id, lang, ctx.locale()).await);
+
+    match output::tag_reply(&tag, source, to.map(|m| m.user.id), ctx.guild_id()).await {
+        Ok(reply) => {
+            ctx.send(reply).await?;
+            dbi::increment_tag_uses(&tag.name, source.db_guild_id(ctx.guild_id())).await?;
+        }
+        Err(e) => {
+            ctx.say(format!("{}", e)).await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Modal)]
+#[name = "Find a tag"]
+struct TagSearchModal {
+    #[name = "Search"]
+    #[placeholder = "Part of the name or an alias"]
+    #[max_length = 100]
+    search: Option<String>,
+}
+
+/// The tags the "Send tag…" command offers. Tags that may only be shown ephemerally are left out,
+/// since the reply is visible to everyone
+async fn sendable_tags(ctx: Context<'_>, search: &str) -> Result<Vec<(Tag, TagSource)>, Error> {
+    let mut tags = shown_tags(ctx, search).await?;
+    tags.retain(|(t, _)| !t.access.ephemeral);
+    tags.sort_by_key(|(t, _)| std::cmp::Reverse(t.uses));
+
+    Ok(tags)
+}
+
+/// The ephemeral picker of the "Send tag…" command. Lists the first 25 tags that match the search
+fn tag_picker(
+    ctx_id: u64,
+    tags: &[(Tag, TagSource)],
+    search: &str,
+    ping: bool,
+) -> poise::CreateReply {
+    let options: Vec<serenity::CreateSelectMenuOption> = tags
+        .iter()
+        .take(25)
+        .map(|(t, source)| {
+            let option = serenity::CreateSelectMenuOption::new(shown_label(t, *source), &t.name);
+            match content_preview(&t.content) {
+                preview if preview.is_empty() => option,
+                preview => option.description(preview),
+            }
+        })
+        .collect();
+
+    let mut text = match (search.is_empty(), tags.len()) {
+        (_, 0) => "No tags match your search.".to_string(),
+        (true, n) if n > 25 => {
+            format!(
+                "Pick a tag to send. Showing 25 of {} tags, search to find others.",
+                n
+            )
+        }
+        (true, _) => "Pick a tag to send.".to_string(),
+        (false, n) => format!("Pick a tag to send. {} tags match \"{}\".", n, search),
+    };
+    if ping {
+        text.push_str("\nThe author of the message will be pinged.");
+    }
+
+    let mut components = vec![];
+    if !options.is_empty() {
+        components.push(serenity::CreateActionRow::SelectMenu(
+            serenity::CreateSelectMenu::new(
+                format!("{}tag", ctx_id),
+                serenity::CreateSelectMenuKind::String { options },
+            )
+            .placeholder("Select a tag"),
+        ));
+    }
+    components.push(serenity::CreateActionRow::Buttons(vec![
+        serenity::CreateButton::new(format!("{}search", ctx_id))
+            .style(serenity::ButtonStyle::Primary)
+            .label("Search"),
+        serenity::CreateButton::new(format!("{}ping", ctx_id))
+            .style(serenity::ButtonStyle::Secondary)
+            .label(match ping {
+                true => "Don't ping author",
+                false => "Ping author",
+            }),
+    ]));
+
+    poise::CreateReply::default()
+        .content(text)
+        .components(components)
+        .ephemeral(true)
+}
+
+/// Send a tag as a reply to this message
+///
+/// Pick a tag from the menu or search for one and it will be posted as a reply to the message.
+/// The author of the message is only pinged when asked for.
+#[poise::command(context_menu_command = "Send tag…", category = "Tags", guild_only)]
+pub async fn send_tag(
+    ctx: Context<'_>,
+    #[description = "Message to reply to"] message: serenity::Message,
+) -> Result<(), Error> {
+    let ctx_id = ctx.id();
+    let custom_ids = ["tag", "search", "ping"].map(|c| format!("{}{}", ctx_id, c));
+    let mut search = String::new();
+    let mut ping = false;
+    let mut tags = sendable_tags(ctx, &search).await?;
+
+    let reply = ctx.send(tag_picker(ctx_id, &tags, &search, ping)).await?;
+    let tagname = loop {
+        let interaction = match reply
+            .message()
+            .await?
+            .await_component_interaction(ctx)
+            .author_id(ctx.author().id)
+            .custom_ids(custom_ids.to_vec())
+            .timeout(Duration::from_secs(300))
+            .await
+        {
+            Some(i) => i,
+            None => {
+                reply
+                    .edit(
+                        ctx,
+                        poise::CreateReply::default()
+                            .content("No tag was picked in time.")
+                            .components(vec![]),
+                    )
+                    .await?;
+                return Ok(());
+            }
+        };
+
+        match interaction.data.custom_id.strip_prefix(&ctx_id.to_string()) {
+            Some("search") => {
+                let defaults = TagSearchModal {
+                    search: Some(search.to_owned()).filter(|s| !s.is_empty()),
+                };
+                if let Some(data) = poise::execute_modal_on_component_interaction(
+                    ctx,
+                    interaction,
+                    Some(defaults),
+                    None,
+                )
+                .await?
+                {
+                    search = data.search.unwrap_or_default().trim().to_owned();
+                    tags = sendable_tags(ctx, &search).await?;
+                }
+            }
+            Some(component) => {
+                interaction
+                    .create_response(ctx, serenity::CreateInteractionResponse::Acknowledge)
+                    .await?;
+                match (component, &interaction.data.kind) {
+                    ("tag", serenity::ComponentInteractionDataKind::StringSelect { values }) => {
+                        if let Some(name) = values.first() {
+                            break name.to_owned();
+                        }
+                    }
+                    ("ping", _) => ping = !ping,
+                    _ => (),
+                }
+            }
+            None => (),
+        }
+        reply
+            .edit(ctx, tag_picker(ctx_id, &tags, &search, ping))
+            .await?;
+    };
+
+    let (tag, source) = match dbi::find_tag(&tagname, ctx.guild_id()).await {
+        Ok(found) => found,
+        Err(e) => {
+            reply
+                .edit(
+                    ctx,
+                    poise::CreateReply::default()
+                        .content(format!("{:?}", e))
+                        .components(vec![]),
+                )
+                .await?;
+            return Ok(());
+        }
+    };
+    // The tag is meant for the author of the message, so it is shown in their language
+    let tag = tag.localized(&reader_languages(message.author.id, None, None).await);
+    let tag_message = output::tag_message(&tag, source, &message, ping, ctx.guild_id()).await?;
+    message.channel_id.send_message(ctx, tag_message).await?;
+    dbi::increment_tag_uses(&tag.name, source.db_guild_id(ctx.guild_id())).await?;
+
+    reply
+        .edit(
+            ctx,
+            poise::CreateReply::default()
+                .content(format!("Sent tag {}.", tag.name))
+                .components(vec![]),
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Checks if the author of the command may change the tag. This is the case for the creator of the
+/// tag and for everyone with the manage messages permission
+async fn can_manage_tag(ctx: Context<'_>, tag: &Tag) -> bool {
+    if ctx.author().id == tag.creator.id {
+        return true;
+    }
+
+    let member = match ctx.author_member().await {
+        Some(m) => m,
+        None => return false,
+    };
+    // Permissions are only included in the member for slash commands
+    let permissions = match member.permissions {
+        Some(p) => p,
+        None => match ctx.guild() {
+            Some(g) => g.member_permissions(&member),
+            None => return false,
+        },
+    };
+
+    permissions.manage_messages()
+}
+
+/// Trims the category entered in the form and drops it if only whitespace is left
+fn clean_category(category: Option<String>) -> Option<String> {
+    category
+        .map(|c| c.trim().to_owned())
+        .filter(|c| !c.is_empty())
+}
+
+/// Returns the content squashed onto one line, cut to at most 100 characters
+fn content_preview(content: &str) -> String {
+    let flat = content.split_whitespace().collect::<Vec<&str>>().join(" ");
+    if flat.chars().count() > 100 {
+        format!("{}…", flat.chars().take(99).collect::<String>())
+    } else {
+        flat
+    }
+}
+
+/// List all tags on this server.
+///
+/// Shows all tags grouped by their category, together with who created them and a short preview
+/// of the content. Optionally only the tags of one category are listed.
+#[poise::command(slash_command, category = "Tags", rename = "list", guild_only)]
+pub async fn list_tags(
+    ctx: Context<'_>,
+    #[description = "Only list tags of this category"]
+    #[autocomplete = "autocomplete_category"]
+    category: Option<String>,
+) -> Result<(), Error> {
+    let roles = author_roles(ctx).await;
+    let mut categories: BTreeMap<String, Vec<Tag>> = BTreeMap::new();
+    for tag in dbi::get_all_tags(ctx.guild_id())
+        .await?
+        .into_iter()
+        .filter(|t| t.access.allows(&roles, ctx.channel_id()))
+    {
+        let tag_category = tag
+            .category
+            .to_owned()
+            .unwrap_or(constants::DEFAULT_TAG_CATEGORY.to_string());
+        categories.entry(tag_category).or_default().push(tag);
+    }
+    if let Some(c) = &category {
+        categories.retain(|tag_category, _| tag_category == c);
+    }
+
+    if categories.is_empty() {
+        ctx.say(match category {
+            Some(c) => format!("There are no tags in the category {}.", c),
+            None => String::from("There are no tags on this server yet."),
+        })
+        .await?;
+        return Ok(());
+    }
+
+    let mut sections: Vec<(String, Vec<serenity::CreateEmbed>)> =
+        Vec::with_capacity(categories.len());
+    for (tag_category, mut tags) in categories.into_iter() {
+        tags.sort_by(|a, b| a.name.cmp(&b.name));
+        let pages = tags.chunks(10).count();
+        let embeds: Vec<serenity::CreateEmbed> = tags
+            .chunks(10)
+            .enumerate()
+            .map(|(idx, chunk)| {
+                serenity::CreateEmbed::default()
+                    .title(format!("Tags: {} ({}/{})", tag_category, idx + 1, pages))
+                    .fields(chunk.iter().map(|t| {
+                        (
+                            t.name.to_owned(),
+                            format!(
+                                "*by {}*\n{}",
+                                t.creator.name,
+                                // The list is public, so tags that are only shown to the member
+                                // using them don't get a preview
+                                match t.access.ephemeral {
+                                    true => String::from("*Only shown to the member using it*"),
+                                    false => content_preview(&t.content),
+                                }
+                            ),
+                            false,
+                        )
+                    }))
+                    .colour(serenity::Colour::BLUE)
+                    .footer(serenity::CreateEmbedFooter::new(format!(
+                        "Requsted by {}. Only they can change pages.",
+                        ctx.author().name
+                    )))
+            })
+            .collect();
+        sections.push((tag_category, embeds));
+    }
+
+    paginate_with_embed_sections(ctx, sections).await?;
 
     Ok(())
 }
@@ -53,7 +642,8 @@ pub async fn show_tag(
 /// Create a tag by specifying the name, followed by the content.
 ///
 /// The name needs to be one word without spaces. Everything after the name will be considered part
-/// of the content.
+/// of the content. Files attached to the message are stored with the tag. Prefer `/tags create`
+/// for content spanning multiple lines.
 #[poise::command(
     prefix_command,
     required_permissions = "MANAGE_MESSAGES",
@@ -67,11 +657,43 @@ pub async fn create_tag(
     #[description = "The content of the tag"]
     tagcontent: String,
 ) -> Result<(), Error> {
-    let newtag = Tag {
+    if let Err(e) = validate_tag(&tagname, &tagcontent) {
+        ctx.say(format!("{}", e)).await?;
+        return Ok(());
+    }
+    let files = match ctx {
+        poise::Context::Prefix(prefix_ctx) => prefix_ctx.msg.attachments.to_owned(),
+        poise::Context::Application(_) => vec![],
+    };
+
+    let mut newtag = Tag {
         name: tagname,
         content: tagcontent,
+        attachments: vec![],
         creator: ctx.author().to_owned(),
+        embed: None,
+        category: None,
+        aliases: vec![],
+        created_at: Some(serenity::Timestamp::now()),
+        last_editor: None,
+        edited_at: None,
+        uses: 0,
+        variants: BTreeMap::new(),
+        access: TagAccess::default(),
     };
+    // Check the name first, so no files get stored for a tag that can't be created
+    if let Err(e) = dbi::check_tag_names(&newtag, ctx.guild_id()).await {
+        ctx.say(format!("{}", e)).await?;
+        return Ok(());
+    }
+    newtag.attachments =
+        match attachment_store::store_attachments(&files, &[], ctx.guild_id()).await {
+            Ok(a) => a,
+            Err(e) => {
+                ctx.say(format!("{}", e)).await?;
+                return Ok(());
+            }
+        };
 
     match dbi::create_tag(newtag, ctx.guild_id()).await {
         Ok(t) => {
@@ -86,29 +708,2264 @@ pub async fn create_tag(
     Ok(())
 }
 
-/// Removes a tag.
+/// Create a tag using a form.
 ///
-/// This command can only be used by people with the manage messages permission.
+/// Opens a form in which the name and the content of the new tag can be entered. The content can
+/// span multiple lines and be at most 2000 characters long. A file can be given to be posted
+/// together with the tag, more can be added with `/tags attach`.
 #[poise::command(
     slash_command,
     required_permissions = "MANAGE_MESSAGES",
     category = "Tags",
-    rename = "remove",
+    rename = "create",
     guild_only
 )]
-pub async fn remove_tag(
-    ctx: Context<'_>,
-    #[description = "Tagname of tag to remove"]
+pub async fn new_tag(
+    ctx: ApplicationContext<'_>,
+    #[description = "File to post with the tag"] file: Option<serenity::Attachment>,
+) -> Result<(), Error> {
+    create_tag_with_modal(ctx, file.into_iter().collect(), ctx.guild_id()).await
+}
+
+/// Opens the tag form and creates the tag with the given files in the db of the given server
+async fn create_tag_with_modal(
+    ctx: ApplicationContext<'_>,
+    files: Vec<serenity::Attachment>,
+    guildid: Option<serenity::GuildId>,
+) -> Result<(), Error> {
+    let data = match TagModal::execute(ctx).await? {
+        Some(data) => data,
+        None => return Ok(()),
+    };
+
+    if let Err(e) = validate_tag(&data.name, &data.content) {
+        ctx.say(format!("{}", e)).await?;
+        return Ok(());
+    }
+
+    let mut newtag = Tag {
+        aliases: parse_aliases(data.aliases, &data.name),
+        name: data.name,
+        content: data.content,
+        creator: ctx.author().to_owned(),
+        embed: None,
+        category: clean_category(data.category),
+        created_at: Some(serenity::Timestamp::now()),
+        last_editor: None,
+        edited_at: None,
+        uses: 0,
+        variants: BTreeMap::new(),
+        access: TagAccess::default(),
+        attachments: vec![],
+    };
+    if let Err(e) = dbi::check_tag_names(&newtag, guildid).await {
+        ctx.say(format!("{}", e)).await?;
+        return Ok(());
+    }
+    newtag.attachments = match attachment_store::store_attachments(&files, &[], guildid).await {
+        Ok(a) => a,
+        Err(e) => {
+            ctx.say(format!("{}", e)).await?;
+            return Ok(());
+        }
+    };
+
+    match dbi::create_tag(newtag, guildid).await {
+        Ok(t) => {
+            ctx.say(format!("Tag {} created sucessfully!", &t.name))
+                .await?;
+        }
+        Err(e) => {
+            ctx.say(format!("{}", e)).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Edit the name and content of a tag using a form.
+///
+/// Opens a form that is pre-filled with the current name and content of the tag. Changing the
+/// name renames the tag. Only the creator of the tag and people with the manage messages
+/// permission can edit it.
+#[poise::command(slash_command, category = "Tags", rename = "edit", guild_only)]
+pub async fn edit_tag(
+    ctx: ApplicationContext<'_>,
+    #[description = "Tag to edit"]
     #[autocomplete = "autocomplete_tagname"]
     tagname: String,
 ) -> Result<(), Error> {
-    match dbi::remove_tag(&tagname, ctx.guild_id()).await {
+    let cur_tag = match dbi::get_tag(&tagname, ctx.guild_id()).await {
+        Ok(t) => t,
+        Err(e) => {
+            ctx.say(format!("{}", e)).await?;
+            return Ok(());
+        }
+    };
+    if !can_manage_tag(ctx.into(), &cur_tag).await {
+        ctx.say("Only the creator of the tag or moderators can edit it.")
+            .await?;
+        return Ok(());
+    }
+
+    edit_tag_with_modal(ctx, cur_tag, ctx.guild_id()).await
+}
+
+/// Opens the tag form pre-filled with the given tag and saves the changes to the db of the given
+/// server
+async fn edit_tag_with_modal(
+    ctx: ApplicationContext<'_>,
+    cur_tag: Tag,
+    guildid: Option<serenity::GuildId>,
+) -> Result<(), Error> {
+    let defaults = TagModal {
+        name: cur_tag.name.to_owned(),
+        content: cur_tag.content.to_owned(),
+        category: cur_tag.category.to_owned(),
+        aliases: Some(cur_tag.aliases.join(" ")).filter(|a| !a.is_empty()),
+    };
+    let data = match poise::execute_modal(ctx, Some(defaults), None).await? {
+        Some(data) => data,
+        None => return Ok(()),
+    };
+
+    if let Err(e) = validate_tag(&data.name, &data.content) {
+        ctx.say(format!("{}", e)).await?;
+        return Ok(());
+    }
+
+    let oldname = cur_tag.name.to_owned();
+    let newtag = Tag {
+        aliases: parse_aliases(data.aliases, &data.name),
+        name: data.name,
+        content: data.content,
+        category: clean_category(data.category),
+        last_editor: Some(ctx.author().to_owned()),
+        edited_at: Some(serenity::Timestamp::now()),
+        ..cur_tag
+    };
+
+    match dbi::update_tag(&oldname, newtag, guildid).await {
         Ok(t) => {
-            ctx.say(format!("Tag {} removed sucessfully!", t.name))
-                .await?
+            ctx.say(format!("Tag {} edited sucessfully!", &t.name))
+                .await?;
         }
-        Err(e) => ctx.say(format!("{}", e)).await?,
+        Err(e) => {
+            ctx.say(format!("{}", e)).await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Modal)]
+#[name = "Tag translation"]
+struct TranslationModal {
+    #[name = "Content"]
+    #[placeholder = "Leave empty to remove the translation"]
+    #[paragraph]
+    #[max_length = 2000]
+    content: Option<String>,
+}
+
+/// Add, change or remove a translation of a tag.
+///
+/// Opens a form with the content of the tag in the given language, e.g. `es` or `pt-BR`. Leave the
+/// content empty to remove the translation. Only the creator of the tag and people with the manage
+/// messages permission can translate it.
+#[poise::command(slash_command, category = "Tags", rename = "translate", guild_only)]
+pub async fn translate_tag(
+    ctx: ApplicationContext<'_>,
+    #[description = "Tag to translate"]
+    #[autocomplete = "autocomplete_tagname"]
+    tagname: String,
+    #[description = "Language code of the translation"]
+    #[autocomplete = "autocomplete_language"]
+    language: String,
+) -> Result<(), Error> {
+    let language = match normalize_language(&language) {
+        Some(l) => l,
+        None => {
+            ctx.say(format!("{}", TagError::InvalidLanguage)).await?;
+            return Ok(());
+        }
+    };
+    let cur_tag = match dbi::get_tag(&tagname, ctx.guild_id()).await {
+        Ok(t) => t,
+        Err(e) => {
+            ctx.say(format!("{}", e)).await?;
+            return Ok(());
+        }
+    };
+    if !can_manage_tag(ctx.into(), &cur_tag).await {
+        ctx.say("Only the creator of the tag or moderators can translate it.")
+            .await?;
+        return Ok(());
+    }
+
+    let defaults = TranslationModal {
+        content: cur_tag.variants.get(&language).cloned(),
+    };
+    let data = match poise::execute_modal(ctx, Some(defaults), None).await? {
+        Some(data) => data,
+        None => return Ok(()),
+    };
+
+    let mut variants = cur_tag.variants.to_owned();
+    match data.content.filter(|c| !c.trim().is_empty()) {
+        Some(content) => {
+            if let Err(e) = validate_tag(&cur_tag.name, &content) {
+                ctx.say(format!("{}", e)).await?;
+                return Ok(());
+            }
+            variants.insert(language.to_owned(), content);
+        }
+        None => {
+            variants.remove(&language);
+        }
+    }
+
+    let oldname = cur_tag.name.to_owned();
+    let newtag = Tag {
+        variants,
+        last_editor: Some(ctx.author().to_owned()),
+        edited_at: Some(serenity::Timestamp::now()),
+        ..cur_tag
     };
 
+    match dbi::update_tag(&oldname, newtag, ctx.guild_id()).await {
+        Ok(t) if t.variants.contains_key(&language) => {
+            ctx.say(format!(
+                "Translation {} of tag {} saved sucessfully!",
+                language, &t.name
+            ))
+            .await?;
+        }
+        Ok(t) => {
+            ctx.say(format!(
+                "Tag {} has no translation {} anymore.",
+                &t.name, language
+            ))
+            .await?;
+        }
+        Err(e) => {
+            ctx.say(format!("{}", e)).await?;
+        }
+    }
+
     Ok(())
 }
+
+async fn autocomplete_attachment(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    let tagname = match ctx {
+        poise::Context::Application(app_ctx) => app_ctx
+            .interaction
+            .data
+            .options()
+            .iter()
+            .find(|o| o.name == "tagname")
+            .and_then(|o| match o.value {
+                serenity::ResolvedValue::String(s) => Some(s.to_string()),
+                serenity::ResolvedValue::Autocomplete { value, .. } => Some(value.to_string()),
+                _ => None,
+            }),
+        poise::Context::Prefix(_) => None,
+    };
+    let tag = match tagname {
+        Some(name) => dbi::get_tag(&name, ctx.guild_id()).await.ok(),
+        None => None,
+    };
+    match tag {
+        Some(t) => t
+            .attachments
+            .iter()
+            .filter(|a| a.filename.contains(partial))
+            .map(|a| a.filename.to_owned())
+            .collect(),
+        None => vec![],
+    }
+}
+
+/// Add a file to a tag.
+///
+/// The file is stored by the bot and posted together with the tag. A tag can have at most 4 files
+/// of 8 MB together. Only the creator of the tag and people with the manage messages permission can
+/// add files.
+#[poise::command(slash_command, category = "Tags", rename = "attach", guild_only)]
+pub async fn attach_tag(
+    ctx: Context<'_>,
+    #[description = "Tag to add the file to"]
+    #[autocomplete = "autocomplete_tagname"]
+    tagname: String,
+    #[description = "File to add"] file: serenity::Attachment,
+) -> Result<(), Error> {
+    let cur_tag = match dbi::get_tag(&tagname, ctx.guild_id()).await {
+        Ok(t) => t,
+        Err(e) => {
+            ctx.say(format!("{}", e)).await?;
+            return Ok(());
+        }
+    };
+    if !can_manage_tag(ctx, &cur_tag).await {
+        ctx.say("Only the creator of the tag or moderators can add files to it.")
+            .await?;
+        return Ok(());
+    }
+
+    ctx.defer().await?;
+    let new_files =
+        match attachment_store::store_attachments(&[file], &cur_tag.attachments, ctx.guild_id())
+            .await
+        {
+            Ok(a) => a,
+            Err(e) => {
+                ctx.say(format!("{}", e)).await?;
+                return Ok(());
+            }
+        };
+
+    let oldname = cur_tag.name.to_owned();
+    let mut attachments = cur_tag.attachments.to_owned();
+    attachments.extend(new_files);
+    let newtag = Tag {
+        attachments,
+        last_editor: Some(ctx.author().to_owned()),
+        edited_at: Some(serenity::Timestamp::now()),
+        ..cur_tag
+    };
+
+    match dbi::update_tag(&oldname, newtag, ctx.guild_id()).await {
+        Ok(t) => {
+            ctx.say(format!(
+                "File added to tag {}. It now has {} files.",
+                &t.name,
+                t.attachments.len()
+            ))
+            .await?;
+        }
+        Err(e) => {
+            ctx.say(format!("{}", e)).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Remove a file from a tag.
+///
+/// The stored file is deleted once no tag uses it anymore. Only the creator of the tag and people
+/// with the manage messages permission can remove files.
+#[poise::command(slash_command, category = "Tags", rename = "detach", guild_only)]
+pub async fn detach_tag(
+    ctx: Context<'_>,
+    #[description = "Tag to remove the file from"]
+    #[autocomplete = "autocomplete_tagname"]
+    tagname: String,
+    #[description = "Name of the file to remove"]
+    #[autocomplete = "autocomplete_attachment"]
+    filename: String,
+) -> Result<(), Error> {
+    let cur_tag = match dbi::get_tag(&tagname, ctx.guild_id()).await {
+        Ok(t) => t,
+        Err(e) => {
+            ctx.say(format!("{}", e)).await?;
+            return Ok(());
+        }
+    };
+    if !can_manage_tag(ctx, &cur_tag).await {
+        ctx.say("Only the creator of the tag or moderators can remove files from it.")
+            .await?;
+        return Ok(());
+    }
+    let idx = match cur_tag
+        .attachments
+        .iter()
+        .position(|a| a.filename == filename)
+    {
+        Some(idx) => idx,
+        None => {
+            ctx.say(format!("Tag {} has no file {}.", cur_tag.name, filename))
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let oldname = cur_tag.name.to_owned();
+    let mut attachments = cur_tag.attachments.to_owned();
+    attachments.remove(idx);
+    let newtag = Tag {
+        attachments,
+        last_editor: Some(ctx.author().to_owned()),
+        edited_at: Some(serenity::Timestamp::now()),
+        ..cur_tag
+    };
+
+    match dbi::update_tag(&oldname, newtag, ctx.guild_id()).await {
+        Ok(t) => {
+            attachment_store::remove_orphans(ctx.guild_id()).await;
+            ctx.say(format!("File {} removed from tag {}.", filename, &t.name))
+                .await?;
+        }
+        Err(e) => {
+            ctx.say(format!("{}", e)).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Describes who may use a tag and where, using mentions of the roles and channels
+fn access_summary(access: &TagAccess) -> String {
+    let mut rules: Vec<String> = Vec::new();
+    if !access.roles.is_empty() {
+        let roles: Vec<String> = access
+            .roles
+            .iter()
+            .map(|r| r.mention().to_string())
+            .collect();
+        rules.push(format!("Roles: {}", roles.join(", ")));
+    }
+    if !access.channels.is_empty() {
+        let channels: Vec<String> = access
+            .channels
+            .iter()
+            .map(|c| c.mention().to_string())
+            .collect();
+        rules.push(format!("Channels: {}", channels.join(", ")));
+    }
+    if access.ephemeral {
+        rules.push(String::from("Only shown to the member using it"));
+    }
+
+    match rules.is_empty() {
+        true => String::from("Anyone, anywhere"),
+        false => rules.join("\n"),
+    }
+}
+
+/// Applies a change to the access rules of a tag and reports the new rules. Only the creator of
+/// the tag and people with the manage messages permission can restrict it
+async fn change_tag_access(
+    ctx: Context<'_>,
+    tagname: &str,
+    change: impl FnOnce(&mut TagAccess),
+) -> Result<(), Error> {
+    let cur_tag = match dbi::get_tag(tagname, ctx.guild_id()).await {
+        Ok(t) => t,
+        Err(e) => {
+            ctx.say(format!("{}", e)).await?;
+            return Ok(());
+        }
+    };
+    if !can_manage_tag(ctx, &cur_tag).await {
+        ctx.say("Only the creator of the tag or moderators can restrict it.")
+            .await?;
+        return Ok(());
+    }
+
+    let oldname = cur_tag.name.to_owned();
+    let mut access = cur_tag.access.to_owned();
+    change(&mut access);
+    let newtag = Tag {
+        access,
+        last_editor: Some(ctx.author().to_owned()),
+        edited_at: Some(serenity::Timestamp::now()),
+        ..cur_tag
+    };
+
+    match dbi::update_tag(&oldname, newtag, ctx.guild_id()).await {
+        Ok(t) => {
+            ctx.send(
+                poise::CreateReply::default()
+                    .content(format!(
+                        "Tag {} can now be used by:\n{}",
+                        &t.name,
+                        access_summary(&t.access)
+                    ))
+                    .allowed_mentions(serenity::CreateAllowedMentions::new()),
+            )
+            .await?;
+        }
+        Err(e) => {
+            ctx.say(format!("{}", e)).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Restrict who may use a tag and where
+///
+/// Tags can require one of a list of roles, be limited to some channels and be shown only to the
+/// member using them. Restricted tags aren't suggested to members who can't use them.
+#[poise::command(
+    slash_command,
+    category = "Tags",
+    subcommands("access_role", "access_channel", "access_ephemeral", "access_clear"),
+    guild_only
+)]
+pub async fn access(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Require a role to use a tag
+///
+/// Members need at least one of the required roles to use the tag. Set `required` to false to
+/// remove the role from the list again.
+#[poise::command(slash_command, category = "Tags", rename = "role", guild_only)]
+pub async fn access_role(
+    ctx: Context<'_>,
+    #[description = "Tag to restrict"]
+    #[autocomplete = "autocomplete_tagname"]
+    tagname: String,
+    #[description = "Role to require"] role: serenity::Role,
+    #[description = "Whether the role is required"] required: bool,
+) -> Result<(), Error> {
+    change_tag_access(ctx, &tagname, |access| {
+        access.roles.retain(|r| *r != role.id);
+        if required {
+            access.roles.push(role.id);
+        }
+    })
+    .await
+}
+
+/// Limit a tag to a channel
+///
+/// If at least one channel is allowed, the tag can only be used in the allowed channels. Set
+/// `allowed` to false to remove the channel from the list again.
+#[poise::command(slash_command, category = "Tags", rename = "channel", guild_only)]
+pub async fn access_channel(
+    ctx: Context<'_>,
+    #[description = "Tag to restrict"]
+    #[autocomplete = "autocomplete_tagname"]
+    tagname: String,
+    #[description = "Channel to allow"] channel: serenity::Channel,
+    #[description = "Whether the tag can be used in the channel"] allowed: bool,
+) -> Result<(), Error> {
+    change_tag_access(ctx, &tagname, |access| {
+        access.channels.retain(|c| *c != channel.id());
+        if allowed {
+            access.channels.push(channel.id());
+        }
+    })
+    .await
+}
+
+/// Only show a tag to the member using it
+///
+/// Ephemeral tags are only visible to the member who showed them, so they can't be triggered
+/// inline or sent as a reply.
+#[poise::command(slash_command, category = "Tags", rename = "ephemeral", guild_only)]
+pub async fn access_ephemeral(
+    ctx: Context<'_>,
+    #[description = "Tag to restrict"]
+    #[autocomplete = "autocomplete_tagname"]
+    tagname: String,
+    #[description = "Whether the tag is only shown to the member using it"] ephemeral: bool,
+) -> Result<(), Error> {
+    change_tag_access(ctx, &tagname, |access| access.ephemeral = ephemeral).await
+}
+
+/// Remove all restrictions of a tag
+///
+/// Afterwards everyone can use the tag in every channel.
+#[poise::command(slash_command, category = "Tags", rename = "clear", guild_only)]
+pub async fn access_clear(
+    ctx: Context<'_>,
+    #[description = "Tag to unrestrict"]
+    #[autocomplete = "autocomplete_tagname"]
+    tagname: String,
+) -> Result<(), Error> {
+    change_tag_access(ctx, &tagname, |access| *access = TagAccess::default()).await
+}
+
+/// Set the language you prefer to read tags in.
+///
+/// Tags with a translation in this language are shown in it, unless another language is picked
+/// when showing the tag. The preference applies on every server. Leave the language empty to go
+/// back to the language of your Discord client.
+#[poise::command(slash_command, category = "Tags", rename = "language")]
+pub async fn language(
+    ctx: Context<'_>,
+    #[description = "Language code, e.g. en, es or de"]
+    #[autocomplete = "autocomplete_language"]
+    language: Option<String>,
+) -> Result<(), Error> {
+    let language = match language.map(|l| normalize_language(&l)) {
+        Some(None) => {
+            ctx.say(format!("{}", TagError::InvalidLanguage)).await?;
+            return Ok(());
+        }
+        Some(l) => l,
+        None => None,
+    };
+
+    match dbi::set_user_language(ctx.author(), language).await? {
+        Some(l) => {
+            ctx.say(format!(
+                "Tags will be shown to you in {} where possible.",
+                l
+            ))
+            .await?
+        }
+        None => {
+            ctx.say("Tags will be shown in the language of your Discord client where possible.")
+                .await?
+        }
+    };
+
+    Ok(())
+}
+
+/// Attach an embed to an existing tag.
+///
+/// The embed can be given as JSON or TOML with the keys `title`, `description`, `colour`,
+/// `footer`, `image_url` and `fields` (a list of `name`, `value` and `inline`). Use the prefix
+/// version of this command for multi-line definitions. Without a definition, a form is opened
+/// instead. Pass `none` to remove the embed again. Only the creator of the tag and people with the
+/// manage messages permission can change its embed.
+#[poise::command(
+    slash_command,
+    prefix_command,
+    category = "Tags",
+    rename = "embed",
+    guild_only
+)]
+pub async fn embed_tag(
+    ctx: Context<'_>,
+    #[description = "Tag to attach the embed to"]
+    #[autocomplete = "autocomplete_tagname"]
+    tagname: String,
+    #[rest]
+    #[description = "Embed as JSON or TOML, `none` to remove it. Leave empty to open a form"]
+    definition: Option<String>,
+) -> Result<(), Error> {
+    let cur_tag = match dbi::get_tag(&tagname, ctx.guild_id()).await {
+        Ok(t) => t,
+        Err(e) => {
+            ctx.say(format!("{}", e)).await?;
+            return Ok(());
+        }
+    };
+    if !can_manage_tag(ctx, &cur_tag).await {
+        ctx.say("Only the creator of the tag or moderators can change its embed.")
+            .await?;
+        return Ok(());
+    }
+
+    let embed = match (definition, ctx) {
+        (Some(d), _) if d.trim() == "none" => None,
+        (Some(d), _) => match parse_tag_embed(&d) {
+            Ok(embed) => Some(embed),
+            Err(e) => {
+                ctx.say(format!("{}", e)).await?;
+                return Ok(());
+            }
+        },
+        (None, poise::Context::Application(app_ctx)) => {
+            let cur_embed = cur_tag.embed.to_owned().unwrap_or_default();
+            let defaults = TagEmbedModal {
+                title: cur_embed.title.to_owned(),
+                description: cur_embed.description.to_owned(),
+                colour: cur_embed.colour.to_owned(),
+                footer: cur_embed.footer.to_owned(),
+                image_url: cur_embed.image_url.to_owned(),
+            };
+            let data = match poise::execute_modal(app_ctx, Some(defaults), None).await? {
+                Some(data) => data,
+                None => return Ok(()),
+            };
+            let embed = TagEmbed {
+                title: data.title,
+                description: data.description,
+                colour: data.colour,
+                footer: data.footer,
+                image_url: data.image_url,
+                ..cur_embed
+            };
+            if let Err(e) = validate_tag_embed(&embed) {
+                ctx.say(format!("{}", e)).await?;
+                return Ok(());
+            }
+            Some(embed)
+        }
+        (None, poise::Context::Prefix(_)) => {
+            ctx.say("Please provide the embed definition as JSON or TOML after the tag name.")
+                .await?;
+            return Ok(());
+        }
+    };
+
+    let oldname = cur_tag.name.to_owned();
+    let newtag = Tag {
+        embed,
+        last_editor: Some(ctx.author().to_owned()),
+        edited_at: Some(serenity::Timestamp::now()),
+        ..cur_tag
+    };
+
+    match dbi::update_tag(&oldname, newtag, ctx.guild_id()).await {
+        Ok(t @ Tag { embed: Some(_), .. }) => {
+            ctx.say(format!("Embed of tag {} set sucessfully!", &t.name))
+                .await?;
+        }
+        Ok(t) => {
+            ctx.say(format!("Embed of tag {} removed sucessfully!", &t.name))
+                .await?;
+        }
+        Err(e) => {
+            ctx.say(format!("{}", e)).await?;
+        }
+    };
+
+    Ok(())
+}
+
+/// Removes a tag.
+///
+/// This command can only be used by the creator of the tag and people with the manage messages
+/// permission.
+#[poise::command(slash_command, category = "Tags", rename = "remove", guild_only)]
+pub async fn remove_tag(
+    ctx: Context<'_>,
+    #[description = "Tagname of tag to remove"]
+    #[autocomplete = "autocomplete_tagname"]
+    tagname: String,
+) -> Result<(), Error> {
+    let cur_tag = match dbi::get_tag(&tagname, ctx.guild_id()).await {
+        Ok(t) => t,
+        Err(e) => {
+            ctx.say(format!("{}", e)).await?;
+            return Ok(());
+        }
+    };
+    if !can_manage_tag(ctx, &cur_tag).await {
+        ctx.say("Only the creator of the tag or moderators can remove it.")
+            .await?;
+        return Ok(());
+    }
+
+    match dbi::trash_tag(&cur_tag.name, ctx.author(), ctx.guild_id()).await {
+        Ok(t) => {
+            let config = dbi::get_tag_trash_config(ctx.guild_id()).await?;
+            ctx.say(format!(
+                "Tag {} moved to the trash. It can be brought back with `/tags restore` for {} days.",
+                t.tag.name, config.retention_days
+            ))
+            .await?
+        }
+        Err(e) => ctx.say(format!("{}", e)).await?,
+    };
+
+    Ok(())
+}
+
+async fn autocomplete_trashed_tag(
+    ctx: Context<'_>,
+    partial: &str,
+) -> Vec<serenity::AutocompleteChoice> {
+    trashed_tag_choices(ctx.guild_id(), partial).await
+}
+
+async fn autocomplete_global_trashed_tag(
+    _ctx: Context<'_>,
+    partial: &str,
+) -> Vec<serenity::AutocompleteChoice> {
+    trashed_tag_choices(None, partial).await
+}
+
+async fn trashed_tag_choices(
+    guildid: Option<serenity::GuildId>,
+    partial: &str,
+) -> Vec<serenity::AutocompleteChoice> {
+    dbi::get_trashed_tags(guildid)
+        .await
+        .unwrap_or_default()
+        .iter()
+        .filter(|t| t.tag.name.contains(partial))
+        .map(|t| {
+            serenity::AutocompleteChoice::new(
+                format!(
+                    "{} (removed {} by {})",
+                    t.tag.name,
+                    t.deleted_at.format("%d. %b %Y %H:%M"),
+                    t.deleted_by.name
+                ),
+                t.trash_id.to_owned(),
+            )
+        })
+        .collect()
+}
+
+/// List recently removed tags.
+///
+/// Removed tags stay in the trash for the retention period of the server and can be brought back
+/// with `/tags restore` until then.
+#[poise::command(
+    slash_command,
+    required_permissions = "MANAGE_MESSAGES",
+    category = "Tags",
+    guild_only
+)]
+pub async fn trash(ctx: Context<'_>) -> Result<(), Error> {
+    let trashed = dbi::get_trashed_tags(ctx.guild_id()).await?;
+    if trashed.is_empty() {
+        ctx.say("The trash is empty.").await?;
+        return Ok(());
+    }
+    let config = dbi::get_tag_trash_config(ctx.guild_id()).await?;
+
+    let pages = trashed.chunks(10).count();
+    let embeds: Vec<serenity::CreateEmbed> = trashed
+        .chunks(10)
+        .enumerate()
+        .map(|(idx, chunk)| {
+            serenity::CreateEmbed::default()
+                .title(format!("Trash ({}/{})", idx + 1, pages))
+                .description(format!(
+                    "Removed tags are purged after {} days.",
+                    config.retention_days
+                ))
+                .fields(chunk.iter().map(|t| {
+                    (
+                        t.tag.name.to_owned(),
+                        format!(
+                            "*removed <t:{}:R> by {}*\n{}",
+                            t.deleted_at.unix_timestamp(),
+                            t.deleted_by.name,
+                            content_preview(&t.tag.content)
+                        ),
+                        false,
+                    )
+                }))
+                .colour(serenity::Colour::BLUE)
+                .footer(serenity::CreateEmbedFooter::new(format!(
+                    "Requsted by {}. Only they can change pages.",
+                    ctx.author().name
+                )))
+        })
+        .collect();
+    paginate_with_embeds(ctx, embeds).await?;
+
+    Ok(())
+}
+
+/// Bring back a removed tag.
+///
+/// Restores a tag from the trash with all its settings. This fails if its name or one of its
+/// aliases was taken in the meantime. Only the creator of the tag and people with the manage
+/// messages permission can restore it.
+#[poise::command(slash_command, category = "Tags", rename = "restore", guild_only)]
+pub async fn restore_tag(
+    ctx: Context<'_>,
+    #[description = "Removed tag to restore"]
+    #[autocomplete = "autocomplete_trashed_tag"]
+    tag: String,
+) -> Result<(), Error> {
+    let trashed = dbi::get_trashed_tags(ctx.guild_id()).await?;
+    // Accept the plain name as well, in which case the most recently removed tag is restored
+    let trashed = match trashed
+        .iter()
+        .find(|t| t.trash_id == tag)
+        .or_else(|| trashed.iter().find(|t| t.tag.name == tag))
+    {
+        Some(t) => t,
+        None => {
+            ctx.say(format!("{}", DBIError::TrashedTagNotFound)).await?;
+            return Ok(());
+        }
+    };
+    if !can_manage_tag(ctx, &trashed.tag).await {
+        ctx.say("Only the creator of the tag or moderators can restore it.")
+            .await?;
+        return Ok(());
+    }
+
+    match dbi::restore_tag(&trashed.trash_id, ctx.guild_id()).await {
+        Ok(t) => {
+            ctx.say(format!("Tag {} restored sucessfully!", t.name))
+                .await?
+        }
+        Err(e) => ctx.say(format!("{}", e)).await?,
+    };
+
+    Ok(())
+}
+
+/// Set how long removed tags are kept.
+///
+/// Tags in the trash are purged for good once they were removed longer ago than this.
+#[poise::command(
+    slash_command,
+    required_permissions = "MANAGE_MESSAGES",
+    category = "Tags",
+    rename = "retention",
+    guild_only
+)]
+pub async fn trash_retention(
+    ctx: Context<'_>,
+    #[description = "Days to keep removed tags"]
+    #[min = 1]
+    #[max = 365]
+    days: u64,
+) -> Result<(), Error> {
+    dbi::set_tag_trash_config(
+        TagTrashConfig {
+            retention_days: days,
+        },
+        ctx.guild_id(),
+    )
+    .await?;
+
+    ctx.say(format!("Removed tags are now kept for {} days.", days))
+        .await?;
+
+    Ok(())
+}
+
+/// Purges the tags in the trash of the server that are older than its retention period, together
+/// with the files only they used
+pub async fn purge_expired_trash(guildid: Option<serenity::GuildId>) -> Result<(), Error> {
+    let config = dbi::get_tag_trash_config(guildid).await?;
+    let retention = (config.retention_days * 24 * 60 * 60) as i64;
+    let cutoff = serenity::Timestamp::from_unix_timestamp(
+        serenity::Timestamp::now().unix_timestamp() - retention,
+    )?;
+
+    if !dbi::purge_trash(cutoff, guildid).await?.is_empty() {
+        attachment_store::remove_orphans(guildid).await;
+    }
+
+    Ok(())
+}
+
+/// Purges the expired tags from the trash of every server the bot is in and from the trash of the
+/// global tags, once per purge interval
+pub async fn purge_trash_periodically(cache: std::sync::Arc<serenity::Cache>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(
+        constants::TAG_TRASH_PURGE_INTERVAL_SECS,
+    ));
+    loop {
+        interval.tick().await;
+        for guildid in cache.guilds() {
+            if let Err(e) = purge_expired_trash(Some(guildid)).await {
+                error!(
+                    "In {}, tag_commands::purge_trash_periodically: {}",
+                    guildid.get(),
+                    e
+                );
+            }
+        }
+        if let Err(e) = purge_expired_trash(None).await {
+            error!(
+                "In {}, tag_commands::purge_trash_periodically: {}",
+                constants::DB_DEFAULT_DB,
+                e
+            );
+        }
+    }
+}
+
+/// Hand a tag over to another member.
+///
+/// The new owner can then edit and remove the tag. Only the current owner of the tag and people
+/// with the manage messages permission can transfer it.
+#[poise::command(slash_command, category = "Tags", rename = "transfer", guild_only)]
+pub async fn transfer_tag(
+    ctx: Context<'_>,
+    #[description = "Tag to transfer"]
+    #[autocomplete = "autocomplete_tagname"]
+    tagname: String,
+    #[description = "New owner of the tag"] new_owner: serenity::User,
+) -> Result<(), Error> {
+    let cur_tag = match dbi::get_tag(&tagname, ctx.guild_id()).await {
+        Ok(t) => t,
+        Err(e) => {
+            ctx.say(format!("{}", e)).await?;
+            return Ok(());
+        }
+    };
+    if !can_manage_tag(ctx, &cur_tag).await {
+        ctx.say("Only the owner of the tag or moderators can transfer it.")
+            .await?;
+        return Ok(());
+    }
+    if new_owner.bot {
+        ctx.say("Tags can't be transferred to bots.").await?;
+        return Ok(());
+    }
+
+    let oldname = cur_tag.name.to_owned();
+    let newtag = Tag {
+        creator: new_owner,
+        ..cur_tag
+    };
+
+    match dbi::update_tag(&oldname, newtag, ctx.guild_id()).await {
+        Ok(t) => {
+            ctx.say(format!("Tag {} now belongs to {}.", &t.name, t.creator))
+                .await?;
+        }
+        Err(e) => {
+            ctx.say(format!("{}", e)).await?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Show information about a tag.
+///
+/// Displays who created the tag and when, who edited it last and how often it was used.
+#[poise::command(slash_command, category = "Tags", rename = "info", guild_only)]
+pub async fn info_tag(
+    ctx: Context<'_>,
+    #[description = "Select a tag"]
+    #[autocomplete = "autocomplete_shown_tagname"]
+    tagname: String,
+) -> Result<(), Error> {
+    let (tag, source) = match dbi::find_tag(&tagname, ctx.guild_id()).await {
+        Ok(found) => found,
+        Err(e) => {
+            ctx.say(format!("{}", e)).await?;
+            return Ok(());
+        }
+    };
+    if !tag
+        .access
+        .allows(&author_roles(ctx).await, ctx.channel_id())
+    {
+        ctx.send(
+            poise::CreateReply::default()
+                .content(format!("You can't use tag {} here.", tag.name))
+                .ephemeral(true),
+        )
+        .await?;
+        return Ok(());
+    }
+    let format_time = |time: &Option<serenity::Timestamp>| match time {
+        Some(t) => t.format("%d. %b %Y %H:%M").to_string(),
+        None => String::from("Unknown"),
+    };
+
+    ctx.send(
+        poise::CreateReply::default()
+            .embed(
+                serenity::CreateEmbed::default()
+                    .title(format!("Tag: {}", &tag.name))
+                    .description(content_preview(&tag.content))
+                    .field("Owner", tag.creator.name.to_owned(), true)
+                    .field("Created at", format_time(&tag.created_at), true)
+                    .field(
+                        "Category",
+                        tag.category
+                            .to_owned()
+                            .unwrap_or(constants::DEFAULT_TAG_CATEGORY.to_string()),
+                        true,
+                    )
+                    .field(
+                        "Last edited by",
+                        match &tag.last_editor {
+                            Some(u) => u.name.to_owned(),
+                            None => String::from("Never edited"),
+                        },
+                        true,
+                    )
+                    .field("Last edited at", format_time(&tag.edited_at), true)
+                    .field("Uses", tag.uses.to_string(), true)
+                    .field(
+                        "Source",
+                        match source {
+                            TagSource::Guild => "This server",
+                            TagSource::Global => "Global",
+                        },
+                        true,
+                    )
+                    .field(
+                        "Aliases",
+                        match tag.aliases.is_empty() {
+                            true => String::from("None"),
+                            false => tag.aliases.join(", "),
+                        },
+                        false,
+                    )
+                    .field("Access", access_summary(&tag.access), false)
+                    .field(
+                        "Files",
+                        match tag.attachments.is_empty() {
+                            true => String::from("None"),
+                            false => tag
+                                .attachments
+                                .iter()
+                                .map(|a| a.filename.to_owned())
+                                .collect::<Vec<_>>()
+                                .join(", "),
+                        },
+                        false,
+                    )
+                    .field(
+                        "Languages",
+                        match tag.variants.is_empty() {
+                            true => String::from("None"),
+                            false => tag.variants.keys().cloned().collect::<Vec<_>>().join(", "),
+                        },
+                        false,
+                    )
+                    .colour(serenity::Colour::BLUE)
+                    .footer(serenity::CreateEmbedFooter::new(format!(
+                        "Requested by: {}",
+                        ctx.author().name
+                    ))),
+            )
+            .ephemeral(tag.access.ephemeral),
+    )
+    .await?;
+
+    Ok(())
+}
+
+#[derive(Debug, poise::ChoiceParameter)]
+pub enum ExportFormat {
+    #[name = "JSON"]
+    Json,
+    #[name = "Markdown"]
+    Markdown,
+}
+
+/// Renders the tags as a readable Markdown document
+fn tags_to_markdown(tags: &[Tag]) -> String {
+    let mut markdown = String::from("# Tags\n");
+    for tag in tags.iter() {
+        markdown.push_str(&format!("\n## {}\n", tag.name));
+        markdown.push_str(&format!(
+            "*Category:* {} | *Aliases:* {} | *Creator:* {}\n\n",
+            tag.category
+                .to_owned()
+                .unwrap_or(constants::DEFAULT_TAG_CATEGORY.to_string()),
+            match tag.aliases.is_empty() {
+                true => String::from("None"),
+                false => tag.aliases.join(", "),
+            },
+            tag.creator.name
+        ));
+        markdown.push_str(&tag.content);
+        for attachment in tag.attachments.iter() {
+            markdown.push_str(&format!("\n\n*File:* {}", attachment.filename));
+        }
+        for (lang, content) in tag.variants.iter() {
+            markdown.push_str(&format!("\n\n### {}\n\n{}", lang, content));
+        }
+        markdown.push_str("\n\n---\n");
+    }
+
+    markdown
+}
+
+/// Export all tags of this server.
+///
+/// Attaches a file with every tag of this server. JSON exports can be imported again with
+/// `/tags import`, Markdown exports are meant for reading.
+#[poise::command(
+    slash_command,
+    required_permissions = "MANAGE_MESSAGES",
+    category = "Tags",
+    rename = "export",
+    guild_only
+)]
+pub async fn export_tags(
+    ctx: Context<'_>,
+    #[description = "File format, JSON by default"] format: Option<ExportFormat>,
+) -> Result<(), Error> {
+    let mut tags = dbi::get_all_tags(ctx.guild_id()).await?;
+    tags.sort_by(|a, b| a.name.cmp(&b.name));
+
+    let guild_id = ctx.guild_id().unwrap();
+    let (data, filename) = match format.unwrap_or(ExportFormat::Json) {
+        ExportFormat::Json => (
+            serde_json::to_vec_pretty(&tags)?,
+            format!("tags-{}.json", guild_id),
+        ),
+        ExportFormat::Markdown => (
+            tags_to_markdown(&tags).into_bytes(),
+            format!("tags-{}.md", guild_id),
+        ),
+    };
+
+    ctx.send(
+        poise::CreateReply::default()
+            .content(format!("Exported {} tags.", tags.len()))
+            .attachment(serenity::CreateAttachment::bytes(data, filename)),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// A tag as it is read from an import file. Everything that isn't needed to display a tag is
+/// optional, so files from other sources can be imported as well
+#[derive(Debug, Deserialize)]
+struct ImportedTag {
+    name: String,
+    content: String,
+    creator: Option<serenity::User>,
+    embed: Option<TagEmbed>,
+    category: Option<String>,
+    #[serde(default)]
+    aliases: Vec<String>,
+    created_at: Option<serenity::Timestamp>,
+    #[serde(default)]
+    variants: BTreeMap<String, String>,
+    #[serde(default)]
+    access: TagAccess,
+}
+
+/// Reads the entries of a JSON export. Entries that aren't tags are returned as errors, so they
+/// can be reported together with the other problems
+fn read_json_import(data: &[u8]) -> Result<Vec<Result<ImportedTag, String>>, String> {
+    let entries: Vec<serde_json::Value> = match serde_json::from_slice(data) {
+        Ok(e) => e,
+        Err(e) => return Err(format!("The file is not a JSON list of tags: {}", e)),
+    };
+
+    Ok(entries
+        .into_iter()
+        .map(|entry| serde_json::from_value(entry).map_err(|e| e.to_string()))
+        .collect())
+}
+
+/// Drops empty lines at the start and the end
+fn trim_blank_lines<'a>(mut lines: &'a [&'a str]) -> &'a [&'a str] {
+    while lines.first().is_some_and(|l| l.trim().is_empty()) {
+        lines = &lines[1..];
+    }
+    while lines.last().is_some_and(|l| l.trim().is_empty()) {
+        lines = &lines[..lines.len() - 1];
+    }
+
+    lines
+}
+
+/// Reads a Markdown export as written by `tags_to_markdown`. Markdown exports don't hold embeds,
+/// access rules or creators, so those are left empty
+fn read_markdown_import(text: &str) -> Result<Vec<Result<ImportedTag, String>>, String> {
+    let lines: Vec<&str> = text.lines().collect();
+    // Every tag starts with its name as a heading, followed by the line with its details
+    let starts: Vec<usize> = (0..lines.len().saturating_sub(1))
+        .filter(|&i| lines[i].starts_with("## ") && lines[i + 1].starts_with("*Category:* "))
+        .collect();
+    if starts.is_empty() {
+        return Err(String::from(
+            "The file is not a Markdown export of `/tags export`.",
+        ));
+    }
+
+    let mut entries: Vec<Result<ImportedTag, String>> = Vec::with_capacity(starts.len());
+    for (idx, &start) in starts.iter().enumerate() {
+        let end = starts.get(idx + 1).copied().unwrap_or(lines.len());
+        let name = lines[start].trim_start_matches("## ").trim().to_owned();
+
+        let mut category = None;
+        let mut aliases = vec![];
+        for detail in lines[start + 1].split(" | ") {
+            if let Some(c) = detail.strip_prefix("*Category:* ") {
+                category = Some(c.to_owned()).filter(|c| c != constants::DEFAULT_TAG_CATEGORY);
+            } else if let Some(a) = detail.strip_prefix("*Aliases:* ") {
+                aliases = match a {
+                    "None" => vec![],
+                    _ => a.split(", ").map(|a| a.to_owned()).collect(),
+                };
+            }
+        }
+
+        let mut body = trim_blank_lines(&lines[start + 2..end]);
+        if body.last() == Some(&"---") {
+            body = trim_blank_lines(&body[..body.len() - 1]);
+        }
+        // Translations follow the content, each under a heading with its language
+        let variant_starts: Vec<usize> = (0..body.len())
+            .filter(|&i| {
+                body[i]
+                    .strip_prefix("### ")
+                    .is_some_and(|lang| normalize_language(lang).is_some())
+                    && (i == 0 || body[i - 1].trim().is_empty())
+            })
+            .collect();
+        let mut content =
+            trim_blank_lines(&body[..variant_starts.first().copied().unwrap_or(body.len())]);
+        // The files themselves are not part of exports, only their names
+        while content.last().is_some_and(|l| l.starts_with("*File:* ")) {
+            content = trim_blank_lines(&content[..content.len() - 1]);
+        }
+        let mut variants = BTreeMap::new();
+        for (vidx, &vstart) in variant_starts.iter().enumerate() {
+            let vend = variant_starts.get(vidx + 1).copied().unwrap_or(body.len());
+            variants.insert(
+                body[vstart].trim_start_matches("### ").to_owned(),
+                trim_blank_lines(&body[vstart + 1..vend]).join("\n"),
+            );
+        }
+
+        entries.push(Ok(ImportedTag {
+            name,
+            content: content.join("\n"),
+            creator: None,
+            embed: None,
+            category,
+            aliases,
+            created_at: None,
+            variants,
+            access: TagAccess::default(),
+        }));
+    }
+
+    Ok(entries)
+}
+
+/// Validates all entries of an import file. Returns a list of problems if any entry is invalid
+fn parse_import(
+    entries: Vec<Result<ImportedTag, String>>,
+    importer: &serenity::User,
+) -> Result<Vec<Tag>, Vec<String>> {
+    let mut tags: Vec<Tag> = Vec::with_capacity(entries.len());
+    let mut problems: Vec<String> = Vec::new();
+    for (idx, entry) in entries.into_iter().enumerate() {
+        let imported: ImportedTag = match entry {
+            Ok(t) => t,
+            Err(e) => {
+                problems.push(format!("Entry {}: {}", idx + 1, e));
+                continue;
+            }
+        };
+        if let Err(e) = validate_tag(&imported.name, &imported.content) {
+            problems.push(format!("Entry {} ({}): {}", idx + 1, imported.name, e));
+            continue;
+        }
+        if let Some(embed) = &imported.embed {
+            if let Err(e) = validate_tag_embed(embed) {
+                problems.push(format!("Entry {} ({}): {}", idx + 1, imported.name, e));
+                continue;
+            }
+        }
+        let variants: Result<BTreeMap<String, String>, TagError> = imported
+            .variants
+            .into_iter()
+            .map(|(lang, content)| {
+                validate_tag(&imported.name, &content)?;
+                Ok((
+                    normalize_language(&lang).ok_or(TagError::InvalidLanguage)?,
+                    content,
+                ))
+            })
+            .collect();
+        let variants = match variants {
+            Ok(v) => v,
+            Err(e) => {
+                problems.push(format!("Entry {} ({}): {}", idx + 1, imported.name, e));
+                continue;
+            }
+        };
+
+        let aliases = parse_aliases(Some(imported.aliases.join(" ")), &imported.name);
+        tags.push(Tag {
+            name: imported.name,
+            content: imported.content,
+            variants,
+            access: imported.access,
+            // The files themselves are not part of exports
+            attachments: vec![],
+            creator: imported.creator.unwrap_or(importer.to_owned()),
+            embed: imported.embed,
+            category: clean_category(imported.category),
+            aliases,
+            created_at: imported.created_at.or(Some(serenity::Timestamp::now())),
+            last_editor: None,
+            edited_at: None,
+            uses: 0,
+        });
+    }
+
+    match problems.is_empty() {
+        true => Ok(tags),
+        false => Err(problems),
+    }
+}
+
+/// Who currently holds a tag name during an import
+#[derive(Debug, Clone, PartialEq)]
+enum NameOwner {
+    /// A tag that is already stored on the server
+    Existing(String),
+    /// A tag from the import file, by its position in the list of tags to create
+    Imported(usize),
+}
+
+#[derive(Debug, PartialEq)]
+enum ConflictChoice {
+    Skip,
+    Overwrite,
+    Rename(String),
+}
+
+#[derive(Debug, Modal)]
+#[name = "Rename tag"]
+struct RenameModal {
+    #[name = "New name"]
+    #[placeholder = "One word without spaces"]
+    #[max_length = 100]
+    name: String,
+}
+
+/// Asks the author how to resolve a name conflict during an import. Returns `None` if the author
+/// didn't answer in time. "Skip all" and "Overwrite all" are remembered in `apply_to_all`
+async fn ask_conflict_choice(
+    ctx: Context<'_>,
+    reply: &poise::ReplyHandle<'_>,
+    question: String,
+    apply_to_all: &mut Option<ConflictChoice>,
+) -> Result<Option<ConflictChoice>, Error> {
+    let ctx_id = ctx.id();
+    let button_ids = ["skip", "overwrite", "rename", "skipall", "overwriteall"]
+        .map(|b| format!("{}{}", ctx_id, b));
+    let components = serenity::CreateActionRow::Buttons(vec![
+        serenity::CreateButton::new(&button_ids[0])
+            .style(serenity::ButtonStyle::Secondary)
+            .label("Skip"),
+        serenity::CreateButton::new(&button_ids[1])
+            .style(serenity::ButtonStyle::Danger)
+            .label("Overwrite"),
+        serenity::CreateButton::new(&button_ids[2])
+            .style(serenity::ButtonStyle::Primary)
+            .label("Rename"),
+        serenity::CreateButton::new(&button_ids[3])
+            .style(serenity::ButtonStyle::Secondary)
+            .label("Skip all"),
+        serenity::CreateButton::new(&button_ids[4])
+            .style(serenity::ButtonStyle::Danger)
+            .label("Overwrite all"),
+    ]);
+    reply
+        .edit(
+            ctx,
+            poise::CreateReply::default()
+                .content(question)
+                .components(vec![components]),
+        )
+        .await?;
+
+    let interaction = match reply
+        .message()
+        .await?
+        .await_component_interaction(ctx)
+        .author_id(ctx.author().id)
+        .custom_ids(button_ids.to_vec())
+        .timeout(Duration::from_secs(300))
+        .await
+    {
+        Some(i) => i,
+        None => return Ok(None),
+    };
+
+    let choice = match interaction.data.custom_id.strip_prefix(&ctx_id.to_string()) {
+        Some("rename") => {
+            match poise::execute_modal_on_component_interaction::<RenameModal>(
+                ctx,
+                interaction,
+                None,
+                None,
+            )
+            .await?
+            {
+                Some(data) => ConflictChoice::Rename(data.name.trim().to_owned()),
+                None => return Ok(None),
+            }
+        }
+        Some(button) => {
+            interaction
+                .create_response(ctx, serenity::CreateInteractionResponse::Acknowledge)
+                .await?;
+            match button {
+                "skipall" => {
+                    *apply_to_all = Some(ConflictChoice::Skip);
+                    ConflictChoice::Skip
+                }
+                "overwrite" => ConflictChoice::Overwrite,
+                "overwriteall" => {
+                    *apply_to_all = Some(ConflictChoice::Overwrite);
+                    ConflictChoice::Overwrite
+                }
+                _ => ConflictChoice::Skip,
+            }
+        }
+        None => ConflictChoice::Skip,
+    };
+
+    Ok(Some(choice))
+}
+
+/// Import tags from a file.
+///
+/// Accepts a JSON or Markdown file as created by `/tags export`. Markdown exports don't contain
+/// embeds and access rules, so tags imported from them come without. Every entry is validated
+/// first. For every tag whose name is already taken, you can choose to skip it, overwrite the
+/// existing tag or import it under another name. Overwritten tags are moved to the trash. All tags
+/// are then imported at once, so either all or none of them end up on the server.
+#[poise::command(
+    slash_command,
+    required_permissions = "MANAGE_MESSAGES",
+    category = "Tags",
+    rename = "import",
+    guild_only
+)]
+pub async fn import_tags(
+    ctx: Context<'_>,
+    #[description = "JSON or Markdown file with the tags"] file: serenity::Attachment,
+) -> Result<(), Error> {
+    let markdown = file.filename.ends_with(".md");
+    if !markdown && !file.filename.ends_with(".json") {
+        ctx.say("Only JSON and Markdown files can be imported. Use an export of `/tags export`.")
+            .await?;
+        return Ok(());
+    }
+    if file.size > constants::TAG_IMPORT_SIZE_LIMIT {
+        ctx.say("The file is too large to be imported.").await?;
+        return Ok(());
+    }
+    ctx.defer().await?;
+
+    let data = file.download().await?;
+    let entries = match markdown {
+        true => read_markdown_import(&String::from_utf8_lossy(&data)),
+        false => read_json_import(&data),
+    };
+    let entries = match entries {
+        Ok(e) => e,
+        Err(e) => {
+            ctx.say(format!("Nothing was imported: {}", e)).await?;
+            return Ok(());
+        }
+    };
+    let imported = match parse_import(entries, ctx.author()) {
+        Ok(tags) => tags,
+        Err(problems) => {
+            let mut report = format!(
+                "Nothing was imported, {} entries are invalid:\n",
+                problems.len()
+            );
+            for problem in problems.iter() {
+                if report.chars().count() + problem.chars().count() + 20
+                    > constants::MESSAGE_CHAR_LIMIT
+                {
+                    report.push('…');
+                    break;
+                }
+                report.push_str(&format!("- {}\n", problem));
+            }
+            ctx.say(report).await?;
+            return Ok(());
+        }
+    };
+
+    // Map every taken name and alias to the tag holding it
+    let mut taken: HashMap<String, NameOwner> = HashMap::new();
+    let mut existing: HashMap<String, Tag> = HashMap::new();
+    for tag in dbi::get_all_tags(ctx.guild_id()).await?.into_iter() {
+        for name in std::iter::once(&tag.name).chain(tag.aliases.iter()) {
+            taken.insert(name.to_owned(), NameOwner::Existing(tag.name.to_owned()));
+        }
+        existing.insert(tag.name.to_owned(), tag);
+    }
+
+    let reply = ctx
+        .say(format!("Checking {} tags for conflicts…", imported.len()))
+        .await?;
+    let mut to_create: Vec<Option<Tag>> = Vec::with_capacity(imported.len());
+    let mut to_trash: Vec<TrashedTag> = Vec::new();
+    let mut apply_to_all: Option<ConflictChoice> = None;
+    let (mut skipped, mut renamed, mut overwritten, mut dropped_aliases) = (0, 0, 0, 0);
+    let now = serenity::Timestamp::now();
+
+    for mut tag in imported.into_iter() {
+        let mut was_renamed = false;
+        let mut rename_problem: Option<String> = None;
+        // Ask until the tag has a free name or is skipped
+        while let Some(owner) = taken.get(&tag.name).cloned() {
+            let question = format!(
+                "{}The name **{}** is already taken by {}. What should happen to the imported tag?",
+                rename_problem.take().unwrap_or_default(),
+                tag.name,
+                match &owner {
+                    NameOwner::Existing(name) => format!("the existing tag {}", name),
+                    NameOwner::Imported(_) => String::from("an earlier tag in the file"),
+                }
+            );
+            let choice = match &apply_to_all {
+                Some(ConflictChoice::Skip) => ConflictChoice::Skip,
+                Some(ConflictChoice::Overwrite) => ConflictChoice::Overwrite,
+                _ => match ask_conflict_choice(ctx, &reply, question, &mut apply_to_all).await? {
+                    Some(c) => c,
+                    None => {
+                        reply
+                            .edit(
+                                ctx,
+                                poise::CreateReply::default()
+                                    .content("No answer received, nothing was imported.")
+                                    .components(vec![]),
+                            )
+                            .await?;
+                        return Ok(());
+                    }
+                },
+            };
+
+            match choice {
+                ConflictChoice::Skip => {
+                    skipped += 1;
+                    break;
+                }
+                ConflictChoice::Overwrite => {
+                    match &owner {
+                        NameOwner::Existing(name) => {
+                            if let Some(t) = existing.remove(name) {
+                                to_trash.push(TrashedTag {
+                                    trash_id: TrashedTag::new_id(),
+                                    tag: t,
+                                    deleted_by: ctx.author().to_owned(),
+                                    deleted_at: now,
+                                });
+                            }
+                        }
+                        NameOwner::Imported(idx) => to_create[*idx] = None,
+                    }
+                    overwritten += 1;
+                    taken.retain(|_, o| *o != owner);
+                }
+                ConflictChoice::Rename(name) => match validate_tag(&name, &tag.content) {
+                    Ok(_) => {
+                        tag.aliases.retain(|a| *a != name);
+                        tag.name = name;
+                        was_renamed = true;
+                    }
+                    Err(e) => {
+                        rename_problem = Some(format!("**{}** can't be used: {}\n", name, e));
+                    }
+                },
+            }
+        }
+        if taken.contains_key(&tag.name) {
+            continue;
+        }
+        if was_renamed {
+            renamed += 1;
+        }
+
+        let alias_count = tag.aliases.len();
+        tag.aliases.retain(|a| !taken.contains_key(a));
+        dropped_aliases += alias_count - tag.aliases.len();
+
+        for name in std::iter::once(&tag.name).chain(tag.aliases.iter()) {
+            taken.insert(name.to_owned(), NameOwner::Imported(to_create.len()));
+        }
+        to_create.push(Some(tag));
+    }
+
+    let to_create: Vec<Tag> = to_create.into_iter().flatten().collect();
+    let summary = match dbi::import_tags(to_trash, to_create, ctx.guild_id()).await {
+        Ok(created) => format!(
+            "Imported {} tags ({} overwritten, {} renamed, {} skipped). {} aliases were dropped \
+because they were already taken.",
+            created, overwritten, renamed, skipped, dropped_aliases
+        ),
+        Err(e) => format!("Nothing was imported: {}", e),
+    };
+    reply
+        .edit(
+            ctx,
+            poise::CreateReply::default()
+                .content(summary)
+                .components(vec![]),
+        )
+        .await?;
+
+    Ok(())
+}
+
+/// Form used to suggest a tag and to edit a suggestion during review
+#[derive(Debug, Modal)]
+#[name = "Tag suggestion"]
+pub struct SuggestionModal {
+    #[name = "Name"]
+    #[placeholder = "One word without spaces"]
+    #[max_length = 100]
+    pub name: String,
+    #[name = "Content"]
+    #[paragraph]
+    #[max_length = 2000]
+    pub content: String,
+    #[name = "Category"]
+    #[placeholder = "Optional"]
+    #[max_length = 100]
+    pub category: Option<String>,
+}
+
+impl SuggestionModal {
+    /// Checks the entered name and content and cleans up the category
+    pub fn validated(self) -> Result<SuggestionModal, TagError> {
+        validate_tag(&self.name, &self.content)?;
+        Ok(SuggestionModal {
+            category: clean_category(self.category),
+            ..self
+        })
+    }
+}
+
+/// The embed shown in the review channel for a tag suggestion
+pub fn suggestion_embed(suggestion: &TagSuggestion) -> serenity::CreateEmbed {
+    serenity::CreateEmbed::default()
+        .title(format!("Tag suggestion: {}", suggestion.name))
+        .description(&suggestion.content)
+        .field(
+            "Category",
+            suggestion
+                .category
+                .to_owned()
+                .unwrap_or(constants::DEFAULT_TAG_CATEGORY.to_string()),
+            true,
+        )
+        .field("Suggested by", suggestion.suggester.to_string(), true)
+        .colour(serenity::Colour::GOLD)
+        .timestamp(suggestion.suggested_at)
+}
+
+/// The review buttons below a tag suggestion. They stay valid across restarts, since the id of
+/// the suggestion is part of their custom ids
+pub fn suggestion_components(
+    suggestion_id: &str,
+    disabled: bool,
+) -> Vec<serenity::CreateActionRow> {
+    let button = |action: &str| {
+        serenity::CreateButton::new(format!(
+            "{}:{}:{}",
+            constants::TAG_SUGGESTION_ID,
+            action,
+            suggestion_id
+        ))
+        .disabled(disabled)
+    };
+
+    vec![serenity::CreateActionRow::Buttons(vec![
+        button("approve")
+            .style(serenity::ButtonStyle::Success)
+            .label("Approve"),
+        button("reject")
+            .style(serenity::ButtonStyle::Danger)
+            .label("Reject"),
+        button("edit")
+            .style(serenity::ButtonStyle::Secondary)
+            .label("Edit"),
+    ])]
+}
+
+/// Suggest a new tag.
+///
+/// Opens a form to write a tag. The suggestion is posted to the review channel of the server,
+/// where moderators can approve, edit or reject it. Once approved, you are the creator of the tag.
+#[poise::command(slash_command, category = "Tags", rename = "suggest", guild_only)]
+pub async fn suggest_tag(ctx: ApplicationContext<'_>) -> Result<(), Error> {
+    let review_config = match dbi::get_tag_review_config(ctx.guild_id()).await? {
+        Some(c) => c,
+        None => {
+            ctx.send(
+                poise::CreateReply::default()
+                    .content("Tag suggestions are not enabled on this server.")
+                    .ephemeral(true),
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+
+    let data = match SuggestionModal::execute(ctx).await? {
+        Some(data) => data,
+        None => return Ok(()),
+    };
+    let data = match data.validated() {
+        Ok(d) => d,
+        Err(e) => {
+            ctx.send(
+                poise::CreateReply::default()
+                    .content(format!("{}", e))
+                    .ephemeral(true),
+            )
+            .await?;
+            return Ok(());
+        }
+    };
+    if dbi::get_tag(&data.name, ctx.guild_id()).await.is_ok() {
+        ctx.send(
+            poise::CreateReply::default()
+                .content(format!("A tag called {} already exists.", data.name))
+                .ephemeral(true),
+        )
+        .await?;
+        return Ok(());
+    }
+
+    let suggestion = TagSuggestion {
+        suggestion_id: ctx.id().to_string(),
+        name: data.name,
+        content: data.content,
+        category: data.category,
+        suggester: ctx.author().to_owned(),
+        suggested_at: serenity::Timestamp::now(),
+        review_message: None,
+    };
+    let review_message = review_config
+        .channel
+        .send_message(
+            ctx,
+            serenity::CreateMessage::new()
+                .embed(suggestion_embed(&suggestion))
+                .components(suggestion_components(&suggestion.suggestion_id, false)),
+        )
+        .await?;
+    dbi::create_tag_suggestion(
+        TagSuggestion {
+            review_message: Some(review_message),
+            ..suggestion
+        },
+        ctx.guild_id(),
+    )
+    .await?;
+
+    ctx.send(
+        poise::CreateReply::default()
+            .content("Thanks! Your suggestion was sent to the moderators for review.")
+            .ephemeral(true),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Set the channel tag suggestions are posted to.
+///
+/// Members can only suggest tags with `/tags suggest` once a review channel is set. Leave the
+/// channel empty to disable suggestions.
+#[poise::command(
+    slash_command,
+    required_permissions = "MANAGE_MESSAGES",
+    category = "Tags",
+    rename = "review_channel",
+    guild_only
+)]
+pub async fn review_channel(
+    ctx: Context<'_>,
+    #[description = "Channel for tag suggestions, empty to disable suggestions"]
+    #[channel_types("Text")]
+    channel: Option<serenity::GuildChannel>,
+) -> Result<(), Error> {
+    let config = channel.as_ref().map(|c| TagReviewConfig {
+        channel: c.id,
+        set_by: ctx.author().to_owned(),
+    });
+    dbi::set_tag_review_config(config, ctx.guild_id()).await?;
+
+    match channel {
+        Some(c) => {
+            ctx.say(format!("Tag suggestions will be posted to {}.", c))
+                .await?
+        }
+        None => ctx.say("Tag suggestions disabled.").await?,
+    };
+
+    Ok(())
+}
+
+#[derive(Debug, poise::ChoiceParameter)]
+pub enum ChannelRule {
+    #[name = "Allow"]
+    Allow,
+    #[name = "Deny"]
+    Deny,
+    #[name = "Reset"]
+    Reset,
+}
+
+/// Inline tag trigger settings
+///
+/// When a trigger sigil like `?` is set, tags can be posted by writing the sigil followed by the
+/// tag name anywhere in a normal message, e.g. `?passive`.
+#[poise::command(
+    slash_command,
+    required_permissions = "MANAGE_MESSAGES",
+    category = "Tags",
+    subcommands("trigger_sigil", "trigger_channel", "trigger_cooldown", "trigger_show"),
+    guild_only
+)]
+pub async fn trigger(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Set the sigil for inline tag triggers
+///
+/// Sets the characters that have to be put in front of a tag name to trigger it inside of a normal
+/// message, e.g. `?` or `$$`. Leave empty to disable inline triggers.
+#[poise::command(
+    slash_command,
+    required_permissions = "MANAGE_MESSAGES",
+    category = "Tags",
+    rename = "sigil",
+    guild_only
+)]
+pub async fn trigger_sigil(
+    ctx: Context<'_>,
+    #[description = "Sigil in front of the tag name, empty to disable"]
+    #[max_length = 5]
+    sigil: Option<String>,
+) -> Result<(), Error> {
+    let sigil = sigil.map(|s| s.trim().to_owned()).filter(|s| !s.is_empty());
+    if sigil
+        .as_ref()
+        .is_some_and(|s| s.contains(char::is_whitespace))
+    {
+        ctx.say("The sigil can't contain spaces.").await?;
+        return Ok(());
+    }
+
+    let config = dbi::get_tag_trigger_config(ctx.guild_id()).await?;
+    let config =
+        dbi::set_tag_trigger_config(TagTriggerConfig { sigil, ..config }, ctx.guild_id()).await?;
+
+    match config.sigil {
+        Some(s) => {
+            ctx.say(format!(
+                "Inline tag triggers enabled. Tags can now be used with `{}tagname`.",
+                s
+            ))
+            .await?
+        }
+        None => ctx.say("Inline tag triggers disabled.").await?,
+    };
+
+    Ok(())
+}
+
+/// Allow or deny inline tag triggers in a channel
+///
+/// If at least one channel is allowed, inline triggers only work in the allowed channels. Denied
+/// channels never react to inline triggers. Reset removes the channel from both lists.
+#[poise::command(
+    slash_command,
+    required_permissions = "MANAGE_MESSAGES",
+    category = "Tags",
+    rename = "channel",
+    guild_only
+)]
+pub async fn trigger_channel(
+    ctx: Context<'_>,
+    #[description = "Channel to change"] channel: serenity::Channel,
+    #[description = "Allow, deny or reset the channel"] rule: ChannelRule,
+) -> Result<(), Error> {
+    let channel_id = channel.id();
+    let mut config = dbi::get_tag_trigger_config(ctx.guild_id()).await?;
+    config.allowed_channels.retain(|c| *c != channel_id);
+    config.denied_channels.retain(|c| *c != channel_id);
+    match rule {
+        ChannelRule::Allow => config.allowed_channels.push(channel_id),
+        ChannelRule::Deny => config.denied_channels.push(channel_id),
+        ChannelRule::Reset => {}
+    };
+    dbi::set_tag_trigger_config(config, ctx.guild_id()).await?;
+
+    ctx.say(format!(
+        "Inline tag trigger rule for {} set to {}.",
+        channel,
+        rule.name()
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// Set the cooldown for inline tag triggers
+///
+/// After a tag was triggered inline, the same tag can't be triggered again in the same channel
+/// until the cooldown is over.
+#[poise::command(
+    slash_command,
+    required_permissions = "MANAGE_MESSAGES",
+    category = "Tags",
+    rename = "cooldown",
+    guild_only
+)]
+pub async fn trigger_cooldown(
+    ctx: Context<'_>,
+    #[description = "Cooldown in seconds"]
+    #[max = 3600]
+    seconds: u64,
+) -> Result<(), Error> {
+    let config = dbi::get_tag_trigger_config(ctx.guild_id()).await?;
+    dbi::set_tag_trigger_config(
+        TagTriggerConfig {
+            cooldown: seconds,
+            ..config
+        },
+        ctx.guild_id(),
+    )
+    .await?;
+
+    ctx.say(format!(
+        "Inline tag trigger cooldown set to {} seconds.",
+        seconds
+    ))
+    .await?;
+
+    Ok(())
+}
+
+/// Show the inline tag trigger settings
+///
+/// Displays the current sigil, the allowed and denied channels and the cooldown.
+#[poise::command(
+    slash_command,
+    required_permissions = "MANAGE_MESSAGES",
+    category = "Tags",
+    rename = "show",
+    guild_only
+)]
+pub async fn trigger_show(ctx: Context<'_>) -> Result<(), Error> {
+    let config = dbi::get_tag_trigger_config(ctx.guild_id()).await?;
+    let channel_list = |channels: &Vec<serenity::ChannelId>| match channels.is_empty() {
+        true => String::from("None"),
+        false => channels
+            .iter()
+            .map(|c| format!("<#{}>", c))
+            .collect::<Vec<String>>()
+            .join(" "),
+    };
+
+    ctx.send(
+        poise::CreateReply::default().embed(
+            serenity::CreateEmbed::default()
+                .title("Inline tag triggers")
+                .field(
+                    "Sigil",
+                    match &config.sigil {
+                        Some(s) => format!("`{}`", s),
+                        None => String::from("None (disabled)"),
+                    },
+                    true,
+                )
+                .field("Cooldown", format!("{} seconds", config.cooldown), true)
+                .field(
+                    "Allowed channels",
+                    channel_list(&config.allowed_channels),
+                    false,
+                )
+                .field(
+                    "Denied channels",
+                    channel_list(&config.denied_channels),
+                    false,
+                )
+                .colour(serenity::Colour::BLUE)
+                .footer(serenity::CreateEmbedFooter::new(format!(
+                    "Requested by: {}",
+                    ctx.author().name
+                ))),
+        ),
+    )
+    .await?;
+
+    Ok(())
+}
+
+/// Global tags
+///
+/// Global tags are shared by all servers the bot is in. A tag of a server overrides a global tag
+/// with the same name. Only the owners of the bot can manage global tags.
+#[poise::command(
+    slash_command,
+    owners_only,
+    category = "Tags",
+    subcommands("global_create", "global_edit", "global_remove", "global_restore"),
+    hide_in_help = true
+)]
+pub async fn global(_ctx: Context<'_>) -> Result<(), Error> {
+    Ok(())
+}
+
+/// Create a global tag using a form.
+///
+/// The tag is available on all servers the bot is in.
+#[poise::command(
+    slash_command,
+    owners_only,
+    category = "Tags",
+    rename = "create",
+    hide_in_help = true
+)]
+pub async fn global_create(ctx: ApplicationContext<'_>) -> Result<(), Error> {
+    create_tag_with_modal(ctx, vec![], None).await
+}
+
+/// Edit a global tag using a form.
+///
+/// Opens a form that is pre-filled with the current name and content of the global tag.
+#[poise::command(
+    slash_command,
+    owners_only,
+    category = "Tags",
+    rename = "edit",
+    hide_in_help = true
+)]
+pub async fn global_edit(
+    ctx: ApplicationContext<'_>,
+    #[description = "Global tag to edit"]
+    #[autocomplete = "autocomplete_global_tagname"]
+    tagname: String,
+) -> Result<(), Error> {
+    match dbi::get_tag(&tagname, None).await {
+        Ok(t) => edit_tag_with_modal(ctx, t, None).await?,
+        Err(e) => {
+            ctx.say(format!("{}", e)).await?;
+        }
+    };
+
+    Ok(())
+}
+
+/// Remove a global tag.
+///
+/// The tag is removed from all servers the bot is in. It can be brought back with
+/// `/tags global restore` until the trash is purged.
+#[poise::command(
+    slash_command,
+    owners_only,
+    category = "Tags",
+    rename = "remove",
+    hide_in_help = true
+)]
+pub async fn global_remove(
+    ctx: Context<'_>,
+    #[description = "Global tag to remove"]
+    #[autocomplete = "autocomplete_global_tagname"]
+    tagname: String,
+) -> Result<(), Error> {
+    match dbi::trash_tag(&tagname, ctx.author(), None).await {
+        Ok(t) => {
+            let config = dbi::get_tag_trash_config(None).await?;
+            ctx.say(format!(
+                "Global tag {} moved to the trash. It can be brought back with `/tags global restore` for {} days.",
+                t.tag.name, config.retention_days
+            ))
+            .await?
+        }
+        Err(e) => ctx.say(format!("{}", e)).await?,
+    };
+
+    Ok(())
+}
+
+/// Bring back a removed global tag.
+///
+/// Restores a global tag from the trash with all its settings. This fails if its name or one of
+/// its aliases was taken in the meantime.
+#[poise::command(
+    slash_command,
+    owners_only,
+    category = "Tags",
+    rename = "restore",
+    hide_in_help = true
+)]
+pub async fn global_restore(
+    ctx: Context<'_>,
+    #[description = "Removed global tag to restore"]
+    #[autocomplete = "autocomplete_global_trashed_tag"]
+    tag: String,
+) -> Result<(), Error> {
+    let trashed = dbi::get_trashed_tags(None).await?;
+    // Accept the plain name as well, in which case the most recently removed tag is restored
+    let trash_id = match trashed
+        .iter()
+        .find(|t| t.trash_id == tag)
+        .or_else(|| trashed.iter().find(|t| t.tag.name == tag))
+    {
+        Some(t) => t.trash_id.to_owned(),
+        None => {
+            ctx.say(format!("{}", DBIError::TrashedTagNotFound)).await?;
+            return Ok(());
+        }
+    };
+
+    match dbi::restore_tag(&trash_id, None).await {
+        Ok(t) => {
+            ctx.say(format!("Global tag {} restored sucessfully!", t.name))
+                .await?
+        }
+        Err(e) => ctx.say(format!("{}", e)).await?,
+    };
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_tags(text: &str) -> Vec<ImportedTag> {
+        read_markdown_import(text)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn reads_markdown_export() {
+        let tags = read_tags(
+            "# Tags\n\n\
+             ## passive\n\
+             *Category:* Grammar | *Aliases:* pv, passivevoice | *Creator:* someone\n\n\
+             The passive voice.\n\nSecond paragraph.\n\n\
+             *File:* chart.png\n\n\
+             ### de\n\nDas Passiv.\n\n---\n\n\
+             ## tense\n\
+             *Category:* Uncategorized | *Aliases:* None | *Creator:* someone\n\n\
+             Tenses.\n\n---\n",
+        );
+
+        assert_eq!(tags.len(), 2);
+        assert_eq!(tags[0].name, "passive");
+        assert_eq!(tags[0].category.as_deref(), Some("Grammar"));
+        assert_eq!(tags[0].aliases, vec!["pv", "passivevoice"]);
+        assert_eq!(tags[0].content, "The passive voice.\n\nSecond paragraph.");
+        assert_eq!(
+            tags[0].variants.get("de").map(|v| v.as_str()),
+            Some("Das Passiv.")
+        );
+        assert_eq!(tags[1].name, "tense");
+        assert_eq!(tags[1].category, None);
+        assert!(tags[1].aliases.is_empty());
+        assert_eq!(tags[1].content, "Tenses.");
+        assert!(tags[1].variants.is_empty());
+    }
+
+    #[test]
+    fn keeps_headings_that_are_no_language() {
+        let tags = read_tags(
+            "## steps\n\
+             *Category:* Uncategorized | *Aliases:* None | *Creator:* someone\n\n\
+             ### First step\n\nDo this.\n\n---\n",
+        );
+
+        assert_eq!(tags[0].content, "### First step\n\nDo this.");
+        assert!(tags[0].variants.is_empty());
+    }
+
+    #[test]
+    fn refuses_other_markdown() {
+        assert!(read_markdown_import("# Notes\n\n## Heading\nJust text.").is_err());
+        assert!(read_markdown_import("").is_err());
+    }
+}
@@ -1,5 +1,9 @@
-use poise::serenity_prelude::{Emoji, Message, Role, User};
+use poise::serenity_prelude::{
+    parse_emoji, ChannelId, Emoji, GuildId, Message, ReactionType, Role, RoleId, Timestamp, User,
+    UserId,
+};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::error;
 use std::fmt;
 use surrealdb::sql::Thing;
@@ -9,6 +13,258 @@ pub struct Tag {
     pub name: String,
     pub content: String,
     pub creator: User,
+    pub embed: Option<TagEmbed>,
+    pub category: Option<String>,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    pub created_at: Option<Timestamp>,
+    pub last_editor: Option<User>,
+    pub edited_at: Option<Timestamp>,
+    /// How often the tag was shown
+    #[serde(default)]
+    pub uses: u32,
+    /// Translations of the content, keyed by language code. `content` is shown when no variant
+    /// fits the reader
+    #[serde(default)]
+    pub variants: BTreeMap<String, String>,
+    /// Who may use the tag and where
+    #[serde(default)]
+    pub access: TagAccess,
+    /// Files that are uploaded together with the tag
+    #[serde(default)]
+    pub attachments: Vec<TagAttachment>,
+}
+
+impl Tag {
+    /// The tag with its content replaced by the variant for the first of the languages it has a
+    /// variant for. A language also matches variants of the same base language, e.g. `es-ES` and
+    /// `es`. The default content is kept if none of the languages match
+    pub fn localized(mut self, languages: &[String]) -> Tag {
+        let base = |code: &str| code.split('-').next().unwrap_or_default().to_lowercase();
+        for lang in languages.iter() {
+            let variant = self
+                .variants
+                .iter()
+                .find(|(code, _)| code.eq_ignore_ascii_case(lang))
+                .or_else(|| {
+                    self.variants
+                        .iter()
+                        .find(|(code, _)| base(code) == base(lang))
+                });
+            if let Some((_, content)) = variant {
+                self.content = content.to_owned();
+                break;
+            }
+        }
+        self
+    }
+}
+
+/// A file of a tag. The file itself is kept on disk, named after the hash of its content
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TagAttachment {
+    pub filename: String,
+    /// SHA-256 hash of the content in hex
+    pub hash: String,
+    pub size: u32,
+}
+
+/// Restrictions on who may use a tag and where. Empty lists don't restrict anything
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct TagAccess {
+    /// Members need at least one of these roles to use the tag
+    #[serde(default)]
+    pub roles: Vec<RoleId>,
+    /// The tag can only be used in these channels
+    #[serde(default)]
+    pub channels: Vec<ChannelId>,
+    /// The tag is only ever shown to the member who asked for it
+    #[serde(default)]
+    pub ephemeral: bool,
+}
+
+impl TagAccess {
+    /// Checks if a member with the given roles may use the tag in the channel
+    pub fn allows(&self, roles: &[RoleId], channel: ChannelId) -> bool {
+        (self.roles.is_empty() || self.roles.iter().any(|r| roles.contains(r)))
+            && (self.channels.is_empty() || self.channels.contains(&channel))
+    }
+
+    /// Tags that are only shown ephemerally can't be posted where others see them, e.g. through
+    /// inline triggers or as a reply
+    pub fn allows_public(&self, roles: &[RoleId], channel: ChannelId) -> bool {
+        !self.ephemeral && self.allows(roles, channel)
+    }
+}
+
+/// The language a user prefers to read tags in. Stored globally, so it applies on every server
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct UserLanguage {
+    pub user: User,
+    pub language: String,
+}
+
+/// Settings for posting tags that are mentioned inline in normal messages, e.g. `?passive`
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TagTriggerConfig {
+    /// Inline triggers are disabled as long as no sigil is set
+    pub sigil: Option<String>,
+    /// If not empty, inline triggers only work in these channels
+    pub allowed_channels: Vec<ChannelId>,
+    pub denied_channels: Vec<ChannelId>,
+    /// Seconds before the same tag can be triggered again in the same channel
+    pub cooldown: u64,
+}
+
+impl Default for TagTriggerConfig {
+    fn default() -> Self {
+        TagTriggerConfig {
+            sigil: None,
+            allowed_channels: vec![],
+            denied_channels: vec![],
+            cooldown: crate::constants::DEFAULT_TAG_TRIGGER_COOLDOWN,
+        }
+    }
+}
+
+/// Which mentions in user-provided text (tags, role messages) are allowed to ping
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct MentionPolicy {
+    pub users: bool,
+    pub roles: bool,
+    pub everyone: bool,
+}
+
+impl Default for MentionPolicy {
+    fn default() -> Self {
+        MentionPolicy {
+            users: true,
+            roles: false,
+            everyone: false,
+        }
+    }
+}
+
+/// A removed tag. It can be restored until it is purged after the retention period
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TrashedTag {
+    /// Id of the record in the trash. The same name can be trashed several times
+    pub trash_id: String,
+    pub tag: Tag,
+    pub deleted_by: User,
+    pub deleted_at: Timestamp,
+}
+
+impl TrashedTag {
+    /// A new id for a tag that is moved to the trash. Ulids don't collide even when the same name
+    /// is trashed several times within a second, and they stay short enough for autocomplete
+    pub fn new_id() -> String {
+        ulid::Ulid::new().to_string()
+    }
+}
+
+/// How long removed tags are kept in the trash
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TagTrashConfig {
+    pub retention_days: u64,
+}
+
+impl Default for TagTrashConfig {
+    fn default() -> Self {
+        TagTrashConfig {
+            retention_days: crate::constants::DEFAULT_TAG_TRASH_RETENTION_DAYS,
+        }
+    }
+}
+
+/// An automatic reply to messages that match a pattern
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AutoResponder {
+    pub name: String,
+    pub pattern: String,
+    /// Whether the pattern is a regex. Otherwise it is a phrase that has to appear as whole words,
+    /// ignoring case
+    pub regex: bool,
+    pub response: ResponderReply,
+    /// If not empty, the responder only reacts in these channels
+    pub channels: Vec<ChannelId>,
+    /// Seconds before the responder reacts again in the same channel
+    pub cooldown: u64,
+    pub enabled: bool,
+    pub creator: User,
+}
+
+/// What an auto-responder replies with
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub enum ResponderReply {
+    Text(String),
+    /// The name of a tag, which is looked up when the responder reacts
+    Tag(String),
+}
+
+/// A tag proposed by a member which waits for a moderator to approve it
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TagSuggestion {
+    pub suggestion_id: String,
+    pub name: String,
+    pub content: String,
+    pub category: Option<String>,
+    pub suggester: User,
+    pub suggested_at: Timestamp,
+    pub review_message: Option<Message>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TagReviewConfig {
+    pub channel: ChannelId,
+    pub set_by: User,
+}
+
+/// Channel the bot reports changes it made on its own to, e.g. after roles or emotes were deleted
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct ModLogConfig {
+    pub channel: ChannelId,
+    pub set_by: User,
+}
+
+/// Where a tag comes from. Global tags are shared by all servers and are overridden by server tags
+/// with the same name
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TagSource {
+    Guild,
+    Global,
+}
+
+impl TagSource {
+    /// The id to pass to the db interactions to reach the tag
+    pub fn db_guild_id(&self, guildid: Option<GuildId>) -> Option<GuildId> {
+        match self {
+            TagSource::Guild => guildid,
+            TagSource::Global => None,
+        }
+    }
+}
+
+/// Optional embed that is displayed together with the content of a tag
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct TagEmbed {
+    pub title: Option<String>,
+    pub description: Option<String>,
+    /// Hex colour in the form `#RRGGBB`
+    #[serde(alias = "color")]
+    pub colour: Option<String>,
+    #[serde(default)]
+    pub fields: Vec<TagEmbedField>,
+    pub footer: Option<String>,
+    pub image_url: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TagEmbedField {
+    pub name: String,
+    pub value: String,
+    #[serde(default)]
+    pub inline: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -21,27 +277,197 @@ pub struct MyUser {
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct UserRole {
     pub guild_role: Role,
-    pub emote: Emoji,
+    pub emote: ReactionEmote,
     pub desc: String,
+    /// Name of the role panel the role is offered on
+    #[serde(default)]
+    pub panel: String,
+    /// Name of the role group the role belongs to, if any
+    #[serde(default)]
+    pub role_group: Option<String>,
+    /// Roles a member needs to have to pick the role
+    #[serde(default)]
+    pub required: Vec<RoleId>,
+    /// Roles that keep a member from picking the role
+    #[serde(default)]
+    pub forbidden: Vec<RoleId>,
+    /// Seconds after which a picked role is taken away again
+    #[serde(default)]
+    pub duration: Option<u64>,
+}
+
+/// Roles members get when they join the server
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct JoinRoleConfig {
+    /// Roles every new member gets
+    pub roles: Vec<RoleId>,
+    /// Whether members who rejoin get back the self-assignable roles they had when they left
+    pub sticky: bool,
+}
+
+impl Default for JoinRoleConfig {
+    fn default() -> Self {
+        JoinRoleConfig {
+            roles: vec![],
+            sticky: true,
+        }
+    }
+}
+
+/// The self-assignable roles a member had when they left the server
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RoleSnapshot {
+    pub user: User,
+    pub roles: Vec<RoleId>,
+    pub left_at: Timestamp,
+    /// The expiries of the timed roles among `roles`, so roles that ran out while the member was
+    /// away don't come back
+    #[serde(default)]
+    pub expiries: Vec<RoleExpiry>,
+}
+
+/// A role that is taken away from a member again at a set time
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RoleExpiry {
+    pub expiry_id: String,
+    pub user: User,
+    pub role: Role,
+    pub expires_at: Timestamp,
+    pub granted_by: Option<User>,
+}
+
+impl RoleExpiry {
+    /// A member has at most one expiry per role, so the id is made up of both
+    pub fn id_for(user: UserId, role: RoleId) -> String {
+        format!("{}-{}", user.get(), role.get())
+    }
 }
 
+/// A group of user-assignable roles with limits on how many of them a member can have. In an
+/// exclusive group, picking a role replaces the other role of the group
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct RoleGroup {
+    pub name: String,
+    pub exclusive: bool,
+    pub min: Option<u32>,
+    pub max: Option<u32>,
+}
+
+impl RoleGroup {
+    /// The most roles of the group a member can have
+    pub fn max_roles(&self) -> Option<u32> {
+        match self.exclusive {
+            true => Some(1),
+            false => self.max,
+        }
+    }
+}
+
+/// A role panel. Each panel has its own text and roles and is posted as its own message
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct RoleMessage {
+    #[serde(default)]
+    pub name: String,
     pub messagetext: String,
     pub guild_message: Option<Message>,
     pub active: bool,
     pub message_by: User,
     pub posted_by: Option<User>,
+    /// How members pick their roles on the posted message
+    #[serde(default)]
+    pub style: RoleMessageStyle,
+}
+
+#[derive(
+    Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, poise::ChoiceParameter,
+)]
+pub enum RoleMessageStyle {
+    #[default]
+    #[name = "Reactions"]
+    Reactions,
+    #[name = "Buttons"]
+    Buttons,
+    #[name = "Select menu"]
+    SelectMenu,
+}
+
+/// An emoji members react with. Either a custom emoji of the server or a standard Unicode emoji
+/// like 🇩🇪. Custom emoji are stored like the guild `Emoji` they used to be, so older records
+/// still load
+#[derive(Debug, Serialize, Deserialize, Clone)]
+#[serde(untagged)]
+pub enum ReactionEmote {
+    Custom(Box<Emoji>),
+    Unicode(String),
+}
+
+impl ReactionEmote {
+    /// Parses an emoji given in a command. Custom emoji have to be one of the `guild_emojis` and
+    /// can be given by their name too
+    pub fn parse(input: &str, guild_emojis: &[Emoji]) -> Option<ReactionEmote> {
+        let input = input.trim();
+        if let Some(identifier) = parse_emoji(input) {
+            return guild_emojis
+                .iter()
+                .find(|e| e.id == identifier.id)
+                .map(|e| ReactionEmote::Custom(Box::new(e.to_owned())));
+        }
+        if let Some(emoji) = guild_emojis
+            .iter()
+            .find(|e| e.name == input.trim_matches(':'))
+        {
+            return Some(ReactionEmote::Custom(Box::new(emoji.to_owned())));
+        }
+
+        // Unicode emoji are looked up in the full emoji list, which also knows the forms without
+        // variation selector. They are stored in their fully qualified form
+        emojis::get(input).map(|e| ReactionEmote::Unicode(e.as_str().to_string()))
+    }
+
+    /// Whether a reaction was made with this emoji. Unicode emoji are compared without the
+    /// variation selector, which clients don't send consistently
+    pub fn matches(&self, reaction: &ReactionType) -> bool {
+        let plain = |s: &str| s.replace('\u{FE0F}', "");
+        match (self, reaction) {
+            (ReactionEmote::Custom(emoji), ReactionType::Custom { id, .. }) => emoji.id == *id,
+            (ReactionEmote::Unicode(emoji), ReactionType::Unicode(unicode)) => {
+                plain(emoji) == plain(unicode)
+            }
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for ReactionEmote {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReactionEmote::Custom(emoji) => write!(f, "{}", emoji),
+            ReactionEmote::Unicode(emoji) => write!(f, "{}", emoji),
+        }
+    }
+}
+
+impl From<ReactionEmote> for ReactionType {
+    fn from(emote: ReactionEmote) -> ReactionType {
+        match emote {
+            ReactionEmote::Custom(emoji) => (*emoji).into(),
+            ReactionEmote::Unicode(emoji) => ReactionType::Unicode(emoji),
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct PointsData {
-    pub guild_emote: Emoji,
+    pub guild_emote: ReactionEmote,
     pub set_by: User,
     pub active: bool,
     pub total: u32,
+    /// Set when the emote was deleted from the server, until a new one is set
+    #[serde(default)]
+    pub emote_deleted: bool,
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Deserialize)]
 pub struct Record {
     #[allow(dead_code)]
@@ -52,6 +478,7 @@ pub struct Record {
 pub struct Data {} // User data, which is stored and accessible in all command invocations
 pub type Error = Box<dyn std::error::Error + Send + Sync>;
 pub type Context<'a> = poise::Context<'a, Data, Error>;
+pub type ApplicationContext<'a> = poise::ApplicationContext<'a, Data, Error>;
 
 #[derive(Debug)]
 pub enum DBIError {
@@ -59,8 +486,15 @@ pub enum DBIError {
     TagAlreadyExists,
     TagNotFound,
     UserNotFound,
+    SuggestionNotFound,
+    TrashedTagNotFound,
+    ResponderAlreadyExists,
+    ResponderNotFound,
     RoleAlreadyExists,
     RoleNotFound,
+    RolePanelNotFound,
+    RoleGroupNotFound,
+    RoleExpiryNotFound,
     PointDataNotFound,
 }
 
@@ -71,8 +505,15 @@ impl fmt::Display for DBIError {
             DBIError::TagAlreadyExists => write!(f, "Tag name already exists"),
             DBIError::TagNotFound => write!(f, "Tag name not found"),
             DBIError::UserNotFound => write!(f, "User not found"),
+            DBIError::SuggestionNotFound => write!(f, "Tag suggestion not found"),
+            DBIError::TrashedTagNotFound => write!(f, "Tag not found in the trash"),
+            DBIError::ResponderAlreadyExists => write!(f, "Auto-responder name already exists"),
+            DBIError::ResponderNotFound => write!(f, "Auto-responder not found"),
             DBIError::RoleAlreadyExists => write!(f, "Role already exists"),
             DBIError::RoleNotFound => write!(f, "Role not found"),
+            DBIError::RolePanelNotFound => write!(f, "Role panel not found"),
+            DBIError::RoleGroupNotFound => write!(f, "Role group not found"),
+            DBIError::RoleExpiryNotFound => write!(f, "Role expiry not found"),
             DBIError::PointDataNotFound => write!(f, "Points data doesn't exist"),
         }
     }
@@ -84,8 +525,15 @@ impl error::Error for DBIError {
             DBIError::TagAlreadyExists => None,
             DBIError::TagNotFound => None,
             DBIError::UserNotFound => None,
+            DBIError::SuggestionNotFound => None,
+            DBIError::TrashedTagNotFound => None,
+            DBIError::ResponderAlreadyExists => None,
+            DBIError::ResponderNotFound => None,
             DBIError::RoleAlreadyExists => None,
             DBIError::RoleNotFound => None,
+            DBIError::RolePanelNotFound => None,
+            DBIError::RoleGroupNotFound => None,
+            DBIError::RoleExpiryNotFound => None,
             DBIError::PointDataNotFound => None,
             // The cause is the underlying implementation error type. Is implicitly
             // cast to the trait object `&error::Error`. This works because the
@@ -101,6 +549,48 @@ impl From<surrealdb::Error> for DBIError {
     }
 }
 
+#[derive(Debug)]
+pub enum TagError {
+    InvalidEmbed(String),
+    InvalidName,
+    EmptyContent,
+    ContentTooLong,
+    InvalidLanguage,
+    TooManyAttachments,
+    AttachmentsTooLarge,
+}
+
+impl fmt::Display for TagError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TagError::InvalidEmbed(reason) => write!(f, "Invalid embed definition: {}", reason),
+            TagError::InvalidName => write!(f, "Tag names can't be empty or contain spaces"),
+            TagError::EmptyContent => write!(f, "Tag content can't be empty"),
+            TagError::InvalidLanguage => {
+                write!(f, "Languages are given as codes like `en`, `es` or `pt-BR`")
+            }
+            TagError::ContentTooLong => write!(
+                f,
+                "Tag content can be at most {} characters long",
+                crate::constants::MESSAGE_CHAR_LIMIT
+            ),
+            TagError::TooManyAttachments => write!(
+                f,
+                "Tags can have at most {} files",
+                crate::constants::TAG_ATTACHMENT_COUNT_LIMIT
+            ),
+            TagError::AttachmentsTooLarge => write!(
+                f,
+                "The files of a tag can be at most {} MB together",
+                crate::constants::TAG_ATTACHMENT_SIZE_LIMIT / 1024 / 1024
+            ),
+        }
+    }
+}
+
+impl error::Error for TagError {}
+
+#[allow(dead_code)]
 #[derive(Debug)]
 pub struct LogError;
 
@@ -109,3 +599,43 @@ impl fmt::Display for LogError {
         write!(f, "failed to set up loggger")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unicode(input: &str) -> Option<String> {
+        match ReactionEmote::parse(input, &[]) {
+            Some(ReactionEmote::Unicode(emoji)) => Some(emoji),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn parses_unicode_emoji() {
+        assert_eq!(unicode("👍").as_deref(), Some("👍"));
+        assert_eq!(unicode(" 👍 ").as_deref(), Some("👍"));
+    }
+
+    #[test]
+    fn stores_fully_qualified_emoji() {
+        assert_eq!(unicode("❤").as_deref(), Some("❤\u{FE0F}"));
+        assert_eq!(unicode("❤\u{FE0F}").as_deref(), Some("❤\u{FE0F}"));
+    }
+
+    #[test]
+    fn refuses_text() {
+        assert_eq!(unicode("日本"), None);
+        assert_eq!(unicode("é"), None);
+        assert_eq!(unicode("a"), None);
+        assert_eq!(unicode(""), None);
+    }
+
+    #[test]
+    fn matches_reactions_with_and_without_variation_selector() {
+        let heart = ReactionEmote::Unicode("❤\u{FE0F}".to_string());
+        assert!(heart.matches(&ReactionType::Unicode("❤".to_string())));
+        assert!(heart.matches(&ReactionType::Unicode("❤\u{FE0F}".to_string())));
+        assert!(!heart.matches(&ReactionType::Unicode("👍".to_string())));
+    }
+}